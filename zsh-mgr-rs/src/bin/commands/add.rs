@@ -1,63 +1,196 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use colored::Colorize;
-use std::process::Command;
-use zsh_mgr_rs::config::{Config, PluginInfo, PluginList};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use zsh_mgr_rs::clone::CloneProgress;
+use zsh_mgr_rs::config::{Config, PluginInfo, PluginList, PrivateProtocol};
+use zsh_mgr_rs::fetch_options::FetchOverrides;
+use zsh_mgr_rs::installer::{self, InstallRequest, PluginInstaller};
+use zsh_mgr_rs::release::ReleaseSpec;
+use zsh_mgr_rs::snippet;
 
-pub fn run(plugin: String, flags: Option<String>, private: bool) -> Result<()> {
+use super::update;
+
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    plugin: String,
+    flags: Option<String>,
+    private: bool,
+    https: bool,
+    identities: Vec<String>,
+    fetch: FetchOverrides,
+    sparse: Vec<String>,
+    path: Option<String>,
+    build: Vec<String>,
+) -> Result<()> {
     let config = Config::load()?;
     let mut plugin_list = PluginList::load(&config)?;
+    let name = match path {
+        Some(ref path) => PluginInfo::subdir_name(&plugin, path),
+        None => plugin.clone(),
+    };
     
     // Check if plugin already exists
-    if plugin_list.get(&plugin).is_some() {
-        eprintln!("{} Plugin '{}' is already installed", "⚠️".yellow(), plugin);
+    if plugin_list.get(&name).is_some() {
+        eprintln!("{} Plugin '{}' is already installed", "⚠️".yellow(), name);
         return Ok(());
     }
     
-    // Build URL
-    let url = if private {
-        format!("git@github.com:{}.git", plugin)
-    } else {
-        format!("https://github.com/{}.git", plugin)
-    };
+    let identity_files: Vec<_> = identities
+        .iter()
+        .map(|path| PathBuf::from(shellexpand::tilde(path).to_string()))
+        .collect();
+    if let Some(missing) = identity_files.iter().find(|path| !path.exists()) {
+        anyhow::bail!("SSH key not found: {}", missing.display());
+    }
+    
+    let https = https || config.private_protocol == PrivateProtocol::Https;
+    let request = InstallRequest::new(plugin.clone(), flags, private)
+        .with_https(https)
+        .with_identity_files(identity_files)
+        .with_fetch(fetch)
+        .with_sparse(sparse)
+        .with_path(path)
+        .with_build(build);
+    let installer = PluginInstaller::new(&config)?;
     
     // Plugin directory
-    let plugin_dir = config.plugin_dir.join(&plugin);
+    let plugin_dir = installer.plugin_path(&name);
     
     // Clone repository
-    println!("{} Cloning {}...", "📦".cyan(), plugin);
-
-    let mut cmd = Command::new("git");
-    cmd.arg("clone");
-
-    if let Some(ref f) = flags {
-        for flag in f.split_whitespace() {
-            cmd.arg(flag);
-        }
+    if plugin_list.plugins_of(&plugin).next().is_some() {
+        println!("{} Installing {} from the clone of {}...", "📦".cyan(), name, plugin);
+    } else {
+        println!("{} Cloning {}...", "📦".cyan(), plugin);
     }
 
-    cmd.arg(&url).arg(&plugin_dir);
-
-    let status = cmd.status().context("Failed to execute git clone")?;
-
-    if !status.success() {
-        anyhow::bail!("Git clone failed for {}", plugin);
+    let result = installer.install(&request, &print_progress);
+    eprint!("\r\x1B[2K");
+    let plugin_info = result?;
+    if let Some(ref build) = plugin_info.build {
+        println!("{} Building {}...", "🔨".cyan(), name);
+        update::print_build(build);
     }
     
     // Add to plugin list
-    let plugin_info = PluginInfo::new(plugin.clone(), url, private, flags);
-    plugin_list.add(plugin_info);
-    plugin_list.save()?;
+    installer::register(&config, &mut plugin_list, vec![plugin_info])?;
     
-    // Create timestamp file
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)?
-        .as_secs();
+    println!("{} Plugin '{}' installed successfully", "✓".green(), name);
+    println!("{} Add this to your .zshrc:", "💡".bright_cyan());
+    println!("   source {}", plugin_dir.join("*.plugin.zsh").display());
     
-    std::fs::write(config.timestamp_file(&plugin), now.to_string())?;
+    Ok(())
+}
+
+/// Register directory `path` as a local plugin named after it
+pub fn run_local(path: String) -> Result<()> {
+    let config = Config::load()?;
+    let mut plugin_list = PluginList::load(&config)?;
+    let source = PathBuf::from(shellexpand::tilde(&path).to_string());
+    let Some(name) = source.file_name().map(|name| name.to_string_lossy().into_owned()) else {
+        anyhow::bail!("Cannot name a plugin after {}", source.display());
+    };
+    
+    if plugin_list.get(&name).is_some() {
+        eprintln!("{} Plugin '{}' is already installed", "⚠️".yellow(), name);
+        return Ok(());
+    }
     
-    println!("{} Plugin '{}' installed successfully", "✓".green(), plugin);
+    let request = InstallRequest::new(name.clone(), None, false).with_local(Some(source));
+    let installer = PluginInstaller::new(&config)?;
+    let plugin_info = installer.install(&request, &print_progress)?;
+    let target = plugin_info.info.url.clone();
+    installer::register(&config, &mut plugin_list, vec![plugin_info])?;
+    
+    println!("{} Linked local plugin '{}' to {}", "✓".green(), name, target);
     println!("{} Add this to your .zshrc:", "💡".bright_cyan());
-    println!("   source {}", plugin_dir.join("*.plugin.zsh").display());
+    println!("   source {}", installer.plugin_path(&name).join("*.plugin.zsh").display());
+    
+    Ok(())
+}
+
+/// Download the file at `source` (a URL or a path) as a snippet plugin named after it
+pub fn run_snippet(source: String) -> Result<()> {
+    let config = Config::load()?;
+    let mut plugin_list = PluginList::load(&config)?;
+    let url = snippet::source_url(&source)?;
+    let Some(name) = snippet::snippet_name(&url) else {
+        anyhow::bail!("Cannot name a plugin after {}", url);
+    };
+    
+    if plugin_list.get(&name).is_some() {
+        eprintln!("{} Plugin '{}' is already installed", "⚠️".yellow(), name);
+        return Ok(());
+    }
+    
+    println!("{} Downloading {}...", "📄".cyan(), url);
+    let request = InstallRequest::new(name.clone(), None, false)
+        .with_url(url)
+        .with_snippet(true);
+    let installer = PluginInstaller::new(&config)?;
+    let plugin_info = installer.install(&request, &print_progress)?;
+    installer::register(&config, &mut plugin_list, vec![plugin_info])?;
+    
+    println!("{} Snippet '{}' installed successfully", "✓".green(), name);
+    println!("{} Add this to your .zshrc:", "💡".bright_cyan());
+    println!("   source {}", snippet::snippet_file(&installer.plugin_path(&name)).display());
     
     Ok(())
 }
+
+/// Install `bins` of the latest release of repository `plugin` ("user/repo")
+pub fn run_release(plugin: String, asset: Option<String>, bins: Vec<String>) -> Result<()> {
+    let config = Config::load()?;
+    let mut plugin_list = PluginList::load(&config)?;
+    
+    if plugin_list.get(&plugin).is_some() {
+        eprintln!("{} Plugin '{}' is already installed", "⚠️".yellow(), plugin);
+        return Ok(());
+    }
+    
+    let bins = if bins.is_empty() {
+        vec![plugin.rsplit('/').next().unwrap_or(&plugin).to_string()]
+    } else {
+        bins
+    };
+    
+    println!("{} Downloading the latest release of {}...", "🧰".cyan(), plugin);
+    let request = InstallRequest::new(plugin.clone(), None, false)
+        .with_release(Some(ReleaseSpec { asset, bins }));
+    let installer = PluginInstaller::new(&config)?;
+    let plugin_info = installer.install(&request, &print_progress)?;
+    let version = plugin_info.info.version.clone().unwrap_or_default();
+    let bins = plugin_info.info.bins.join(", ");
+    installer::register(&config, &mut plugin_list, vec![plugin_info])?;
+    
+    println!("{} Release {} of '{}' installed successfully", "✓".green(), version, plugin);
+    println!("{} Linked {} into {}", "💡".bright_cyan(), bins, config.bin_dir().display());
+    
+    Ok(())
+}
+
+fn print_progress(progress: CloneProgress) {
+    match progress {
+        CloneProgress::Receiving { received_objects, total_objects, received_bytes } => eprint!(
+            "\r\x1B[2K   Receiving objects: {:3}% ({}/{}), {:.1} KiB",
+            received_objects * 100 / total_objects,
+            received_objects,
+            total_objects,
+            received_bytes as f64 / 1024.0
+        ),
+        CloneProgress::Resolving { indexed_deltas, total_deltas } => eprint!(
+            "\r\x1B[2K   Resolving deltas: {:3}% ({}/{})",
+            indexed_deltas * 100 / total_deltas,
+            indexed_deltas,
+            total_deltas
+        ),
+        CloneProgress::CheckingOut { completed, total } if total > 0 => eprint!(
+            "\r\x1B[2K   Checking out files: {:3}% ({}/{})",
+            completed * 100 / total,
+            completed,
+            total
+        ),
+        CloneProgress::CheckingOut { .. } => {}
+    }
+    io::stderr().flush().ok();
+}
@@ -1,10 +1,12 @@
 use anyhow::Result;
 use colored::Colorize;
+use std::collections::HashSet;
 use std::fs;
 use std::path::PathBuf;
-use zsh_mgr_rs::config::real_home_dir;
+use zsh_mgr_rs::config::{real_home_dir, Config, PluginList};
+use zsh_mgr_rs::installer::{self, InstallRequest, PluginInstaller};
 
-pub fn run(plugins_file: Option<String>) -> Result<()> {
+pub fn run(plugins_file: Option<String>, jobs: Option<usize>) -> Result<()> {
     // Determine plugins file path
     let file_path = if let Some(path) = plugins_file {
         PathBuf::from(shellexpand::tilde(&path).to_string())
@@ -29,59 +31,59 @@ pub fn run(plugins_file: Option<String>) -> Result<()> {
     println!("{} Reading plugins from {}", "📋".cyan(), file_path.display());
     
     let content = fs::read_to_string(&file_path)?;
+    let requests = parse_plugins_file(&content);
+    
+    let config = Config::load()?;
+    let mut plugin_list = PluginList::load(&config)?;
+    
+    // Plugins already registered are skipped up front
+    let (already_installed, to_install): (Vec<_>, Vec<_>) = requests
+        .into_iter()
+        .partition(|r| plugin_list.get(&r.plugin_name()).is_some());
+    
+    for request in &already_installed {
+        println!("{} {} already installed", "⚠️".yellow(), request.plugin_name());
+    }
+    
+    let mut failures = Vec::new();
     let mut installed = 0;
-    let mut skipped = 0;
-    let mut failed = 0;
     
-    for line in content.lines() {
-        let line = line.trim();
-        
-        // Skip empty lines and comments
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
-        
-        // Parse plugin and flags
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.is_empty() {
-            continue;
-        }
+    if !to_install.is_empty() {
+        let jobs = jobs.unwrap_or(installer::DEFAULT_JOBS).max(1);
+        println!(
+            "\n{} Installing {} plugins ({} parallel jobs)...",
+            "📦".cyan(),
+            to_install.len(),
+            jobs
+        );
         
-        let plugin = parts[0];
-        let flags = if parts.len() > 1 {
-            Some(parts[1..].join(" "))
-        } else {
-            None
-        };
-        
-        println!("\n{} {}", "Installing".cyan(), plugin);
+        let installer = PluginInstaller::new(&config)?;
+        let results = installer::install_parallel(&installer, &to_install, jobs)?;
         
-        // Use the add command
-        match super::add::run(plugin.to_string(), flags, false) {
-            Ok(_) => {
-                installed += 1;
-            }
-            Err(e) => {
-                // Check if it's because plugin already exists
-                let err_msg = e.to_string();
-                if err_msg.contains("already installed") {
-                    println!("{} Already installed", "⚠️".yellow());
-                    skipped += 1;
-                } else {
-                    eprintln!("{} Failed: {}", "✗".red(), e);
-                    failed += 1;
-                }
+        let mut new_plugins = Vec::new();
+        for (request, result) in results {
+            match result {
+                Ok(plugin_info) => new_plugins.push(plugin_info),
+                Err(e) => failures.push((request.plugin_name(), format!("{:#}", e))),
             }
         }
+        
+        installed = new_plugins.len();
+        installer::register(&config, &mut plugin_list, new_plugins)?;
     }
     
+    let skipped = already_installed.len();
+    
     println!("\n{}", "═".repeat(50));
     println!("{}", "Bootstrap Summary".bright_cyan().bold());
     println!("{}", "═".repeat(50));
     println!("{} Installed: {}", "✓".green(), installed);
     println!("{} Skipped: {}", "⚠".yellow(), skipped);
-    if failed > 0 {
-        println!("{} Failed: {}", "✗".red(), failed);
+    if !failures.is_empty() {
+        println!("{} Failed: {}", "✗".red(), failures.len());
+        for (name, error) in &failures {
+            println!("   {} {}: {}", "•".red(), name, error);
+        }
     }
     println!("{}", "═".repeat(50));
     
@@ -89,5 +91,45 @@ pub fn run(plugins_file: Option<String>) -> Result<()> {
         println!("\n{} Run 'zsh-mgr init' to update your .zshrc", "💡".bright_cyan());
     }
     
+    if !failures.is_empty() {
+        anyhow::bail!("{} plugins failed to install", failures.len());
+    }
+    
     Ok(())
 }
+
+/// Parse default-plugins.txt: one "user/repo [path:dir] [clone flags]" per line, `#` starts a comment
+fn parse_plugins_file(content: &str) -> Vec<InstallRequest> {
+    let mut seen = HashSet::new();
+    let mut requests = Vec::new();
+    
+    for line in content.lines() {
+        let line = line.trim();
+        
+        // Skip empty lines and comments
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        
+        // Parse plugin, subdirectory and flags
+        let (plugin, rest) = match line.split_once(char::is_whitespace) {
+            Some((plugin, rest)) => (plugin, rest.trim()),
+            None => (line, ""),
+        };
+        let (path, flags) = match rest.strip_prefix("path:") {
+            Some(rest) => match rest.split_once(char::is_whitespace) {
+                Some((path, flags)) => (Some(path.to_string()), flags.trim()),
+                None => (Some(rest.to_string()), ""),
+            },
+            None => (None, rest),
+        };
+        let flags = (!flags.is_empty()).then(|| flags.to_string());
+        
+        let request = InstallRequest::new(plugin.to_string(), flags, false).with_path(path);
+        if seen.insert(request.plugin_name()) {
+            requests.push(request);
+        }
+    }
+    
+    requests
+}
@@ -2,7 +2,7 @@ use anyhow::Result;
 use chrono::{DateTime, Local, TimeZone};
 use comfy_table::{presets::UTF8_FULL, Cell, Color, ContentArrangement, Table};
 use serde::{Deserialize, Serialize};
-use zsh_mgr_rs::config::{Config, PluginList};
+use zsh_mgr_rs::config::{Config, PluginKind, PluginList};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct UpdateInfo {
@@ -18,6 +18,8 @@ enum UpdateStatus {
     Current,
     UpdateSoon,
     UpdateNeeded,
+    /// Local plugins are never updated
+    Local,
 }
 
 pub fn run(plugins: bool, manager: bool, json: bool) -> Result<()> {
@@ -29,7 +31,17 @@ pub fn run(plugins: bool, manager: bool, json: bool) -> Result<()> {
     // Check plugins
     if !manager {
         for plugin in plugin_list.list() {
-            if let Ok(timestamp_str) = std::fs::read_to_string(config.timestamp_file(&plugin.name)) {
+            if plugin.kind == PluginKind::Local {
+                updates.push(UpdateInfo {
+                    name: plugin.name.clone(),
+                    last_update: plugin.installed_at,
+                    next_update: 0,
+                    time_until_update: 0,
+                    status: UpdateStatus::Local,
+                });
+                continue;
+            }
+            if let Ok(timestamp_str) = std::fs::read_to_string(config.timestamp_file(plugin.repo_name())) {
                 if let Ok(last_update) = timestamp_str.trim().parse::<u64>() {
                     let next_update = last_update + config.time_threshold;
                     let now = std::time::SystemTime::now()
@@ -110,7 +122,10 @@ fn print_table(updates: &[UpdateInfo]) {
     
     for update in updates {
         let last = format_timestamp(update.last_update);
-        let next = format_timestamp(update.next_update);
+        let next = match update.status {
+            UpdateStatus::Local => "-".to_string(),
+            _ => format_timestamp(update.next_update),
+        };
         
         let (status_text, color) = match update.status {
             UpdateStatus::Current => ("✓ Up to date".to_string(), Color::Green),
@@ -122,6 +137,7 @@ fn print_table(updates: &[UpdateInfo]) {
                 )
             }
             UpdateStatus::UpdateNeeded => ("⚠ Update needed".to_string(), Color::Red),
+            UpdateStatus::Local => ("📂 Local".to_string(), Color::Cyan),
         };
         
         table.add_row(vec![
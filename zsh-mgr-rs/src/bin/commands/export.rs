@@ -0,0 +1,31 @@
+use anyhow::Result;
+use colored::Colorize;
+use std::fs;
+use std::path::PathBuf;
+use zsh_mgr_rs::config::{Config, PluginList};
+use zsh_mgr_rs::manifest::Manifest;
+
+pub fn run(output: Option<String>) -> Result<()> {
+    let config = Config::load()?;
+    let plugin_list = PluginList::load(&config)?;
+
+    let manifest = Manifest::from_plugins(plugin_list.list());
+    let json = manifest.to_json()?;
+
+    // Without an output file the manifest goes to stdout, so it can be piped or redirected
+    match output {
+        Some(path) => {
+            let path = PathBuf::from(shellexpand::tilde(&path).to_string());
+            fs::write(&path, json + "\n")?;
+            eprintln!(
+                "{} Exported {} plugins to {}",
+                "✓".green(),
+                manifest.plugins.len(),
+                path.display()
+            );
+        }
+        None => println!("{}", json),
+    }
+
+    Ok(())
+}
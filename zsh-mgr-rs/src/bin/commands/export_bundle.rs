@@ -0,0 +1,115 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use git2::Repository;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zsh_mgr_rs::bundle::{self, ARCHIVE_PLUGIN_LIST};
+use zsh_mgr_rs::config::{Config, PluginKind, PluginList};
+use zsh_mgr_rs::git_cli;
+use zsh_mgr_rs::snippet;
+
+pub fn run(output: String) -> Result<()> {
+    let config = Config::load()?;
+    let plugin_list = PluginList::load(&config)?;
+    let output = PathBuf::from(shellexpand::tilde(&output).to_string());
+
+    if plugin_list.list().is_empty() {
+        eprintln!("{} No plugins to export", "ℹ️".blue());
+        return Ok(());
+    }
+
+    println!(
+        "{} Exporting {} plugins to {}",
+        "📦".cyan(),
+        plugin_list.list().len(),
+        output.display()
+    );
+
+    // Write next to the destination and rename at the end, so a failed export
+    // never leaves a truncated archive behind
+    let partial = output.with_extension("partial");
+    let result = write_archive(&config, &plugin_list, &partial)
+        .and_then(|_| fs::rename(&partial, &output).map_err(anyhow::Error::from));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result?;
+
+    println!("{} Exported {} plugins", "✓".green(), plugin_list.list().len());
+    println!(
+        "{} Install them offline with: zsh-mgr import-bundle {}",
+        "💡".bright_cyan(),
+        output.display()
+    );
+
+    Ok(())
+}
+
+fn write_archive(config: &Config, plugin_list: &PluginList, path: &Path) -> Result<()> {
+    let mut archive = tar::Builder::new(fs::File::create(path)?);
+    // Releases may ship symlinks, which are archived as links
+    archive.follow_symlinks(false);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    // Plugins sharing a clone are bundled once, under the name of their repository.
+    // Local plugins are only listed, importing them links the same directory again.
+    // Releases are archived as extracted, so importing them needs no network either.
+    for plugin in plugin_list.repos() {
+        match plugin.kind {
+            PluginKind::Git => {}
+            PluginKind::Local => continue,
+            PluginKind::Release => {
+                archive
+                    .append_dir_all(bundle::archive_release_path(&plugin.name), config.plugin_dir.join(&plugin.name))
+                    .with_context(|| format!("Failed to archive release {}", plugin.name))?;
+                println!("   {} {}", "✓".green(), plugin.name);
+                continue;
+            }
+            PluginKind::Snippet => {
+                let data = fs::read(snippet::snippet_file(&config.plugin_dir.join(&plugin.name)))
+                    .with_context(|| format!("Failed to read snippet {}", plugin.name))?;
+                append(&mut archive, &bundle::archive_snippet_path(&plugin.name), &data, now)?;
+                println!("   {} {}", "✓".green(), plugin.name);
+                continue;
+            }
+        }
+
+        let repo = Repository::open(config.plugin_dir.join(&plugin.name))
+            .with_context(|| format!("Failed to open {}", plugin.name))?;
+        if git_cli::is_partial(&repo) {
+            bail!("{} is a partial clone and does not have every object to bundle", plugin.name);
+        }
+
+        // Streamed into the archive, so large plugins are never held in memory
+        let mut header = entry_header(0, now);
+        let mut entry = archive.append_writer(&mut header, bundle::archive_bundle_path(&plugin.name))?;
+        bundle::write_bundle(&repo, &mut entry)
+            .with_context(|| format!("Failed to bundle {}", plugin.name))?;
+        entry.finish()?;
+
+        println!("   {} {}", "✓".green(), plugin.name);
+    }
+
+    let metadata = serde_json::to_vec_pretty(plugin_list.list())?;
+    append(&mut archive, Path::new(ARCHIVE_PLUGIN_LIST), &metadata, now)?;
+
+    archive.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+fn append(archive: &mut tar::Builder<fs::File>, path: &Path, data: &[u8], mtime: u64) -> Result<()> {
+    let mut header = entry_header(data.len() as u64, mtime);
+    archive.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+fn entry_header(size: u64, mtime: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    header
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use colored::Colorize;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use zsh_mgr_rs::config::{Config, PluginList};
+use zsh_mgr_rs::installer::{self, PluginInstaller};
+use zsh_mgr_rs::manifest::Manifest;
+
+pub fn run(file: String, jobs: Option<usize>) -> Result<()> {
+    let contents = if file == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        contents
+    } else {
+        let path = PathBuf::from(shellexpand::tilde(&file).to_string());
+        if !path.exists() {
+            anyhow::bail!("Manifest not found: {}", path.display());
+        }
+        println!("{} Reading plugins from {}", "📋".cyan(), path.display());
+        fs::read_to_string(&path)?
+    };
+    let manifest = Manifest::from_json(&contents)?;
+
+    let config = Config::load()?;
+    let mut plugin_list = PluginList::load(&config)?;
+
+    // Plugins that are already installed are kept as they are
+    let (already_installed, to_install): (Vec<_>, Vec<_>) = manifest
+        .plugins
+        .iter()
+        .partition(|spec| plugin_list.get(&spec.name).is_some());
+
+    for spec in &already_installed {
+        match plugin_list.get(&spec.name) {
+            Some(existing) if existing.url != spec.url => println!(
+                "{} {} already installed from {}, keeping it",
+                "⚠️".yellow(),
+                spec.name,
+                existing.url
+            ),
+            _ => println!("{} {} already installed", "⚠️".yellow(), spec.name),
+        }
+    }
+
+    let mut failures = Vec::new();
+    let mut build_errors = Vec::new();
+    let mut installed = 0;
+
+    if !to_install.is_empty() {
+        let jobs = jobs.unwrap_or(installer::DEFAULT_JOBS).max(1);
+        println!(
+            "\n{} Installing {} plugins ({} parallel jobs)...",
+            "📦".cyan(),
+            to_install.len(),
+            jobs
+        );
+
+        let requests: Vec<_> = to_install.iter().map(|spec| spec.install_request()).collect();
+        let installer = PluginInstaller::new(&config)?;
+        let results = installer::install_parallel(&installer, &requests, jobs)?;
+
+        let mut new_plugins = Vec::new();
+        for (request, result) in results {
+            match result {
+                Ok(plugin) => new_plugins.push(plugin),
+                Err(e) => failures.push((request.plugin_name(), format!("{:#}", e))),
+            }
+        }
+
+        installed = new_plugins.len();
+        build_errors = installer::build_errors(&new_plugins);
+        installer::register(&config, &mut plugin_list, new_plugins)?;
+    }
+
+    println!("\n{}", "═".repeat(50));
+    println!("{}", "Import Summary".bright_cyan().bold());
+    println!("{}", "═".repeat(50));
+    println!("{} Installed: {}", "✓".green(), installed);
+    println!("{} Skipped: {}", "⚠".yellow(), already_installed.len());
+    if !failures.is_empty() {
+        println!("{} Failed: {}", "✗".red(), failures.len());
+        for (name, error) in &failures {
+            println!("   {} {}: {}", "•".red(), name, error);
+        }
+    }
+    if !build_errors.is_empty() {
+        println!("{} Built with errors: {}", "⚠".yellow(), build_errors.len());
+        for (name, error) in &build_errors {
+            println!("   {} {}: {}", "•".yellow(), name, error);
+        }
+    }
+    println!("{}", "═".repeat(50));
+
+    if installed > 0 {
+        println!("\n{} Run 'zsh-mgr init' to update your .zshrc", "💡".bright_cyan());
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("{} plugins failed to install", failures.len());
+    }
+
+    Ok(())
+}
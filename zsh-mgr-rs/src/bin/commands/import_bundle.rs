@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zsh_mgr_rs::bundle::{self, ARCHIVE_PLUGIN_LIST};
+use zsh_mgr_rs::config::{Config, PluginInfo, PluginKind, PluginList};
+use zsh_mgr_rs::installer::{self, InstallRequest, PluginInstaller};
+use zsh_mgr_rs::release::ReleaseSpec;
+
+pub fn run(archive: String) -> Result<()> {
+    let config = Config::load()?;
+    let mut plugin_list = PluginList::load(&config)?;
+    let archive = PathBuf::from(shellexpand::tilde(&archive).to_string());
+
+    if !archive.exists() {
+        anyhow::bail!("Bundle archive not found: {}", archive.display());
+    }
+
+    let installer = PluginInstaller::new(&config)?;
+    let extract_dir = installer.staging_path("import");
+
+    let result = import(&config, &mut plugin_list, &installer, &archive, &extract_dir);
+    if let Err(e) = fs::remove_dir_all(&extract_dir) {
+        log::warn!("Failed to clean up {}: {}", extract_dir.display(), e);
+    }
+    result
+}
+
+fn import(
+    config: &Config,
+    plugin_list: &mut PluginList,
+    installer: &PluginInstaller,
+    archive: &Path,
+    extract_dir: &Path,
+) -> Result<()> {
+    println!("{} Reading bundles from {}", "📋".cyan(), archive.display());
+
+    fs::create_dir_all(extract_dir)?;
+    tar::Archive::new(fs::File::open(archive)?)
+        .unpack(extract_dir)
+        .with_context(|| format!("Failed to extract {}", archive.display()))?;
+
+    let contents = fs::read_to_string(extract_dir.join(ARCHIVE_PLUGIN_LIST))
+        .with_context(|| format!("{} has no {}", archive.display(), ARCHIVE_PLUGIN_LIST))?;
+    let plugins: Vec<PluginInfo> = serde_json::from_str(&contents)?;
+
+    let mut skipped = 0;
+    let mut failures = Vec::new();
+    let mut new_plugins = Vec::new();
+
+    for plugin in &plugins {
+        if plugin_list.get(&plugin.name).is_some() {
+            println!("{} {} already installed", "⚠️".yellow(), plugin.name);
+            skipped += 1;
+            continue;
+        }
+
+        // Plugins loaded from subdirectories share the bundle of their repository; the
+        // first one to be installed clones it and the others reuse the clone
+        let bundle_path = match plugin.kind {
+            PluginKind::Snippet => extract_dir.join(bundle::archive_snippet_path(&plugin.name)),
+            PluginKind::Release => extract_dir.join(bundle::archive_release_path(&plugin.name)),
+            _ => extract_dir.join(bundle::archive_bundle_path(plugin.repo_name())),
+        };
+        let request = InstallRequest::new(plugin.repo_name().to_string(), plugin.flags.clone(), plugin.private)
+            .with_url(plugin.url.clone())
+            .with_pin(plugin.pinned.clone())
+            .with_bundle(bundle_path)
+            .with_sparse(plugin.sparse.clone())
+            .with_path(plugin.path.clone())
+            .with_local((plugin.kind == PluginKind::Local).then(|| PathBuf::from(&plugin.url)))
+            .with_snippet(plugin.kind == PluginKind::Snippet)
+            .with_release((plugin.kind == PluginKind::Release).then(|| ReleaseSpec {
+                asset: plugin.asset.clone(),
+                bins: plugin.bins.clone(),
+            }))
+            .with_build(plugin.build.clone());
+
+        match installer.install(&request, &|_| {}) {
+            Ok(mut installed) => {
+                if plugin.kind == PluginKind::Release {
+                    installed.info.version = plugin.version.clone();
+                    installed.info.checksum = plugin.checksum.clone();
+                }
+                println!("   {} {}", "✓".green(), plugin.name);
+                new_plugins.push(installed);
+            }
+            Err(e) => {
+                println!("   {} {}", "✗".red(), plugin.name);
+                failures.push((plugin.name.clone(), format!("{:#}", e)));
+            }
+        }
+    }
+
+    let installed = new_plugins.len();
+    let build_errors = installer::build_errors(&new_plugins);
+    installer::register(config, plugin_list, new_plugins)?;
+
+    println!("\n{}", "═".repeat(50));
+    println!("{}", "Import Summary".bright_cyan().bold());
+    println!("{}", "═".repeat(50));
+    println!("{} Installed: {}", "✓".green(), installed);
+    println!("{} Skipped: {}", "⚠".yellow(), skipped);
+    if !failures.is_empty() {
+        println!("{} Failed: {}", "✗".red(), failures.len());
+        for (name, error) in &failures {
+            println!("   {} {}: {}", "•".red(), name, error);
+        }
+    }
+    if !build_errors.is_empty() {
+        println!("{} Built with errors: {}", "⚠".yellow(), build_errors.len());
+        for (name, error) in &build_errors {
+            println!("   {} {}: {}", "•".yellow(), name, error);
+        }
+    }
+    println!("{}", "═".repeat(50));
+
+    if installed > 0 {
+        println!("\n{} Run 'zsh-mgr init' to update your .zshrc", "💡".bright_cyan());
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("{} plugins failed to import", failures.len());
+    }
+
+    Ok(())
+}
@@ -2,7 +2,7 @@ use anyhow::Result;
 use colored::Colorize;
 use std::fs;
 use std::path::PathBuf;
-use zsh_mgr_rs::config::{Config, PluginList, real_home_dir};
+use zsh_mgr_rs::config::{Config, PluginKind, PluginList, real_home_dir};
 
 pub fn run(zshrc_path: Option<String>) -> Result<()> {
     let config = Config::load()?;
@@ -15,15 +15,23 @@ pub fn run(zshrc_path: Option<String>) -> Result<()> {
     }
     
     // Generate plugin lines with FULL repo names (user/repo)
-    let mut load_lines = Vec::new();
-    load_lines.push("# ═══════════════════════════════════════════════════════════════════".to_string());
-    load_lines.push("# PLUGINS - Auto-generated by zsh-mgr".to_string());
-    load_lines.push("# ═══════════════════════════════════════════════════════════════════".to_string());
-    load_lines.push("".to_string());
+    let mut load_lines = vec![
+        "# ═══════════════════════════════════════════════════════════════════".to_string(),
+        "# PLUGINS - Auto-generated by zsh-mgr".to_string(),
+        "# ═══════════════════════════════════════════════════════════════════".to_string(),
+        "".to_string(),
+    ];
     
     for plugin in plugin_list.list() {
+        // Release plugins only provide binaries, which are put on PATH below
+        if plugin.kind == PluginKind::Release {
+            continue;
+        }
         // Use FULL repository name (user/repo) instead of just plugin name
-        load_lines.push(format!("plugin {}", plugin.name));
+        load_lines.push(format!("plugin {}", plugin.load_spec()));
+    }
+    if plugin_list.list().iter().any(|p| p.kind == PluginKind::Release) {
+        load_lines.push(format!("path=(\"{}\" $path)", config.bin_dir().display()));
     }
     
     let output = load_lines.join("\n");
@@ -54,7 +62,7 @@ pub fn run(zshrc_path: Option<String>) -> Result<()> {
         println!("{} {} already has plugin calls", "ℹ️".cyan(), zshrc.display());
         println!("\n{}", "Current plugins in zsh-mgr:".bright_cyan());
         for plugin in plugin_list.list() {
-            println!("  plugin {}", plugin.name);
+            println!("  plugin {}", plugin.load_spec());
         }
         println!("\n{}", "Options:".bright_cyan());
         println!("  1. Update manually in {}", zshrc.display());
@@ -73,7 +81,7 @@ pub fn run(zshrc_path: Option<String>) -> Result<()> {
         new_content.push_str(&content[..=line_end]);
         new_content.push_str("\n\n");
         new_content.push_str(&output);
-        new_content.push_str("\n");
+        new_content.push('\n');
         new_content.push_str(&content[line_end + 1..]);
         
         // Write back
@@ -82,7 +90,7 @@ pub fn run(zshrc_path: Option<String>) -> Result<()> {
         println!("{} Updated {}", "✓".green(), zshrc.display());
         println!("\n{}", "Added plugin loading:".bright_cyan());
         for plugin in plugin_list.list() {
-            println!("  {} plugin {}", "•".green(), plugin.name);
+            println!("  {} plugin {}", "•".green(), plugin.load_spec());
         }
         println!("\n{} Run: source ~/.zshrc", "💡".bright_cyan());
     } else {
@@ -44,6 +44,13 @@ pub fn run(
         config_dir: config_dir.clone(),
         time_threshold,
         mgr_time_threshold,
+        object_cache: None,
+        credentials: Default::default(),
+        private_protocol: Default::default(),
+        concurrency: Default::default(),
+        fetch: Default::default(),
+        release_api: zsh_mgr_rs::release::DEFAULT_RELEASE_API.to_string(),
+        build_timeout: zsh_mgr_rs::build::DEFAULT_BUILD_TIMEOUT,
     };
     
     let config_file = config_dir.join("zsh-mgr").join("config.json");
@@ -115,6 +122,9 @@ export MGR_TIME_THRESHOLD={}
 
 # Initialize zsh-mgr if installed
 if command -v zsh-mgr &> /dev/null; then
+    # Binaries installed by release plugins
+    path=("$ZSH_PLUGIN_DIR/.bin" $path)
+
     # Source installed plugins
     for plugin in "$ZSH_PLUGIN_DIR"/*/*.plugin.zsh; do
         [ -f "$plugin" ] && source "$plugin"
@@ -135,6 +145,9 @@ export ZSH_CONFIG_DIR="{}"
 export TIME_THRESHOLD={}
 export MGR_TIME_THRESHOLD={}
 
+# Binaries installed by release plugins
+path=("$ZSH_PLUGIN_DIR/.bin" $path)
+
 # Source installed plugins
 for plugin in "$ZSH_PLUGIN_DIR"/*/*.plugin.zsh; do
     [ -f "$plugin" ] && source "$plugin"
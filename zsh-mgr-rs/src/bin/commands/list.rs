@@ -1,6 +1,6 @@
 use anyhow::Result;
 use colored::Colorize;
-use zsh_mgr_rs::config::{Config, PluginList};
+use zsh_mgr_rs::config::{Config, PluginKind, PluginList};
 
 pub fn run(json: bool, names_only: bool) -> Result<()> {
     let config = Config::load()?;
@@ -16,7 +16,7 @@ pub fn run(json: bool, names_only: bool) -> Result<()> {
     if names_only {
         // Output only the plugin names (repo name after last '/')
         for plugin in plugins {
-            let repo_name = plugin.name.split('/').last().unwrap_or(&plugin.name);
+            let repo_name = plugin.name.split('/').next_back().unwrap_or(&plugin.name);
             println!("{}", repo_name);
         }
     } else if json {
@@ -25,9 +25,28 @@ pub fn run(json: bool, names_only: bool) -> Result<()> {
         println!("{} Installed plugins:", "📦".cyan());
         println!();
         for plugin in plugins {
-            let private_marker = if plugin.private { "🔒" } else { "  " };
-            println!("  {} {}", private_marker, plugin.name.bright_white());
-            println!("     URL: {}", plugin.url.dimmed());
+            let marker = match plugin.kind {
+                PluginKind::Local => "📂",
+                PluginKind::Snippet => "📄",
+                PluginKind::Release => "🧰",
+                PluginKind::Git if plugin.private => "🔒",
+                PluginKind::Git => "  ",
+            };
+            println!("  {} {}", marker, plugin.name.bright_white());
+            if plugin.kind == PluginKind::Local {
+                println!("     Path: {}", plugin.url.dimmed());
+            } else {
+                println!("     URL: {}", plugin.url.dimmed());
+            }
+            if let Some(ref repo) = plugin.repo {
+                println!("     Clone: {} (shared)", repo.dimmed());
+            }
+            if let Some(ref version) = plugin.version {
+                println!("     Release: {} ({})", version.dimmed(), plugin.bins.join(", ").dimmed());
+            }
+            if !plugin.build.is_empty() {
+                println!("     Build: {}", plugin.build.join(" && ").dimmed());
+            }
             if let Some(ref flags) = plugin.flags {
                 println!("     Flags: {}", flags.dimmed());
             }
@@ -1,9 +1,14 @@
 pub mod add;
 pub mod bootstrap;
 pub mod check;
+pub mod export;
+pub mod export_bundle;
+pub mod import;
+pub mod import_bundle;
 pub mod init;
 pub mod install;
 pub mod list;
 pub mod remove;
 pub mod sync;
+pub mod unshallow;
 pub mod update;
@@ -1,17 +1,19 @@
 use anyhow::Result;
 use colored::Colorize;
 use std::io::{self, Write};
-use zsh_mgr_rs::config::{Config, PluginList};
+use zsh_mgr_rs::config::{Config, PluginKind, PluginList};
+use zsh_mgr_rs::release;
 
 pub fn run(plugin: String, force: bool) -> Result<()> {
     let config = Config::load()?;
     let mut plugin_list = PluginList::load(&config)?;
     
     // Check if plugin exists
-    if plugin_list.get(&plugin).is_none() {
+    let Some(info) = plugin_list.get(&plugin).cloned() else {
         eprintln!("{} Plugin '{}' is not installed", "⚠️".yellow(), plugin);
         return Ok(());
-    }
+    };
+    let repo = info.repo_name().to_string();
     
     // Confirm removal if not forced
     if !force {
@@ -31,14 +33,35 @@ pub fn run(plugin: String, force: bool) -> Result<()> {
     plugin_list.remove(&plugin);
     plugin_list.save()?;
     
+    // Only the link of a local plugin is removed, never its directory
+    if info.kind == PluginKind::Local {
+        let link = config.plugin_dir.join(&plugin);
+        if link.symlink_metadata().is_ok() {
+            std::fs::remove_file(&link)?;
+        }
+        println!("{} Plugin '{}' removed successfully ({} was left in place)", "✓".green(), plugin, info.url);
+        return Ok(());
+    }
+    
+    // A clone shared with other plugins of the same repository stays
+    let users = plugin_list.plugins_of(&repo).count();
+    if users > 0 {
+        println!("{} Plugin '{}' removed successfully", "✓".green(), plugin);
+        println!("{} Keeping the clone of {}, still used by {} plugins", "ℹ️".blue(), repo, users);
+        return Ok(());
+    }
+    
     // Remove directory
-    let plugin_dir = config.plugin_dir.join(&plugin);
+    let plugin_dir = config.plugin_dir.join(&repo);
+    if info.kind == PluginKind::Release {
+        release::unlink_bins(&config.bin_dir(), &plugin_dir)?;
+    }
     if plugin_dir.exists() {
         std::fs::remove_dir_all(&plugin_dir)?;
     }
     
     // Remove timestamp file
-    let timestamp_file = config.timestamp_file(&plugin);
+    let timestamp_file = config.timestamp_file(&repo);
     if timestamp_file.exists() {
         std::fs::remove_file(&timestamp_file)?;
     }
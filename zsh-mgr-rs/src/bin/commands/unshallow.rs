@@ -0,0 +1,56 @@
+use anyhow::Result;
+use colored::Colorize;
+use std::sync::Arc;
+use zsh_mgr_rs::clone::flags_without_depth;
+use zsh_mgr_rs::config::{Config, PluginList};
+use zsh_mgr_rs::credentials_manager::CredentialManager;
+use zsh_mgr_rs::engine::RepoUpdater;
+use zsh_mgr_rs::progress::format_bytes;
+
+pub fn run(plugin: String) -> Result<()> {
+    let config = Config::load()?;
+    let mut plugin_list = PluginList::load(&config)?;
+
+    let Some(info) = plugin_list.get(&plugin) else {
+        eprintln!("{} Plugin '{}' is not installed", "⚠️".yellow(), plugin);
+        return Ok(());
+    };
+    if !info.kind.is_git() {
+        eprintln!("{} {} is not a git clone", "ℹ️".blue(), plugin);
+        return Ok(());
+    }
+    let flags = match info.flags {
+        Some(ref flags) => flags_without_depth(flags)?,
+        None => None,
+    };
+    // Subdirectory plugins unshallow the clone they share
+    let clone_dir = config.plugin_dir.join(info.repo_name());
+
+    let credentials = Arc::new(CredentialManager::new()
+        .map_err(|e| anyhow::anyhow!("Failed to create credential manager: {}", e))?
+        .with_settings(&config.credentials));
+    let mut updater = RepoUpdater::new(clone_dir, credentials)?
+        .with_fetch_settings(config.fetch.clone());
+
+    eprintln!("{} Fetching the full history of {}...", "📥".cyan(), plugin);
+    match updater.unshallow()? {
+        // Fetches through git report no transfer stats
+        Some(fetched) if fetched.bytes_received == 0 => {
+            eprintln!("{} {} now has its full history", "✓".green(), plugin)
+        }
+        Some(fetched) => eprintln!(
+            "{} {} now has its full history ({})",
+            "✓".green(),
+            plugin,
+            format_bytes(fetched.bytes_received)
+        ),
+        None => eprintln!("{} {} is not a shallow clone", "ℹ️".blue(), plugin),
+    }
+
+    // Later updates must not make it shallow again
+    plugin_list.set_depth(&plugin, None);
+    plugin_list.set_flags(&plugin, flags);
+    plugin_list.save()?;
+
+    Ok(())
+}
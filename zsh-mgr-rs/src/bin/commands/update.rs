@@ -1,84 +1,695 @@
 use anyhow::Result;
+use clap::ValueEnum;
 use colored::Colorize;
-use std::sync::Arc;
-use zsh_mgr_rs::config::{Config, PluginList};
-use zsh_mgr_rs::updater::{BatchUpdater, UpdateConfig};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use zsh_mgr_rs::build::BuildResult;
+use zsh_mgr_rs::config::{ConcurrencySettings, Config, PluginInfo, PluginKind, PluginList};
+use zsh_mgr_rs::engine::{
+    BatchUpdateResults, BatchUpdater, CancelToken, EventStream, FetchResult, MergeInfo, MergeType,
+    PendingChanges, UpdateConfig, UpdateEvent, UpdateResult,
+};
+use zsh_mgr_rs::history::HistoryRecord;
+use zsh_mgr_rs::installer::PluginInstaller;
+use zsh_mgr_rs::local::LocalStatus;
+use zsh_mgr_rs::progress::ProgressDisplay;
+use zsh_mgr_rs::snippet;
 
-pub fn run(only: Option<Vec<String>>, verbose: bool, jobs: Option<usize>) -> Result<()> {
+/// Machine-readable progress written to stdout while updating
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventFormat {
+    /// One JSON object per line (see `UpdateEvent`)
+    Ndjson,
+}
+
+/// Lines of output shown for a failed build; the update result keeps all of it
+const BUILD_OUTPUT_LINES: usize = 10;
+
+/// Decision taken for a plugin during `update --review`
+enum ReviewChoice {
+    Apply,
+    Skip,
+    Pin,
+    Quit,
+}
+
+pub fn run(
+    only: Option<Vec<String>>,
+    verbose: bool,
+    jobs: Option<usize>,
+    review: bool,
+    events: Option<EventFormat>,
+) -> Result<()> {
     let config = Config::load()?;
     let mut plugin_list = PluginList::load(&config)?;
-    
-    // Configure thread pool
-    if let Some(j) = jobs {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(j)
-            .build_global()?;
-    }
-    
-    // Get plugins to update
+
+    // Get plugins to update. Plugins loaded from subdirectories of one repository share
+    // its clone, which is updated once under the repository name.
     let plugins: Vec<_> = if let Some(filter) = only {
-        plugin_list
+        let repos: Vec<String> = plugin_list
             .list()
             .iter()
-            .filter(|p| filter.contains(&p.name))
-            .cloned()
+            .filter(|p| filter.contains(&p.name) || filter.iter().any(|f| f == p.repo_name()))
+            .map(|p| p.repo_name().to_string())
+            .collect();
+        plugin_list
+            .repos()
+            .into_iter()
+            .filter(|r| repos.contains(&r.name))
+            .collect()
+    } else {
+        plugin_list.repos()
+    };
+
+    // Local plugins are never updated; only the state of their repository is reported
+    let (local, plugins): (Vec<_>, Vec<_>) = plugins.into_iter().partition(|p| p.kind == PluginKind::Local);
+    print_local(&local);
+
+    // Pinned plugins are only considered in review mode, where they can be unpinned
+    let plugins: Vec<_> = if review {
+        plugins
+    } else {
+        plugins
+            .into_iter()
+            .filter(|p| match p.pinned {
+                Some(ref sha) => {
+                    eprintln!("{} Skipping {} (pinned at {})", "📌".yellow(), p.name, short_sha(sha));
+                    false
+                }
+                None => true,
+            })
             .collect()
+    };
+
+    // Snippets and releases are downloaded instead of fetched, so there is nothing to review
+    // before they change
+    let (files, plugins): (Vec<_>, Vec<_>) = plugins
+        .into_iter()
+        .partition(|p| matches!(p.kind, PluginKind::Snippet | PluginKind::Release));
+    let files = if review {
+        for plugin in &files {
+            eprintln!("{} Skipping {} (downloads cannot be reviewed)", "⏭".yellow(), plugin.name);
+        }
+        Vec::new()
     } else {
-        plugin_list.list().to_vec()
+        files
     };
-    
-    if plugins.is_empty() {
+
+    if plugins.is_empty() && files.is_empty() {
         eprintln!("{} No plugins to update", "ℹ️".blue());
         return Ok(());
     }
-    
-    // Build repository paths
-    let repo_paths: Vec<_> = plugins
-        .iter()
-        .map(|p| config.plugin_dir.join(&p.name))
-        .collect();
-    
-    // Create updater
-    let update_config = UpdateConfig::new(false, verbose);
-    let updater = BatchUpdater::new(Arc::new(update_config), &repo_paths)?;
-    
-    // Update repositories
-    println!("{} Updating {} plugins...", "🔄".cyan(), plugins.len());
-    let results = updater.update_all(&repo_paths);
-    
+
+    let events = events.map(|EventFormat::Ndjson| Arc::new(EventStream::stdout()));
+    let table = Arc::new(Mutex::new(None));
+    let cancel = install_interrupt_handler(Arc::clone(&table))?;
+    let start = std::time::Instant::now();
+
+    // Snippets and releases are updated before the git batch and reported along with it
+    let (snippets, releases): (Vec<_>, Vec<_>) = files.iter().partition(|p| p.kind == PluginKind::Snippet);
+    let mut file_results = update_snippets(&config, &mut plugin_list, &snippets, &cancel, events.as_deref());
+    file_results.extend(update_releases(&config, &mut plugin_list, &releases, &cancel, events.as_deref())?);
+    let files: Vec<_> = snippets.into_iter().chain(releases).cloned().collect();
+
+    let git_results = if plugins.is_empty() {
+        Vec::new()
+    } else {
+        // Build repository paths
+        let repo_paths: Vec<_> = plugins
+            .iter()
+            .map(|p| config.plugin_dir.join(&p.name))
+            .collect();
+
+        // Create updater
+        // The live table is redrawn in place, which log lines and review prompts would break
+        let update_config = UpdateConfig::new(false, verbose)
+            .with_progress(!verbose && !review)
+            .with_object_cache(config.object_cache.clone())
+            .with_credentials(config.credentials.clone())
+            .with_fetch(config.fetch.clone())
+            .with_concurrency(ConcurrencySettings {
+                jobs: jobs.or(config.concurrency.jobs),
+                ..config.concurrency.clone()
+            });
+        let labels = plugins.iter().map(|p| p.name.clone()).collect();
+        let depths = plugins.iter().map(|p| p.depth).collect();
+        let updater = BatchUpdater::new(Arc::new(update_config), &repo_paths)?
+            .with_labels(&repo_paths, labels)
+            .with_depths(&repo_paths, depths)
+            .with_events(events.clone())
+            .with_cancel(cancel.clone());
+
+        if review {
+            run_review(&mut plugin_list, &plugins, &repo_paths, &updater, &cancel)?
+        } else {
+            // Update repositories
+            // stdout is reserved for the event stream
+            eprintln!("{} Updating {} plugins...", "🔄".cyan(), plugins.len());
+            set_table(&table, updater.progress());
+            let results = updater.update_all(&repo_paths).results;
+            set_table(&table, None);
+            results
+        }
+    };
+
+    let plugins: Vec<_> = files.into_iter().chain(plugins).collect();
+    let mut results = BatchUpdateResults::new(file_results.into_iter().chain(git_results).collect(), start.elapsed());
+    run_builds(&config, &plugin_list, &mut results, &cancel, events.as_deref())?;
+
     // Update timestamps
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)?
         .as_secs();
-    
+
+    // Plugins skipped or pinned during a review stay due
     for (plugin, result) in plugins.iter().zip(results.results.iter()) {
-        if result.success {
+        let skipped = result.merge_info.as_ref().is_some_and(|m| m.merge_type == MergeType::Skipped);
+        if result.success && !skipped {
             std::fs::write(config.timestamp_file(&plugin.name), now.to_string())?;
             plugin_list.update_timestamp(&plugin.name, now);
         }
     }
-    
+
     plugin_list.save()?;
-    
-    // Print summary
-    eprintln!();
-    eprintln!("══════════════════════════════════════════════════════════");
-    if results.failed == 0 {
-        eprintln!(
-            "{} Updated {} repositories in {:.2}s",
+
+    let names: Vec<_> = plugins.iter().map(|p| p.name.clone()).collect();
+    let record = HistoryRecord::new(&names, &results, cancel.is_cancelled());
+    if let Err(e) = record.append(&config.history_file()) {
+        eprintln!("{} Failed to write update history: {}", "⚠️".yellow(), e);
+    }
+
+    print_summary(&results);
+
+    if cancel.is_cancelled() {
+        print_incomplete(&plugins, &results);
+        anyhow::bail!("Update interrupted");
+    }
+    if results.failed > 0 {
+        anyhow::bail!("{} plugins failed to update", results.failed);
+    }
+
+    Ok(())
+}
+
+/// Show the git status of local plugins in place of updating them
+fn print_local(plugins: &[PluginInfo]) {
+    for plugin in plugins {
+        let path = Path::new(&plugin.url);
+        if !path.is_dir() {
+            eprintln!("{} Skipping {} (local): {} not found", "⚠️".yellow(), plugin.name, path.display());
+            continue;
+        }
+        match LocalStatus::read(path) {
+            Ok(Some(status)) => eprintln!("{} Skipping {} (local, {})", "📂".cyan(), plugin.name, status),
+            Ok(None) => eprintln!("{} Skipping {} (local, not a git repository)", "📂".cyan(), plugin.name),
+            Err(e) => eprintln!("{} Skipping {} (local): {}", "⚠️".yellow(), plugin.name, e.message()),
+        }
+    }
+}
+
+/// Download snippets again, replacing the ones whose contents changed
+fn update_snippets(
+    config: &Config,
+    plugin_list: &mut PluginList,
+    snippets: &[&PluginInfo],
+    cancel: &CancelToken,
+    events: Option<&EventStream>,
+) -> Vec<UpdateResult> {
+    if !snippets.is_empty() {
+        eprintln!("{} Checking {} snippets...", "📄".cyan(), snippets.len());
+    }
+    snippets
+        .iter()
+        .map(|plugin| {
+            update_download(config, plugin, plugin.checksum.clone(), cancel, events, || {
+                let update = snippet::update(
+                    &config.plugin_dir.join(&plugin.name),
+                    &plugin.url,
+                    plugin.checksum.as_deref(),
+                    plugin.etag.as_deref(),
+                )?;
+                let checksum = update.checksum.clone();
+                plugin_list.set_snippet(&plugin.name, update.checksum, update.etag);
+                Ok(update.changed.then_some(checksum))
+            })
+        })
+        .collect()
+}
+
+/// Install the latest release of release plugins that are behind it
+fn update_releases(
+    config: &Config,
+    plugin_list: &mut PluginList,
+    releases: &[&PluginInfo],
+    cancel: &CancelToken,
+    events: Option<&EventStream>,
+) -> Result<Vec<UpdateResult>> {
+    if releases.is_empty() {
+        return Ok(Vec::new());
+    }
+    eprintln!("{} Checking {} releases...", "🧰".cyan(), releases.len());
+    let installer = PluginInstaller::new(config)?;
+
+    Ok(releases
+        .iter()
+        .map(|plugin| {
+            update_download(config, plugin, plugin.version.clone(), cancel, events, || {
+                let Some(extracted) = installer.update_release(plugin)? else {
+                    return Ok(None);
+                };
+                plugin_list.set_release(&plugin.name, extracted.tag.clone(), extracted.checksum);
+                Ok(Some(extracted.tag))
+            })
+        })
+        .collect())
+}
+
+/// Run `update` for a snippet or release and report it like a repository of the git batch.
+/// `old` is its checksum or version, which stand in for the commits; `update` returns the new
+/// one if it was replaced.
+fn update_download(
+    config: &Config,
+    plugin: &PluginInfo,
+    old: Option<String>,
+    cancel: &CancelToken,
+    events: Option<&EventStream>,
+    update: impl FnOnce() -> Result<Option<String>>,
+) -> UpdateResult {
+    let start = std::time::Instant::now();
+    let emit = |event| {
+        if let Some(events) = events {
+            events.emit(event);
+        }
+    };
+    let mut result = UpdateResult {
+        repo_path: config.plugin_dir.join(&plugin.name),
+        branch: String::from("unknown"),
+        success: false,
+        fetch_info: None,
+        merge_info: None,
+        duration: Duration::ZERO,
+        error: None,
+        old_head: old.clone(),
+        new_head: old,
+        interrupted: false,
+        builds: Vec::new(),
+    };
+
+    if cancel.is_cancelled() {
+        emit(UpdateEvent::Interrupted { plugin: plugin.name.clone(), duration_ms: 0 });
+        result.error = Some(String::from("Interrupted"));
+        result.interrupted = true;
+        return result;
+    }
+
+    emit(UpdateEvent::Started {
+        plugin: plugin.name.clone(),
+        path: result.repo_path.display().to_string(),
+        branch: None,
+        head: result.old_head.clone(),
+    });
+    let outcome = update();
+    result.duration = start.elapsed();
+    let duration_ms = result.duration.as_millis() as u64;
+
+    match outcome {
+        Ok(new) => {
+            let merge_type = if new.is_some() { MergeType::Replaced } else { MergeType::UpToDate };
+            match new {
+                Some(ref version) if plugin.kind == PluginKind::Release => {
+                    eprintln!("   {} {} updated to {}", "✓".green(), plugin.name, version)
+                }
+                Some(_) => eprintln!("   {} {} updated", "✓".green(), plugin.name),
+                None => eprintln!("   {} {} up to date", "✓".green(), plugin.name),
+            }
+            if new.is_some() {
+                result.new_head = new;
+            }
+            result.success = true;
+            result.merge_info = Some(MergeInfo::new(merge_type));
+            emit(UpdateEvent::Done {
+                plugin: plugin.name.clone(),
+                merge_type: Some(merge_type),
+                head: result.new_head.clone(),
+                duration_ms,
+            });
+        }
+        Err(e) => {
+            let error = format!("{:#}", e);
+            eprintln!("   {} {}: {}", "✗".red(), plugin.name, error);
+            emit(UpdateEvent::Failed { plugin: plugin.name.clone(), error: error.clone(), duration_ms });
+            result.error = Some(error);
+        }
+    }
+    result
+}
+
+/// Run the build commands of the plugins whose clone moved to another commit, attaching
+/// their outcome to the result of the clone. A failed build does not fail the update.
+/// Once `cancel` is set no further build starts and the running one is killed.
+fn run_builds(
+    config: &Config,
+    plugin_list: &PluginList,
+    results: &mut BatchUpdateResults,
+    cancel: &CancelToken,
+    events: Option<&EventStream>,
+) -> Result<()> {
+    let mut installer = None;
+    for result in results.results.iter_mut().filter(|r| r.head_changed()) {
+        let plugins = plugin_list
+            .list()
+            .iter()
+            .filter(|p| !p.build.is_empty() && config.plugin_dir.join(p.repo_name()) == result.repo_path);
+        for plugin in plugins {
+            if cancel.is_cancelled() {
+                return Ok(());
+            }
+            if installer.is_none() {
+                installer = Some(PluginInstaller::new(config)?.with_cancel(cancel.clone()));
+            }
+            eprintln!("{} Building {}...", "🔨".cyan(), plugin.name);
+            let Some(build) = installer.as_ref().and_then(|i| i.build(plugin)) else {
+                continue;
+            };
+            print_build(&build);
+            if let Some(events) = events {
+                events.emit(UpdateEvent::Built {
+                    plugin: plugin.name.clone(),
+                    success: build.success,
+                    error: build.error.clone(),
+                    duration_ms: build.duration.as_millis() as u64,
+                });
+            }
+            result.builds.push(build);
+        }
+    }
+    Ok(())
+}
+
+/// Report the outcome of the build commands of a plugin, with their output if they failed
+pub fn print_build(build: &BuildResult) {
+    match build.error {
+        None => eprintln!(
+            "   {} Built {} in {:.1}s",
             "✓".green(),
-            results.total,
-            results.results.iter().map(|r| r.duration).sum::<f64>() / results.total as f64
+            build.plugin,
+            build.duration.as_secs_f64()
+        ),
+        Some(ref error) => {
+            eprintln!("   {} {} built with errors: {}", "⚠".yellow(), build.plugin, error);
+            let lines: Vec<_> = build.output.lines().collect();
+            for line in &lines[lines.len().saturating_sub(BUILD_OUTPUT_LINES)..] {
+                eprintln!("     {}", line.dimmed());
+            }
+        }
+    }
+}
+
+/// List the plugins whose build failed, below a summary
+fn print_build_errors(results: &BatchUpdateResults) {
+    for build in results.results.iter().flat_map(|r| &r.builds).filter(|b| !b.success) {
+        eprintln!(
+            "   {} {}: built with errors ({})",
+            "•".yellow(),
+            build.plugin,
+            build.error.as_deref().unwrap_or_default()
+        );
+    }
+}
+
+/// Progress table on screen while the git batch runs, for messages that must not break it
+type ActiveTable = Arc<Mutex<Option<Arc<ProgressDisplay>>>>;
+
+fn set_table(table: &ActiveTable, progress: Option<Arc<ProgressDisplay>>) {
+    if let Ok(mut table) = table.lock() {
+        *table = progress;
+    }
+}
+
+/// Turn the first Ctrl-C into a cancellation of the running batch; a second one exits at once.
+/// The notice goes below the progress table while one is shown, so the next redraw keeps it.
+fn install_interrupt_handler(table: ActiveTable) -> Result<CancelToken> {
+    let cancel = CancelToken::new();
+    let handler = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            std::process::exit(130);
+        }
+        handler.cancel();
+        let message = format!(
+            "{} Interrupted, finishing plugins in progress (press Ctrl-C again to abort now)...",
+            "⚠️".yellow()
         );
+        match table.lock().ok().and_then(|table| table.clone()) {
+            Some(progress) => progress.set_footer(message),
+            None => eprintln!("\n{}", message),
+        }
+    })?;
+    Ok(cancel)
+}
+
+/// List the plugins a cancelled run did not get to, or stopped before they changed
+fn print_incomplete(plugins: &[PluginInfo], results: &BatchUpdateResults) {
+    let incomplete: Vec<_> = plugins
+        .iter()
+        .zip(results.results.iter())
+        .filter(|(_, result)| result.interrupted)
+        .map(|(plugin, _)| plugin.name.as_str())
+        .collect();
+    if incomplete.is_empty() {
+        return;
+    }
+    eprintln!("{} {} plugins were not updated:", "⚠️".yellow(), incomplete.len());
+    for name in incomplete {
+        eprintln!("   {} {}", "•".yellow(), name);
+    }
+}
+
+/// Fetch everything, let the user decide per plugin, then merge the approved ones in parallel.
+/// Returns one result per plugin, with the skipped and pinned ones as `MergeType::Skipped`.
+fn run_review(
+    plugin_list: &mut PluginList,
+    plugins: &[PluginInfo],
+    repo_paths: &[PathBuf],
+    updater: &BatchUpdater,
+    cancel: &CancelToken,
+) -> Result<Vec<UpdateResult>> {
+    println!("{} Fetching {} plugins...", "🔄".cyan(), plugins.len());
+    let fetched = updater.fetch_all(repo_paths);
+    let total = fetched.iter().filter(|r| r.pending.is_some()).count();
+
+    println!();
+    if total == 0 {
+        println!("{} All plugins are up to date", "✓".green());
+    }
+
+    let mut to_apply = Vec::new();
+    // Plugins to apply get their result from `apply_all` below
+    let mut results: Vec<Option<UpdateResult>> = Vec::new();
+    let mut position = 0;
+    let mut quit = false;
+
+    for (plugin, result) in plugins.iter().zip(fetched.iter()) {
+        let Some(ref changes) = result.pending else {
+            results.push(Some(if result.success {
+                reviewed(result, MergeType::UpToDate)
+            } else {
+                not_reviewed(result, result.error.clone().unwrap_or_default(), cancel.is_cancelled())
+            }));
+            continue;
+        };
+        position += 1;
+
+        // Ctrl-C while reviewing ends the review, keeping the choices made so far
+        if cancel.is_cancelled() {
+            results.push(Some(not_reviewed(result, String::from("Interrupted"), true)));
+            continue;
+        }
+        if quit {
+            results.push(Some(reviewed(result, MergeType::Skipped)));
+            continue;
+        }
+
+        print_pending(position, total, plugin, changes);
+
+        match prompt_choice()? {
+            ReviewChoice::Apply => {
+                to_apply.push((result.repo_path.clone(), changes.remote));
+                plugin_list.set_pinned(&plugin.name, None);
+                results.push(None);
+            }
+            ReviewChoice::Skip => results.push(Some(reviewed(result, MergeType::Skipped))),
+            ReviewChoice::Pin => {
+                plugin_list.set_pinned(&plugin.name, Some(changes.local.to_string()));
+                println!("{} Pinned {} at {}", "📌".yellow(), plugin.name, short_sha(&changes.local.to_string()));
+                results.push(Some(reviewed(result, MergeType::Skipped)));
+            }
+            ReviewChoice::Quit => {
+                quit = true;
+                results.push(Some(reviewed(result, MergeType::Skipped)));
+            }
+        }
+    }
+
+    let mut applied = if to_apply.is_empty() {
+        Vec::new()
     } else {
-        eprintln!(
-            "{} Updated repositories: {} successful, {} failed",
-            "⚠".yellow(),
-            results.successful.to_string().green(),
-            results.failed.to_string().red()
+        println!();
+        println!("{} Applying {} plugins...", "⬇️".cyan(), to_apply.len());
+        updater.apply_all(&to_apply).results
+    }
+    .into_iter();
+
+    Ok(results.into_iter().filter_map(|result| result.or_else(|| applied.next())).collect())
+}
+
+/// Result of a reviewed plugin left as it was, up to date or skipped
+fn reviewed(fetched: &FetchResult, merge_type: MergeType) -> UpdateResult {
+    UpdateResult {
+        success: true,
+        merge_info: Some(MergeInfo::new(merge_type)),
+        ..unchanged(fetched)
+    }
+}
+
+/// Result of a plugin that failed to fetch, or was not reviewed because of Ctrl-C
+fn not_reviewed(fetched: &FetchResult, error: String, interrupted: bool) -> UpdateResult {
+    UpdateResult { error: Some(error), interrupted, ..unchanged(fetched) }
+}
+
+fn unchanged(fetched: &FetchResult) -> UpdateResult {
+    let head = match fetched.pending {
+        Some(ref changes) => Some(changes.local.to_string()),
+        None => git2::Repository::open(&fetched.repo_path)
+            .ok()
+            .and_then(|repo| repo.head().ok()?.target())
+            .map(|oid| oid.to_string()),
+    };
+    UpdateResult {
+        repo_path: fetched.repo_path.clone(),
+        branch: fetched.pending.as_ref().map_or_else(|| String::from("unknown"), |p| p.branch.clone()),
+        success: false,
+        fetch_info: fetched.fetch_info.clone(),
+        merge_info: None,
+        duration: fetched.duration,
+        error: None,
+        old_head: head.clone(),
+        new_head: head,
+        interrupted: false,
+        builds: Vec::new(),
+    }
+}
+
+fn print_pending(position: usize, total: usize, plugin: &PluginInfo, changes: &PendingChanges) {
+    println!("{}", "─".repeat(58).bright_black());
+    println!(
+        "[{}/{}] {} ({}) {}..{}",
+        position,
+        total,
+        plugin.name.bright_white().bold(),
+        changes.branch,
+        short_sha(&changes.local.to_string()),
+        short_sha(&changes.remote.to_string())
+    );
+    if let Some(ref sha) = plugin.pinned {
+        println!("   {} currently pinned at {}", "📌".yellow(), short_sha(sha));
+    }
+    println!();
+    for commit in &changes.commits {
+        println!("   {}", commit);
+    }
+    if changes.total_commits > changes.commits.len() {
+        println!(
+            "   {}",
+            format!("... and {} more commits", changes.total_commits - changes.commits.len()).dimmed()
         );
     }
+    println!();
+    println!(
+        "   {} files changed, {} insertions(+), {} deletions(-)",
+        changes.files_changed,
+        changes.insertions.to_string().green(),
+        changes.deletions.to_string().red()
+    );
+    println!();
+}
+
+fn prompt_choice() -> Result<ReviewChoice> {
+    loop {
+        print!("{} [a]pply, [s]kip, [p]in, [q]uit reviewing? ", "?".yellow());
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            // EOF: treat as quitting the review
+            return Ok(ReviewChoice::Quit);
+        }
+
+        match input.trim().to_ascii_lowercase().as_str() {
+            "a" | "apply" => return Ok(ReviewChoice::Apply),
+            "s" | "skip" | "" => return Ok(ReviewChoice::Skip),
+            "p" | "pin" => return Ok(ReviewChoice::Pin),
+            "q" | "quit" => return Ok(ReviewChoice::Quit),
+            other => eprintln!("{} Unknown option '{}'", "⚠️".yellow(), other),
+        }
+    }
+}
+
+fn print_summary(results: &BatchUpdateResults) {
+    eprintln!();
     eprintln!("══════════════════════════════════════════════════════════");
-    
-    Ok(())
+    let updated = results.updated();
+    let no_upstream = results.no_upstream();
+    let skipped = results.skipped();
+    let up_to_date = results.successful - updated - no_upstream - skipped;
+    let interrupted = results.interrupted();
+    let failed = results.failed - interrupted;
+
+    let mut counts = format!("{} updated, {} up to date", updated.to_string().green(), up_to_date);
+    if no_upstream > 0 {
+        counts.push_str(&format!(", {} without upstream", no_upstream.to_string().yellow()));
+    }
+    if skipped > 0 {
+        counts.push_str(&format!(", {} skipped", skipped.to_string().yellow()));
+    }
+    if failed > 0 {
+        counts.push_str(&format!(", {} failed", failed.to_string().red()));
+    }
+    if interrupted > 0 {
+        counts.push_str(&format!(", {} interrupted", interrupted.to_string().yellow()));
+    }
+    let built_with_errors = results.built_with_errors();
+    if built_with_errors > 0 {
+        counts.push_str(&format!(", {} built with errors", built_with_errors.to_string().yellow()));
+    }
+    let icon = if results.failed == 0 && no_upstream == 0 && built_with_errors == 0 {
+        "✓".green()
+    } else {
+        "⚠".yellow()
+    };
+    eprintln!(
+        "{} Checked {} plugins in {:.2}s: {}",
+        icon,
+        results.total,
+        results.elapsed.as_secs_f64(),
+        counts
+    );
+
+    // Interrupted plugins are listed separately by `print_incomplete`
+    for result in &results.results {
+        let no_upstream = result.merge_info.as_ref().is_some_and(|m| m.merge_type == MergeType::NoUpstream);
+        if no_upstream {
+            eprintln!("   {} {}: {}", "•".yellow(), result.repo_path.display(), result.message());
+        } else if !result.success && !result.interrupted {
+            eprintln!("   {} {}: {}", "•".red(), result.repo_path.display(), result.message());
+        }
+    }
+    print_build_errors(results);
+    eprintln!("══════════════════════════════════════════════════════════");
+}
+
+fn short_sha(sha: &str) -> &str {
+    &sha[..sha.len().min(7)]
 }
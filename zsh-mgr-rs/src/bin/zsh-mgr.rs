@@ -1,5 +1,6 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use zsh_mgr_rs::fetch_options::{FetchOverrides, TagMode};
 
 mod commands;
 use commands::*;
@@ -17,7 +18,7 @@ struct Cli {
 enum Commands {
     /// Add a new plugin
     Add {
-        /// Plugin in format "user/repo"
+        /// Plugin in format "user/repo", a directory with --local, or a URL or file with --snippet
         plugin: String,
         
         /// Git clone flags
@@ -27,6 +28,59 @@ enum Commands {
         /// Private repository (use SSH)
         #[arg(short, long)]
         private: bool,
+        
+        /// Clone the private repository over HTTPS with an access token instead of SSH
+        #[arg(long, requires = "private")]
+        https: bool,
+        
+        /// SSH key to use for this plugin (repeatable)
+        #[arg(short, long = "identity")]
+        identity: Vec<String>,
+        
+        /// Delete remote branches of this plugin that were deleted upstream
+        #[arg(long)]
+        prune: bool,
+        
+        /// Tags to fetch for this plugin
+        #[arg(long, value_enum, value_name = "MODE")]
+        tags: Option<TagMode>,
+        
+        /// Refspec to fetch instead of the upstream branch (repeatable)
+        #[arg(long)]
+        refspec: Vec<String>,
+        
+        /// Check out only this directory of the plugin (repeatable); needs git installed
+        #[arg(long, value_name = "DIR")]
+        sparse: Vec<String>,
+        
+        /// Load the plugin from this subdirectory of the repository, sharing its clone
+        #[arg(long, value_name = "DIR")]
+        path: Option<String>,
+        
+        /// Link a local directory as a plugin instead of cloning a repository
+        #[arg(long, conflicts_with_all = ["flags", "private", "identity", "prune", "tags", "refspec", "sparse", "path"])]
+        local: bool,
+        
+        /// Download a single file from a URL (http, https or file://) or path instead of cloning
+        #[arg(long, conflicts_with_all = ["flags", "private", "identity", "prune", "tags", "refspec", "sparse", "path", "local"])]
+        snippet: bool,
+        
+        /// Install binaries from the latest release of the repository instead of cloning it
+        #[arg(long, conflicts_with_all = ["flags", "private", "identity", "prune", "tags", "refspec", "sparse", "path", "local", "snippet"])]
+        release: bool,
+        
+        /// Release asset to download, with wildcards and {os}/{arch} [default: *{os}*{arch}*]
+        #[arg(long, value_name = "PATTERN", requires = "release")]
+        asset: Option<String>,
+        
+        /// Binary of the release to put on PATH (repeatable) [default: name of the repository]
+        #[arg(long = "bin", value_name = "NAME", requires = "release")]
+        bin: Vec<String>,
+        
+        /// Shell command to run in the plugin directory after installing and after updates that
+        /// change it (repeatable)
+        #[arg(long, value_name = "CMD", conflicts_with_all = ["local", "snippet", "release"])]
+        build: Vec<String>,
     },
     
     /// Update all plugins
@@ -42,6 +96,14 @@ enum Commands {
         /// Parallel jobs
         #[arg(short, long)]
         jobs: Option<usize>,
+        
+        /// Fetch first, then review each plugin's changes and apply, skip or pin it
+        #[arg(long)]
+        review: bool,
+        
+        /// Stream progress events to stdout (one JSON object per line)
+        #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "review")]
+        events: Option<update::EventFormat>,
     },
     
     /// Check next update dates
@@ -80,6 +142,12 @@ enum Commands {
         force: bool,
     },
     
+    /// Fetch the full history of a shallow plugin clone
+    Unshallow {
+        /// Plugin name
+        plugin: String,
+    },
+    
     /// Install zsh-mgr for the first time
     Install {
         /// Plugin directory
@@ -118,6 +186,39 @@ enum Commands {
         /// Path to plugins file (default: ~/.config/zsh/default-plugins.txt)
         #[arg(short, long)]
         file: Option<String>,
+        
+        /// Parallel clone jobs (default: 8)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+    
+    /// Export the plugin set as a portable manifest (to stdout by default)
+    Export {
+        /// Write the manifest to this file
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    
+    /// Install the plugins of a manifest created by export, keeping existing ones
+    Import {
+        /// Manifest file ("-" for stdin)
+        file: String,
+        
+        /// Parallel clone jobs (default: 8)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+    
+    /// Write every installed plugin as a git bundle, plus plugins.json, into one archive
+    ExportBundle {
+        /// Archive to create (tar)
+        output: String,
+    },
+    
+    /// Install plugins offline from an archive created by export-bundle
+    ImportBundle {
+        /// Archive created by export-bundle
+        archive: String,
     },
 }
 
@@ -127,11 +228,21 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
     
     match cli.command {
-        Commands::Add { plugin, flags, private } => {
-            add::run(plugin, flags, private)
+        Commands::Add { plugin, local: true, .. } => {
+            add::run_local(plugin)
+        }
+        Commands::Add { plugin, snippet: true, .. } => {
+            add::run_snippet(plugin)
+        }
+        Commands::Add { plugin, release: true, asset, bin, .. } => {
+            add::run_release(plugin, asset, bin)
+        }
+        Commands::Add { plugin, flags, private, https, identity, prune, tags, refspec, sparse, path, build, .. } => {
+            let fetch = FetchOverrides { prune: prune.then_some(true), tags, refspecs: refspec };
+            add::run(plugin, flags, private, https, identity, fetch, sparse, path, build)
         }
-        Commands::Update { only, verbose, jobs } => {
-            update::run(only, verbose, jobs)
+        Commands::Update { only, verbose, jobs, review, events } => {
+            update::run(only, verbose, jobs, review, events)
         }
         Commands::Check { plugins, manager, json } => {
             check::run(plugins, manager, json)
@@ -142,6 +253,9 @@ fn main() -> Result<()> {
         Commands::Remove { plugin, force } => {
             remove::run(plugin, force)
         }
+        Commands::Unshallow { plugin } => {
+            unshallow::run(plugin)
+        }
         Commands::Install { plugin_dir, time_threshold, mgr_time_threshold, quiet } => {
             install::run(plugin_dir, time_threshold, mgr_time_threshold, quiet)
         }
@@ -151,8 +265,20 @@ fn main() -> Result<()> {
         Commands::Sync { force } => {
             sync::run(force)
         }
-        Commands::Bootstrap { file } => {
-            bootstrap::run(file)
+        Commands::Bootstrap { file, jobs } => {
+            bootstrap::run(file, jobs)
+        }
+        Commands::Export { output } => {
+            export::run(output)
+        }
+        Commands::Import { file, jobs } => {
+            import::run(file, jobs)
+        }
+        Commands::ExportBundle { output } => {
+            export_bundle::run(output)
+        }
+        Commands::ImportBundle { archive } => {
+            import_bundle::run(archive)
         }
     }
 }
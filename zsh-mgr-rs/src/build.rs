@@ -0,0 +1,233 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::engine::CancelToken;
+
+/// Seconds a build command may run when `config.json` sets no `build_timeout`
+pub const DEFAULT_BUILD_TIMEOUT: u64 = 300;
+
+/// Output kept per build; anything before the last this many bytes is dropped
+const MAX_OUTPUT: usize = 64 * 1024;
+
+/// Outcome of running the build commands of a plugin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildResult {
+    pub plugin: String,
+    pub success: bool,
+    /// Combined stdout and stderr of the commands that ran
+    pub output: String,
+    /// Why the build failed: the command that failed and its exit status or timeout
+    pub error: Option<String>,
+    #[serde(with = "crate::engine::duration_secs")]
+    pub duration: Duration,
+}
+
+/// Run `commands` one after the other with `sh -c` in `dir`, stopping at the first one that
+/// fails. A command still running after `timeout`, or when `cancel` is set, is killed along with
+/// everything it started.
+pub fn run(plugin: &str, dir: &Path, commands: &[String], timeout: Duration, cancel: &CancelToken) -> BuildResult {
+    let start = Instant::now();
+    let mut output = Vec::new();
+    let mut error = None;
+
+    for command in commands {
+        if let Err(e) = run_command(command, dir, timeout, cancel, &mut output) {
+            error = Some(e);
+            break;
+        }
+    }
+
+    if output.len() > MAX_OUTPUT {
+        output.drain(..output.len() - MAX_OUTPUT);
+    }
+    BuildResult {
+        plugin: plugin.to_string(),
+        success: error.is_none(),
+        output: String::from_utf8_lossy(&output).into_owned(),
+        error,
+        duration: start.elapsed(),
+    }
+}
+
+fn run_command(
+    command: &str,
+    dir: &Path,
+    timeout: Duration,
+    cancel: &CancelToken,
+    output: &mut Vec<u8>,
+) -> Result<(), String> {
+    let (mut reader, writer) = std::io::pipe().map_err(|e| format!("Failed to run `{}`: {}", command, e))?;
+    let stderr = writer.try_clone().map_err(|e| format!("Failed to run `{}`: {}", command, e))?;
+
+    // In a process group of its own, so a timeout kills whatever the command started too
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(writer)
+        .stderr(stderr)
+        .process_group(0)
+        .spawn()
+        .map_err(|e| format!("Failed to run `{}`: {}", command, e))?;
+
+    // The pipe is read on its own thread so a chatty command never blocks on a full pipe
+    let (sender, collected) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = reader.read_to_end(&mut buffer);
+        let _ = sender.send(buffer);
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if Instant::now() >= deadline || cancel.is_cancelled() => break None,
+            Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+            Err(e) => return Err(format!("Failed to wait for `{}`: {}", command, e)),
+        }
+    };
+    if status.is_none() {
+        kill_group(&mut child);
+    }
+
+    // The pipe closes once nothing the command started is left; anything it left running in
+    // the background is killed when the time is up
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let buffer = collected.recv_timeout(remaining).unwrap_or_else(|_| {
+        kill_group(&mut child);
+        collected.recv().unwrap_or_default()
+    });
+    output.extend(buffer);
+
+    match status {
+        None if cancel.is_cancelled() => Err(format!("`{}` was interrupted", command)),
+        None => Err(format!("`{}` timed out after {}s", command, timeout.as_secs())),
+        Some(status) if status.success() => Ok(()),
+        Some(status) => match status.code() {
+            Some(code) => Err(format!("`{}` exited with status {}", command, code)),
+            None => Err(format!("`{}` was killed by a signal", command)),
+        },
+    }
+}
+
+/// Kill the process group of `child`, i.e. the command and everything it started
+fn kill_group(child: &mut Child) {
+    // The command was started with `process_group(0)`, so its pid is the group id
+    // SAFETY: killpg only sends a signal and has no memory safety requirements
+    if unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) } != 0 {
+        let error = io::Error::last_os_error();
+        // The whole group may have exited in the meantime
+        if error.raw_os_error() != Some(libc::ESRCH) {
+            warn!("Failed to kill the process group {}: {}", child.id(), error);
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn commands(commands: &[&str]) -> Vec<String> {
+        commands.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn commands_run_in_order_in_the_plugin_directory() {
+        let tmp = TempDir::new("build");
+        let result = run(
+            "u/repo",
+            tmp.path(),
+            &commands(&["echo one > order", "echo two >> order", "cat order; echo oops >&2"]),
+            Duration::from_secs(30),
+            &CancelToken::new(),
+        );
+
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.plugin, "u/repo");
+        assert_eq!(result.output, "one\ntwo\noops\n");
+    }
+
+    #[test]
+    fn the_first_failing_command_stops_the_build() {
+        let tmp = TempDir::new("build");
+        let result = run(
+            "u/repo",
+            tmp.path(),
+            &commands(&["echo before", "exit 2", "touch after"]),
+            Duration::from_secs(30),
+            &CancelToken::new(),
+        );
+
+        assert!(!result.success);
+        assert_eq!(result.error.as_deref(), Some("`exit 2` exited with status 2"));
+        assert_eq!(result.output, "before\n");
+        assert!(!tmp.path().join("after").exists());
+    }
+
+    #[test]
+    fn timeouts_kill_everything_the_command_started() {
+        let tmp = TempDir::new("build");
+        let result = run(
+            "u/repo",
+            tmp.path(),
+            &commands(&["sleep 30 & echo $! > pid; wait"]),
+            Duration::from_millis(500),
+            &CancelToken::new(),
+        );
+
+        assert_eq!(result.error.as_deref(), Some("`sleep 30 & echo $! > pid; wait` timed out after 0s"));
+        assert!(result.duration < Duration::from_secs(10));
+        let pid = std::fs::read_to_string(tmp.path().join("pid")).unwrap();
+        let stat = Path::new("/proc").join(pid.trim()).join("stat");
+        // Gone, or a zombie nobody reaped yet
+        let dead = || std::fs::read_to_string(&stat).map_or(true, |stat| stat.contains(") Z "));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !dead() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(dead(), "the background sleep survived the timeout");
+    }
+
+    #[test]
+    fn cancelling_stops_the_running_command() {
+        let tmp = TempDir::new("build");
+        let cancel = CancelToken::new();
+        let canceller = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            canceller.cancel();
+        });
+        let result = run("u/repo", tmp.path(), &commands(&["sleep 30", "touch after"]), Duration::from_secs(60), &cancel);
+
+        assert_eq!(result.error.as_deref(), Some("`sleep 30` was interrupted"));
+        assert!(result.duration < Duration::from_secs(10));
+        assert!(!tmp.path().join("after").exists());
+    }
+
+    #[test]
+    fn only_the_end_of_long_output_is_kept() {
+        let tmp = TempDir::new("build");
+        let result = run(
+            "u/repo",
+            tmp.path(),
+            &commands(&["head -c 100000 /dev/zero | tr '\\0' x; echo end"]),
+            Duration::from_secs(30),
+            &CancelToken::new(),
+        );
+
+        assert!(result.success);
+        assert_eq!(result.output.len(), MAX_OUTPUT);
+        assert!(result.output.ends_with("xend\n"));
+    }
+}
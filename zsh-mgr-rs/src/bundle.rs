@@ -0,0 +1,330 @@
+use git2::{Error, Indexer, ObjectType, Oid, Repository};
+use log::debug;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// First line of a version 2 `git bundle` file
+const BUNDLE_SIGNATURE: &str = "# v2 git bundle";
+
+/// Plugin metadata inside an `export-bundle` archive
+pub const ARCHIVE_PLUGIN_LIST: &str = "plugins.json";
+
+/// Location of a plugin's bundle inside an `export-bundle` archive
+pub fn archive_bundle_path(plugin_name: &str) -> PathBuf {
+    Path::new("bundles").join(format!("{}.bundle", plugin_name))
+}
+
+/// Location of the file of a snippet plugin inside an `export-bundle` archive
+pub fn archive_snippet_path(plugin_name: &str) -> PathBuf {
+    Path::new("snippets").join(format!("{}.zsh", plugin_name))
+}
+
+/// Location of the extracted release of a release plugin inside an `export-bundle` archive
+pub fn archive_release_path(plugin_name: &str) -> PathBuf {
+    Path::new("releases").join(plugin_name)
+}
+
+/// References and prerequisites listed in a bundle header
+#[derive(Debug, Clone, Default)]
+pub struct BundleHeader {
+    /// Commits the bundle depends on but does not contain (parents of a shallow history)
+    pub prerequisites: Vec<Oid>,
+    /// `(refname, target)` pairs, including `HEAD` when present
+    pub references: Vec<(String, Oid)>,
+}
+
+impl BundleHeader {
+    /// Commit HEAD pointed to when the bundle was written
+    pub fn head(&self) -> Option<Oid> {
+        self.references
+            .iter()
+            .find(|(name, _)| name == "HEAD")
+            .map(|(_, oid)| *oid)
+    }
+
+    /// Branch HEAD pointed to, preferring `preferred` when several branches share its commit
+    pub fn head_branch(&self, preferred: Option<&str>) -> Option<String> {
+        let head = self.head()?;
+        let branches: Vec<_> = self
+            .references
+            .iter()
+            .filter(|(_, oid)| *oid == head)
+            .filter_map(|(name, _)| name.strip_prefix("refs/heads/"))
+            .collect();
+
+        branches
+            .iter()
+            .find(|b| Some(**b) == preferred)
+            .or_else(|| branches.first())
+            .map(|b| b.to_string())
+    }
+}
+
+/// Write every local branch and tag of `repo`, plus HEAD, as a `git bundle` (format v2).
+///
+/// libgit2 cannot create bundles itself, so the header is written by hand and followed by a
+/// pack built with `PackBuilder`. The result can be read by `git fetch`/`git clone` as well.
+pub fn write_bundle<W: Write>(repo: &Repository, out: &mut W) -> Result<(), Error> {
+    let io_err = |e: std::io::Error| Error::from_str(&e.to_string());
+
+    let mut references = Vec::new();
+    if let Ok(head) = repo.head() {
+        if let Some(oid) = head.target() {
+            references.push(("HEAD".to_string(), oid));
+        }
+    }
+    for reference in repo.references()? {
+        let reference = reference?;
+        let (Some(name), Some(target)) = (reference.name(), reference.target()) else {
+            continue;
+        };
+        if name.starts_with("refs/heads/") || name.starts_with("refs/tags/") {
+            references.push((name.to_string(), target));
+        }
+    }
+
+    let mut walk = repo.revwalk()?;
+    let mut tags = Vec::new();
+    for (_, oid) in &references {
+        let object = repo.find_object(*oid, None)?;
+        if object.kind() == Some(ObjectType::Tag) {
+            tags.push(*oid);
+        }
+        walk.push(object.peel_to_commit()?.id())?;
+    }
+
+    let mut builder = repo.packbuilder()?;
+    builder.insert_walk(&mut walk)?;
+    for tag in &tags {
+        builder.insert_object(*tag, None)?;
+    }
+
+    writeln!(out, "{}", BUNDLE_SIGNATURE).map_err(io_err)?;
+    for oid in shallow_parents(repo)? {
+        writeln!(out, "-{}", oid).map_err(io_err)?;
+    }
+    for (name, oid) in &references {
+        writeln!(out, "{} {}", oid, name).map_err(io_err)?;
+    }
+    writeln!(out).map_err(io_err)?;
+
+    // The pack is passed on as it is built instead of being held in memory
+    let mut written = Ok(());
+    builder.foreach(|chunk| {
+        written = out.write_all(chunk);
+        written.is_ok()
+    })?;
+    written.map_err(io_err)?;
+
+    debug!(
+        "Bundled {} ({} objects, {} references)",
+        repo.path().display(),
+        builder.object_count(),
+        references.len()
+    );
+    Ok(())
+}
+
+/// Parents of the shallow boundary commits, which a bundle of a shallow clone lacks
+fn shallow_parents(repo: &Repository) -> Result<Vec<Oid>, Error> {
+    if !repo.is_shallow() {
+        return Ok(Vec::new());
+    }
+
+    // libgit2 hides the parents of shallow commits, so read them from the raw objects
+    let odb = repo.odb()?;
+    let shallow = fs::read_to_string(repo.path().join("shallow")).unwrap_or_default();
+    let mut parents = Vec::new();
+    for line in shallow.lines().filter(|l| !l.trim().is_empty()) {
+        let object = odb.read(Oid::from_str(line.trim())?)?;
+        parents.extend(raw_parents(object.data())?);
+    }
+    Ok(parents)
+}
+
+/// Parent ids listed in the header of a raw commit object
+fn raw_parents(data: &[u8]) -> Result<Vec<Oid>, Error> {
+    let text = String::from_utf8_lossy(data);
+    text.lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.strip_prefix("parent "))
+        .map(Oid::from_str)
+        .collect()
+}
+
+fn parse_header<R: BufRead>(mut reader: R) -> Result<(BundleHeader, R), Error> {
+    let mut line = String::new();
+    let read_line = |reader: &mut R, line: &mut String| {
+        line.clear();
+        reader
+            .read_line(line)
+            .map_err(|e| Error::from_str(&e.to_string()))
+    };
+
+    read_line(&mut reader, &mut line)?;
+    if line.trim_end() != BUNDLE_SIGNATURE {
+        return Err(Error::from_str("Not a v2 git bundle"));
+    }
+
+    let mut header = BundleHeader::default();
+    loop {
+        if read_line(&mut reader, &mut line)? == 0 {
+            return Err(Error::from_str("Truncated bundle header"));
+        }
+        let entry = line.trim_end();
+        if entry.is_empty() {
+            break;
+        }
+        if let Some(prerequisite) = entry.strip_prefix('-') {
+            let oid = prerequisite.split_whitespace().next().unwrap_or_default();
+            header.prerequisites.push(Oid::from_str(oid)?);
+        } else {
+            let (oid, name) = entry
+                .split_once(' ')
+                .ok_or_else(|| Error::from_str(&format!("Invalid bundle reference '{}'", entry)))?;
+            header.references.push((name.to_string(), Oid::from_str(oid)?));
+        }
+    }
+
+    Ok((header, reader))
+}
+
+/// Index the pack of the bundle at `path` into `repo` and return its header.
+/// References are left to the caller. If the bundle comes from a shallow clone,
+/// `repo` is marked shallow at the same boundary.
+pub fn unbundle(repo: &Repository, path: &Path) -> Result<BundleHeader, Error> {
+    let io_err = |e: std::io::Error| Error::from_str(&e.to_string());
+    let file = fs::File::open(path).map_err(io_err)?;
+    let (header, mut reader) = parse_header(BufReader::new(file))?;
+
+    debug!("Unbundling {} into {}", path.display(), repo.path().display());
+    let odb = repo.odb()?;
+    // The indexer checks that every object the pack refers to is there, which a bundle of a
+    // shallow clone cannot satisfy
+    let mut indexer = Indexer::new(
+        Some(&odb),
+        &repo.path().join("objects").join("pack"),
+        0,
+        header.prerequisites.is_empty(),
+    )?;
+    std::io::copy(&mut reader, &mut indexer).map_err(io_err)?;
+    indexer.commit()?;
+
+    if !header.prerequisites.is_empty() {
+        let boundary = shallow_boundary(repo, &header)?;
+        let shallow: String = boundary.iter().map(|oid| format!("{}\n", oid)).collect();
+        fs::write(repo.path().join("shallow"), shallow).map_err(io_err)?;
+    }
+
+    Ok(header)
+}
+
+/// Commits of a bundle whose parents are missing prerequisites, i.e. the shallow boundary of
+/// the clone it was written from. Only the history between the references and the
+/// prerequisites is walked.
+fn shallow_boundary(repo: &Repository, header: &BundleHeader) -> Result<Vec<Oid>, Error> {
+    let odb = repo.odb()?;
+    let prerequisites: HashSet<_> = header.prerequisites.iter().copied().collect();
+    let mut pending = Vec::new();
+    for (_, oid) in &header.references {
+        pending.push(repo.find_object(*oid, None)?.peel_to_commit()?.id());
+    }
+
+    let mut seen = HashSet::new();
+    let mut boundary = Vec::new();
+    while let Some(oid) = pending.pop() {
+        if !seen.insert(oid) {
+            continue;
+        }
+        let parents = raw_parents(odb.read(oid)?.data())?;
+        if parents.iter().any(|p| prerequisites.contains(p) && !odb.exists(*p)) {
+            boundary.push(oid);
+        }
+        pending.extend(parents.into_iter().filter(|p| !prerequisites.contains(p)));
+    }
+    Ok(boundary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{commit_file, init_repo, TempDir};
+    use std::io::Cursor;
+
+    const A: &str = "1111111111111111111111111111111111111111";
+    const B: &str = "2222222222222222222222222222222222222222";
+    const C: &str = "3333333333333333333333333333333333333333";
+
+    fn parse(text: &str) -> Result<BundleHeader, Error> {
+        parse_header(Cursor::new(text.as_bytes())).map(|(header, _)| header)
+    }
+
+    #[test]
+    fn parse_header_reads_prerequisites_and_references() {
+        let text = format!(
+            "{}\n-{} shallow parent\n{} HEAD\n{} refs/heads/main\n{} refs/tags/v1\n\nPACK",
+            BUNDLE_SIGNATURE, C, A, A, B
+        );
+        let (header, mut rest) = parse_header(Cursor::new(text.as_bytes())).unwrap();
+        assert_eq!(header.prerequisites, [Oid::from_str(C).unwrap()]);
+        assert_eq!(header.references.len(), 3);
+        assert_eq!(header.head(), Some(Oid::from_str(A).unwrap()));
+
+        let mut pack = String::new();
+        rest.read_line(&mut pack).unwrap();
+        assert_eq!(pack, "PACK");
+    }
+
+    #[test]
+    fn parse_header_rejects_other_formats() {
+        assert!(parse("# v3 git bundle\n\n").is_err());
+        assert!(parse(&format!("{}\n{} HEAD\n", BUNDLE_SIGNATURE, A)).is_err());
+        assert!(parse(&format!("{}\n{}\n\n", BUNDLE_SIGNATURE, A)).is_err());
+        assert!(parse(&format!("{}\nnot-an-oid HEAD\n\n", BUNDLE_SIGNATURE)).is_err());
+    }
+
+    #[test]
+    fn head_branch_prefers_the_given_branch() {
+        let header = parse(&format!(
+            "{}\n{} HEAD\n{} refs/heads/main\n{} refs/heads/dev\n{} refs/heads/old\n\n",
+            BUNDLE_SIGNATURE, A, A, A, B
+        ))
+        .unwrap();
+        assert_eq!(header.head_branch(None).as_deref(), Some("main"));
+        assert_eq!(header.head_branch(Some("dev")).as_deref(), Some("dev"));
+        assert_eq!(header.head_branch(Some("old")).as_deref(), Some("main"));
+        assert_eq!(BundleHeader::default().head_branch(None), None);
+    }
+
+    #[test]
+    fn raw_parents_reads_the_commit_header_only() {
+        let data = format!("tree {}\nparent {}\nparent {}\nauthor x\n\nparent {}\n", A, B, C, A);
+        let parents = raw_parents(data.as_bytes()).unwrap();
+        assert_eq!(parents, [Oid::from_str(B).unwrap(), Oid::from_str(C).unwrap()]);
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let tmp = TempDir::new("bundle");
+        let source = init_repo(&tmp.path().join("source"));
+        let head = commit_file(&source, "a.zsh", "echo a\n", "Add a");
+        source.tag_lightweight("v1", &source.find_object(head, None).unwrap(), false).unwrap();
+
+        let path = tmp.path().join("plugin.bundle");
+        let mut out = Vec::new();
+        write_bundle(&source, &mut out).unwrap();
+        fs::write(&path, out).unwrap();
+
+        let target = Repository::init_bare(tmp.path().join("target")).unwrap();
+        let header = unbundle(&target, &path).unwrap();
+
+        assert!(header.prerequisites.is_empty());
+        assert_eq!(header.head(), Some(head));
+        assert_eq!(header.head_branch(None).as_deref(), Some("main"));
+        assert!(header.references.contains(&("refs/tags/v1".to_string(), head)));
+        assert!(target.find_commit(head).unwrap().parent(0).is_ok());
+        assert!(!target.is_shallow());
+    }
+}
@@ -0,0 +1,772 @@
+use anyhow::{bail, Context, Result};
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{Direction, Error, FetchOptions, Oid, Repository, SubmoduleUpdateOptions};
+use log::{debug, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::bundle;
+use crate::credentials_manager::{CredentialManager, IDENTITY_FILE_KEY};
+use crate::fetch_options::{FetchOverrides, FetchSettings, TagMode};
+use crate::git_cli;
+use crate::object_cache::ObjectCache;
+
+/// Structured representation of the `git clone` flags supported by zsh-mgr
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CloneOptions {
+    /// `--depth <n>`: shallow clone with the given history depth
+    pub depth: Option<u32>,
+    /// `--branch <name>` / `-b <name>`: branch to check out instead of the remote HEAD
+    pub branch: Option<String>,
+    /// `--single-branch`: only fetch the checked out branch
+    pub single_branch: bool,
+    /// `--recursive` / `--recurse-submodules`: initialize and clone submodules
+    pub recursive: bool,
+    /// `--filter <spec>`: partial clone leaving out file contents (`blob:none`, `blob:limit=<n>`)
+    pub filter: Option<String>,
+    /// Directories to check out instead of the whole tree; not a git flag, set by the
+    /// installer from the plugin's `sparse` list
+    pub sparse: Vec<String>,
+    /// SSH keys to try for this repository; not a git flag, set by the installer and
+    /// stored as `zsh-mgr.identityFile` in the clone so updates use them as well
+    pub identity_files: Vec<PathBuf>,
+    /// Prune, tag and refspec settings of this plugin; set by the installer and stored in
+    /// the clone as `zsh-mgr.*` keys
+    pub fetch: FetchOverrides,
+}
+
+impl CloneOptions {
+    /// Parse a flags string as stored in `PluginInfo::flags` (e.g. `"--depth 1 -b dev"`).
+    /// Quoting follows shell rules, and unknown flags are rejected instead of ignored.
+    pub fn parse(flags: &str) -> Result<Self> {
+        let words = split_flags(flags)?;
+        let mut options = Self::default();
+        let mut single_branch: Option<bool> = None;
+        let mut words = words.into_iter();
+
+        while let Some(word) = words.next() {
+            let (flag, inline_value) = match word.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                _ => (word, None),
+            };
+
+            match flag.as_str() {
+                "--depth" => {
+                    let value = match inline_value {
+                        Some(v) => v,
+                        None => words.next().context("--depth requires a value")?,
+                    };
+                    let depth: u32 = value
+                        .parse()
+                        .with_context(|| format!("Invalid --depth value '{}'", value))?;
+                    if depth == 0 {
+                        bail!("--depth must be greater than zero");
+                    }
+                    options.depth = Some(depth);
+                }
+                "--branch" | "-b" => {
+                    let value = match inline_value {
+                        Some(v) => v,
+                        None => words.next().with_context(|| format!("{} requires a value", flag))?,
+                    };
+                    options.branch = Some(value);
+                }
+                "--single-branch" => single_branch = Some(true),
+                "--no-single-branch" => single_branch = Some(false),
+                "--recursive" | "--recurse-submodules" => options.recursive = true,
+                "--filter" => {
+                    let value = match inline_value {
+                        Some(v) => v,
+                        None => words.next().context("--filter requires a value")?,
+                    };
+                    if !git_cli::is_supported_filter(&value) {
+                        bail!("Unsupported --filter '{}' (supported: blob:none, blob:limit=<n>)", value);
+                    }
+                    options.filter = Some(value);
+                }
+                other => bail!(
+                    "Unsupported clone flag '{}' (supported: --depth, --branch, --single-branch, --recursive, --filter)",
+                    other
+                ),
+            }
+        }
+
+        // Like git, --depth implies --single-branch unless explicitly disabled
+        options.single_branch = single_branch.unwrap_or(options.depth.is_some());
+        Ok(options)
+    }
+
+    /// Partial and sparse clones are made with the `git` executable, see `git_cli`
+    pub fn uses_git(&self) -> bool {
+        self.filter.is_some() || !self.sparse.is_empty()
+    }
+}
+
+/// `flags` without `--depth`, e.g. after a clone was converted to a full one; `None` if
+/// nothing is left. Words with whitespace or quotes are quoted again.
+pub fn flags_without_depth(flags: &str) -> Result<Option<String>> {
+    let mut kept = Vec::new();
+    let mut words = split_flags(flags)?.into_iter();
+    while let Some(word) = words.next() {
+        if word == "--depth" {
+            words.next();
+        } else if !word.starts_with("--depth=") {
+            kept.push(word);
+        }
+    }
+
+    let quoted: Vec<String> = kept
+        .into_iter()
+        .map(|word| {
+            if word.is_empty() || word.contains(|c: char| c.is_whitespace() || "'\"\\".contains(c)) {
+                format!("'{}'", word.replace('\'', "'\\''"))
+            } else {
+                word
+            }
+        })
+        .collect();
+    Ok((!quoted.is_empty()).then(|| quoted.join(" ")))
+}
+
+/// Depth of a shallow clone: the number of commits from HEAD down to the shallow boundary,
+/// following first parents. `None` for a full clone.
+pub fn shallow_depth(repo: &Repository) -> Option<u32> {
+    if !repo.is_shallow() {
+        return None;
+    }
+    let boundary: HashSet<Oid> = fs::read_to_string(repo.path().join("shallow"))
+        .ok()?
+        .lines()
+        .filter_map(|line| Oid::from_str(line.trim()).ok())
+        .collect();
+
+    let mut commit = repo.head().ok()?.peel_to_commit().ok()?;
+    let mut depth = 1;
+    while !boundary.contains(&commit.id()) {
+        commit = commit.parent(0).ok()?;
+        depth += 1;
+    }
+    Some(depth)
+}
+
+/// Split a flags string into words, honouring single quotes, double quotes and backslashes
+fn split_flags(flags: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = flags.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                in_word = true;
+            }
+            (Some(_), c) => current.push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        bail!("Unterminated quote in flags: {}", flags);
+    }
+    if in_word {
+        words.push(current);
+    }
+    Ok(words)
+}
+
+/// Progress reported while cloning
+#[derive(Debug, Clone, Copy)]
+pub enum CloneProgress {
+    Receiving {
+        received_objects: usize,
+        total_objects: usize,
+        received_bytes: usize,
+    },
+    Resolving {
+        indexed_deltas: usize,
+        total_deltas: usize,
+    },
+    CheckingOut {
+        completed: usize,
+        total: usize,
+    },
+}
+
+impl CloneProgress {
+    /// Progress to report for libgit2's transfer stats, if objects or deltas are being processed
+    pub fn from_transfer(stats: &git2::Progress) -> Option<Self> {
+        if stats.total_objects() > 0 && stats.received_objects() < stats.total_objects() {
+            Some(CloneProgress::Receiving {
+                received_objects: stats.received_objects(),
+                total_objects: stats.total_objects(),
+                received_bytes: stats.received_bytes(),
+            })
+        } else if stats.total_deltas() > 0 {
+            Some(CloneProgress::Resolving {
+                indexed_deltas: stats.indexed_deltas(),
+                total_deltas: stats.total_deltas(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Clones repositories through libgit2, sharing the credential logic used by updates
+pub struct RepoCloner {
+    credentials: Arc<CredentialManager>,
+    object_cache: Option<Arc<ObjectCache>>,
+    /// Fetch settings from `config.json`, before each plugin's own overrides
+    fetch_settings: FetchSettings,
+}
+
+impl RepoCloner {
+    pub fn new(credentials: Arc<CredentialManager>) -> Self {
+        Self {
+            credentials,
+            object_cache: None,
+            fetch_settings: FetchSettings::default(),
+        }
+    }
+
+    /// Borrow objects from a shared object cache instead of downloading them for every clone
+    pub fn with_object_cache(mut self, object_cache: Option<Arc<ObjectCache>>) -> Self {
+        self.object_cache = object_cache;
+        self
+    }
+
+    /// Tags and refspecs to clone with, unless a plugin overrides them
+    pub fn with_fetch_settings(mut self, fetch_settings: FetchSettings) -> Self {
+        self.fetch_settings = fetch_settings;
+        self
+    }
+
+    /// Clone `url` into `dest`, reporting transfer and checkout progress through `progress`
+    pub fn clone_repo(
+        &self,
+        url: &str,
+        dest: &Path,
+        options: &CloneOptions,
+        progress: &(dyn Fn(CloneProgress) + Sync),
+    ) -> Result<Repository, Error> {
+        let settings = self.fetch_settings.merged(&options.fetch);
+        if options.uses_git() {
+            return self.clone_with_git(url, dest, options, &settings);
+        }
+        // The cache holds every branch and tag with their full history, so other choices and
+        // shallow clones need a direct clone
+        let use_cache = options.depth.is_none() && settings.tags == TagMode::All && settings.refspecs.is_empty();
+        if let Some(cache) = self.object_cache.as_ref().filter(|_| use_cache) {
+            return self.clone_from_cache(cache, url, dest, options, progress);
+        }
+
+        let mut callbacks = self.credentials.create_clone_callbacks(&options.identity_files);
+        callbacks.transfer_progress(|stats| {
+            if let Some(update) = CloneProgress::from_transfer(&stats) {
+                progress(update);
+            }
+            true
+        });
+
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(callbacks);
+        settings.apply(&mut fetch_options);
+        if let Some(depth) = options.depth {
+            fetch_options.depth(depth as i32);
+        }
+
+        let mut checkout = CheckoutBuilder::new();
+        checkout.progress(|_path, completed, total| {
+            progress(CloneProgress::CheckingOut { completed, total });
+        });
+
+        let mut builder = RepoBuilder::new();
+        builder.fetch_options(fetch_options).with_checkout(checkout);
+
+        if let Some(ref branch) = options.branch {
+            builder.branch(branch);
+        }
+
+        if !settings.refspecs.is_empty() {
+            // Custom refspecs replace both the default and the single-branch one
+            let refspecs = settings.refspecs.clone();
+            builder.remote_create(move |repo, name, url| {
+                repo.remote_with_fetch(name, url, &refspecs[0])?;
+                for refspec in &refspecs[1..] {
+                    repo.remote_add_fetch(name, refspec)?;
+                }
+                repo.find_remote(name)
+            });
+        } else if options.single_branch {
+            let branch = options.branch.clone();
+            let credentials = Arc::clone(&self.credentials);
+            let identities = options.identity_files.clone();
+            builder.remote_create(move |repo, name, url| {
+                // Without an explicit branch, ask the remote which branch its HEAD points to
+                let branch = match branch {
+                    Some(ref b) => b.clone(),
+                    None => Self::remote_default_branch(&credentials, &identities, repo, url)?,
+                };
+                let refspec = format!("+refs/heads/{0}:refs/remotes/{1}/{0}", branch, name);
+                repo.remote_with_fetch(name, url, &refspec)
+            });
+        }
+
+        // Connect to the host from ~/.ssh/config, but keep the URL as given for origin
+        let connect_url = self.credentials.resolve_url(url);
+        debug!("Cloning {} into {}", connect_url, dest.display());
+        let repo = builder.clone(&connect_url, dest)?;
+        if connect_url != url {
+            repo.remote_set_url("origin", url)?;
+        }
+        Self::store_identities(&repo, &options.identity_files)?;
+        options.fetch.store(&repo)?;
+
+        if options.recursive {
+            self.update_submodules(&repo)?;
+        }
+
+        Ok(repo)
+    }
+
+    /// Partial or sparse clone with the `git` executable. There is no progress to report,
+    /// and the object cache is not used since it would hold every file.
+    fn clone_with_git(
+        &self,
+        url: &str,
+        dest: &Path,
+        options: &CloneOptions,
+        settings: &FetchSettings,
+    ) -> Result<Repository, Error> {
+        let mut args = vec!["clone".to_string(), "--quiet".to_string()];
+        if let Some(ref filter) = options.filter {
+            args.push(format!("--filter={}", filter));
+        }
+        if !options.sparse.is_empty() {
+            args.push("--sparse".to_string());
+        }
+        if let Some(depth) = options.depth {
+            args.push(format!("--depth={}", depth));
+        }
+        if let Some(ref branch) = options.branch {
+            args.push(format!("--branch={}", branch));
+        }
+        let single_branch = if options.single_branch { "--single-branch" } else { "--no-single-branch" };
+        args.push(single_branch.to_string());
+        if settings.tags == TagMode::None {
+            args.push("--no-tags".to_string());
+        }
+        if options.recursive {
+            args.push("--recurse-submodules".to_string());
+        }
+        args.extend(["--".to_string(), url.to_string(), dest.to_string_lossy().into_owned()]);
+
+        debug!("Cloning {} into {} with git", url, dest.display());
+        let env = self.credentials.git_env(url, &options.identity_files);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        git_cli::run(None, &args, &env)?;
+        if !options.sparse.is_empty() {
+            // Checking out the directories downloads their files in a partial clone
+            Self::set_sparse(dest, &options.sparse, &env)?;
+        }
+
+        let repo = Repository::open(dest)?;
+        if !settings.refspecs.is_empty() {
+            let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+            config.remove_multivar("remote.origin.fetch", ".*")?;
+            for refspec in &settings.refspecs {
+                repo.remote_add_fetch("origin", refspec)?;
+            }
+        }
+        Self::store_identities(&repo, &options.identity_files)?;
+        options.fetch.store(&repo)?;
+        Ok(repo)
+    }
+
+    /// Restrict the checkout of `repo_path` to `directories`
+    fn set_sparse(repo_path: &Path, directories: &[String], env: &[(String, String)]) -> Result<(), Error> {
+        let mut args = vec!["sparse-checkout", "set", "--"];
+        args.extend(directories.iter().map(String::as_str));
+        git_cli::run(Some(repo_path), &args, env).map(|_| ())
+    }
+
+    /// Add `directory` to the sparse checkout of `repo_path`, downloading its files if needed
+    pub fn add_sparse(&self, repo_path: &Path, url: &str, identities: &[PathBuf], directory: &str) -> Result<(), Error> {
+        let env = self.credentials.git_env(url, identities);
+        git_cli::run(Some(repo_path), &["sparse-checkout", "add", "--", directory], &env).map(|_| ())
+    }
+
+    /// Move the checked out branch of a new clone to `rev`, e.g. a pinned commit. Clones made
+    /// with git are reset with git, so missing files are downloaded and the sparse checkout kept.
+    pub fn reset_to(&self, repo: &Repository, url: &str, options: &CloneOptions, rev: &str) -> Result<(), Error> {
+        let commit = repo.revparse_single(rev)?.peel_to_commit()?;
+        if !options.uses_git() {
+            return repo.reset(commit.as_object(), git2::ResetType::Hard, None);
+        }
+        let workdir = repo.workdir().ok_or_else(|| Error::from_str("Repository has no working tree"))?;
+        let env = self.credentials.git_env(url, &options.identity_files);
+        git_cli::run(Some(workdir), &["reset", "--hard", "--quiet", &commit.id().to_string()], &env).map(|_| ())
+    }
+
+    /// Clone by fetching into the object cache and creating a working copy that borrows
+    /// the cached objects, so nothing is downloaded twice.
+    fn clone_from_cache(
+        &self,
+        cache: &ObjectCache,
+        url: &str,
+        dest: &Path,
+        options: &CloneOptions,
+        progress: &(dyn Fn(CloneProgress) + Sync),
+    ) -> Result<Repository, Error> {
+        let cache_repo = cache.fetch(url, &options.identity_files, progress)?;
+
+        debug!("Cloning {} into {} from object cache", url, dest.display());
+        let repo = Repository::init(dest)?;
+        Self::store_identities(&repo, &options.identity_files)?;
+        options.fetch.store(&repo)?;
+        cache.link(&repo, &cache_repo)?;
+
+        let branch = match options.branch {
+            Some(ref branch) => branch.clone(),
+            None => ObjectCache::default_branch(&cache_repo)?,
+        };
+
+        if options.single_branch {
+            let refspec = format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch);
+            repo.remote_with_fetch("origin", url, &refspec)?;
+            ObjectCache::copy_refs(&repo, &cache_repo, "origin", Some(&branch))?;
+        } else {
+            repo.remote("origin", url)?;
+            ObjectCache::copy_refs(&repo, &cache_repo, "origin", None)?;
+        }
+
+        Self::checkout_tracking_branch(&repo, &branch, progress)?;
+
+        if options.recursive {
+            self.update_submodules(&repo)?;
+        }
+
+        Ok(repo)
+    }
+
+    /// Clone from a `git bundle` file without any network access. `origin` still points at
+    /// `url`, so later updates fetch from the real remote.
+    /// Submodules are not part of bundles and are left uninitialized.
+    pub fn clone_from_bundle(
+        &self,
+        bundle_path: &Path,
+        url: &str,
+        dest: &Path,
+        options: &CloneOptions,
+        progress: &(dyn Fn(CloneProgress) + Sync),
+    ) -> Result<Repository, Error> {
+        debug!("Cloning {} into {} from bundle {}", url, dest.display(), bundle_path.display());
+        let repo = Repository::init(dest)?;
+        let header = bundle::unbundle(&repo, bundle_path)?;
+        Self::store_identities(&repo, &options.identity_files)?;
+        options.fetch.store(&repo)?;
+
+        let branch = match options.branch {
+            Some(ref branch) => branch.clone(),
+            None => header
+                .head_branch(None)
+                .ok_or_else(|| Error::from_str("Bundle has no branch matching its HEAD"))?,
+        };
+
+        if options.single_branch {
+            let refspec = format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch);
+            repo.remote_with_fetch("origin", url, &refspec)?;
+        } else {
+            repo.remote("origin", url)?;
+        }
+
+        for (name, oid) in &header.references {
+            if let Some(bundled_branch) = name.strip_prefix("refs/heads/") {
+                if options.single_branch && bundled_branch != branch {
+                    continue;
+                }
+                let tracking = format!("refs/remotes/origin/{}", bundled_branch);
+                repo.reference(&tracking, *oid, true, "clone: from bundle")?;
+            } else if name.starts_with("refs/tags/") {
+                repo.reference(name, *oid, true, "clone: tag from bundle")?;
+            }
+        }
+
+        Self::checkout_tracking_branch(&repo, &branch, progress)?;
+        if !options.sparse.is_empty() {
+            // Bundles hold every object, so only the checkout is narrowed
+            Self::set_sparse(dest, &options.sparse, &[])?;
+        }
+
+        if options.recursive {
+            warn!("Submodules of {} are not included in bundles and were not initialized", url);
+        }
+
+        Ok(repo)
+    }
+
+    /// Create `branch` from `origin/<branch>` with upstream tracking and check it out
+    fn checkout_tracking_branch(
+        repo: &Repository,
+        branch: &str,
+        progress: &(dyn Fn(CloneProgress) + Sync),
+    ) -> Result<(), Error> {
+        {
+            let tracking = format!("origin/{}", branch);
+            let target = repo
+                .find_branch(&tracking, git2::BranchType::Remote)
+                .map_err(|_| Error::from_str(&format!("Remote branch '{}' not found", branch)))?
+                .get()
+                .peel_to_commit()?;
+            let mut local = repo.branch(branch, &target, false)?;
+            local.set_upstream(Some(&tracking))?;
+        }
+
+        repo.set_head(&format!("refs/heads/{}", branch))?;
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force().progress(|_path, completed, total| {
+            progress(CloneProgress::CheckingOut { completed, total });
+        });
+        repo.checkout_head(Some(&mut checkout))
+    }
+
+    /// Remember the plugin's SSH keys in its git config for later fetches
+    fn store_identities(repo: &Repository, identities: &[PathBuf]) -> Result<(), Error> {
+        if identities.is_empty() {
+            return Ok(());
+        }
+        let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+        for identity in identities {
+            config.set_multivar(IDENTITY_FILE_KEY, "^$", &identity.to_string_lossy())?;
+        }
+        Ok(())
+    }
+
+    /// Ask the remote which branch its HEAD points to
+    fn remote_default_branch(
+        credentials: &CredentialManager,
+        identities: &[PathBuf],
+        repo: &Repository,
+        url: &str,
+    ) -> Result<String, Error> {
+        let mut remote = repo.remote_anonymous(url)?;
+        let connection = remote.connect_auth(
+            Direction::Fetch,
+            Some(credentials.create_clone_callbacks(identities)),
+            None,
+        )?;
+        let head = connection.default_branch()?;
+        let head = head
+            .as_str()
+            .ok_or_else(|| Error::from_str("Remote HEAD is not valid UTF-8"))?;
+        Ok(head.trim_start_matches("refs/heads/").to_string())
+    }
+
+    /// Initialize and clone every submodule, recursing into nested ones
+    fn update_submodules(&self, repo: &Repository) -> Result<(), Error> {
+        for mut submodule in repo.submodules()? {
+            debug!("Updating submodule {}", submodule.path().display());
+
+            let mut fetch_options = FetchOptions::new();
+            fetch_options.remote_callbacks(self.credentials.create_callbacks(repo));
+            let mut update_options = SubmoduleUpdateOptions::new();
+            update_options.fetch(fetch_options);
+
+            submodule.update(true, Some(&mut update_options))?;
+            self.update_submodules(&submodule.open()?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{clone_repo, commit_file, init_repo, GitDaemon, TempDir};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn split_flags_honours_quotes_and_escapes() {
+        assert_eq!(split_flags("  -b  dev ").unwrap(), ["-b", "dev"]);
+        assert_eq!(split_flags(r#"-b 'a b' "c \"d\"" e\ f"#).unwrap(), ["-b", "a b", r#"c "d""#, "e f"]);
+        assert_eq!(split_flags("-b ''").unwrap(), ["-b", ""]);
+        assert!(split_flags("-b 'dev").is_err());
+    }
+
+    #[test]
+    fn parse_reads_values_inline_or_separate() {
+        let options = CloneOptions::parse("--depth=3 --branch dev --recursive").unwrap();
+        assert_eq!(options.depth, Some(3));
+        assert_eq!(options.branch.as_deref(), Some("dev"));
+        assert!(options.recursive);
+
+        let options = CloneOptions::parse("--depth 1 -b 'my branch'").unwrap();
+        assert_eq!(options.depth, Some(1));
+        assert_eq!(options.branch.as_deref(), Some("my branch"));
+    }
+
+    #[test]
+    fn parse_implies_single_branch_with_depth() {
+        assert!(CloneOptions::parse("--depth 1").unwrap().single_branch);
+        assert!(!CloneOptions::parse("--depth 1 --no-single-branch").unwrap().single_branch);
+        assert!(!CloneOptions::parse("").unwrap().single_branch);
+        assert!(CloneOptions::parse("--single-branch").unwrap().single_branch);
+    }
+
+    #[test]
+    fn parse_accepts_blob_filters_only() {
+        let options = CloneOptions::parse("--filter=blob:none").unwrap();
+        assert_eq!(options.filter.as_deref(), Some("blob:none"));
+        assert!(options.uses_git());
+        assert_eq!(CloneOptions::parse("--filter blob:limit=1k").unwrap().filter.as_deref(), Some("blob:limit=1k"));
+        assert!(CloneOptions::parse("--filter tree:0").is_err());
+        assert!(CloneOptions::parse("--filter").is_err());
+        assert!(!CloneOptions::parse("--depth 1").unwrap().uses_git());
+    }
+
+    #[test]
+    fn parse_rejects_invalid_flags() {
+        assert!(CloneOptions::parse("--depth").is_err());
+        assert!(CloneOptions::parse("--depth 0").is_err());
+        assert!(CloneOptions::parse("--depth x").is_err());
+        assert!(CloneOptions::parse("-b").is_err());
+        assert!(CloneOptions::parse("--bare").is_err());
+    }
+
+    #[test]
+    fn clone_repo_checks_out_a_single_branch() {
+        let tmp = TempDir::new("clone");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let main = upstream.head().unwrap().peel_to_commit().unwrap();
+        upstream.branch("dev", &main, false).unwrap();
+        upstream.set_head("refs/heads/dev").unwrap();
+        let dev = commit_file(&upstream, "dev.zsh", "echo dev\n", "Dev only");
+        upstream.set_head("refs/heads/main").unwrap();
+
+        let checked_out = AtomicBool::new(false);
+        let options = CloneOptions::parse("-b dev --single-branch").unwrap();
+        let repo = RepoCloner::new(Arc::new(CredentialManager::default()))
+            .clone_repo(
+                tmp.path().join("upstream").to_str().unwrap(),
+                &tmp.path().join("clone"),
+                &options,
+                &|progress| {
+                    if let CloneProgress::CheckingOut { .. } = progress {
+                        checked_out.store(true, Ordering::SeqCst);
+                    }
+                },
+            )
+            .unwrap();
+
+        assert_eq!(repo.head().unwrap().shorthand(), Some("dev"));
+        assert_eq!(repo.head().unwrap().target(), Some(dev));
+        assert!(tmp.path().join("clone/dev.zsh").exists());
+        assert!(repo.find_reference("refs/remotes/origin/main").is_err());
+        assert!(checked_out.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn clone_from_bundle_points_origin_at_the_real_remote() {
+        let tmp = TempDir::new("clone");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let head = commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        let mut out = Vec::new();
+        bundle::write_bundle(&upstream, &mut out).unwrap();
+        let bundle_path = tmp.path().join("repo.bundle");
+        std::fs::write(&bundle_path, out).unwrap();
+
+        let url = "https://github.com/u/repo.git";
+        let options = CloneOptions {
+            identity_files: vec![PathBuf::from("/keys/plugin")],
+            ..Default::default()
+        };
+        let repo = RepoCloner::new(Arc::new(CredentialManager::default()))
+            .clone_from_bundle(&bundle_path, url, &tmp.path().join("clone"), &options, &|_| {})
+            .unwrap();
+
+        assert_eq!(repo.find_remote("origin").unwrap().url(), Some(url));
+        assert_eq!(repo.head().unwrap().shorthand(), Some("main"));
+        assert_eq!(repo.head().unwrap().target(), Some(head));
+        assert_eq!(repo.refname_to_id("refs/remotes/origin/main").unwrap(), head);
+        assert!(tmp.path().join("clone/a.zsh").exists());
+        // Later fetches go to the real remote with the plugin's keys
+        let config = repo.config().unwrap().open_level(git2::ConfigLevel::Local).unwrap();
+        assert_eq!(config.get_string(IDENTITY_FILE_KEY).unwrap(), "/keys/plugin");
+    }
+
+    #[test]
+    fn flags_without_depth_keeps_the_other_flags() {
+        assert_eq!(flags_without_depth("--depth 1").unwrap(), None);
+        assert_eq!(flags_without_depth("--depth=3 -b dev").unwrap().as_deref(), Some("-b dev"));
+        assert_eq!(
+            flags_without_depth("-b 'my branch' --depth 2 --single-branch").unwrap().as_deref(),
+            Some("-b 'my branch' --single-branch")
+        );
+        assert_eq!(
+            CloneOptions::parse(&flags_without_depth("-b \"it's\" --depth 1").unwrap().unwrap()).unwrap().branch.as_deref(),
+            Some("it's")
+        );
+    }
+
+    #[test]
+    fn shallow_depth_counts_commits_to_the_boundary() {
+        let tmp = TempDir::new("clone");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        commit_file(&upstream, "b.zsh", "echo b\n", "Add b");
+        let daemon = GitDaemon::start(tmp.path());
+
+        let repo = RepoCloner::new(Arc::new(CredentialManager::default()))
+            .clone_repo(&daemon.url("upstream"), &tmp.path().join("shallow"), &CloneOptions::parse("--depth 2").unwrap(), &|_| {})
+            .unwrap();
+        assert!(repo.is_shallow());
+        assert_eq!(shallow_depth(&repo), Some(2));
+
+        let full = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("full"));
+        assert_eq!(shallow_depth(&full), None);
+    }
+
+    #[test]
+    fn sparse_clones_check_out_only_the_listed_directories() {
+        let tmp = TempDir::new("clone");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        std::fs::create_dir_all(tmp.path().join("upstream/plugin")).unwrap();
+        std::fs::create_dir_all(tmp.path().join("upstream/docs")).unwrap();
+        commit_file(&upstream, "plugin/init.zsh", "echo init\n", "Add plugin");
+        commit_file(&upstream, "docs/guide.md", "guide\n", "Add docs");
+
+        let options = CloneOptions {
+            sparse: vec!["plugin".to_string()],
+            ..Default::default()
+        };
+        let repo = RepoCloner::new(Arc::new(CredentialManager::default()))
+            .clone_repo(tmp.path().join("upstream").to_str().unwrap(), &tmp.path().join("clone"), &options, &|_| {})
+            .unwrap();
+
+        assert!(git_cli::is_sparse(&repo));
+        assert!(tmp.path().join("clone/plugin/init.zsh").exists());
+        assert!(!tmp.path().join("clone/docs").exists());
+        assert_eq!(git_cli::sparse_patterns(&tmp.path().join("clone")), ["plugin"]);
+    }
+}
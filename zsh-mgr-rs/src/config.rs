@@ -1,8 +1,14 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::fetch_options::FetchSettings;
+use crate::build;
+use crate::release;
+use crate::snippet;
+
 /// Get the real home directory of the current user from the system's passwd database.
 /// This ignores `$HOME`, which `sudo` can preserve incorrectly (pointing to
 /// another user's home).  We look up the effective UID and resolve it through
@@ -44,6 +50,100 @@ pub struct Config {
     pub config_dir: PathBuf,
     pub time_threshold: u64,
     pub mgr_time_threshold: u64,
+    /// Shared bare-repository cache that clones borrow objects from (disabled if `None`)
+    #[serde(default)]
+    pub object_cache: Option<PathBuf>,
+    #[serde(flatten)]
+    pub credentials: CredentialSettings,
+    #[serde(flatten)]
+    pub concurrency: ConcurrencySettings,
+    /// Pruning, tags and refspecs for every plugin, unless a plugin overrides them
+    #[serde(default)]
+    pub fetch: FetchSettings,
+    /// How `add --private` clones plugins that have no explicit URL
+    #[serde(default)]
+    pub private_protocol: PrivateProtocol,
+    /// GitHub-compatible API that release plugins are looked up in
+    #[serde(default = "default_release_api")]
+    pub release_api: String,
+    /// Seconds each build command of a plugin may run before it is killed
+    #[serde(default = "default_build_timeout")]
+    pub build_timeout: u64,
+}
+
+fn default_release_api() -> String {
+    release::DEFAULT_RELEASE_API.to_string()
+}
+
+fn default_build_timeout() -> u64 {
+    build::DEFAULT_BUILD_TIMEOUT
+}
+
+/// Authentication settings shared by every git operation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CredentialSettings {
+    /// SSH keys to try per host pattern, before the ones from `~/.ssh/config`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ssh_identities: BTreeMap<String, Vec<PathBuf>>,
+    /// Command printing an HTTPS token for the host given as its first argument
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_command: Option<String>,
+}
+
+/// How many repositories are fetched at once
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConcurrencySettings {
+    /// Parallel jobs for updates (default: number of CPUs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+    /// Maximum concurrent fetches per host pattern, e.g. `{"github.com": 4}`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub host_jobs: BTreeMap<String, usize>,
+}
+
+/// Protocol used for private GitHub plugins
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrivateProtocol {
+    /// `git@github.com:user/repo.git`, authenticated with SSH keys
+    #[default]
+    Ssh,
+    /// `https://github.com/user/repo.git`, authenticated with a token
+    Https,
+}
+
+/// Settings that can only be set in `config.json`, read leniently so that a partial or
+/// hand-written file does not prevent zsh-mgr from starting
+#[derive(Debug, Default, Deserialize)]
+struct FileSettings {
+    #[serde(default)]
+    ssh_identities: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    token_command: Option<String>,
+    #[serde(default)]
+    private_protocol: PrivateProtocol,
+    #[serde(default)]
+    jobs: Option<usize>,
+    #[serde(default)]
+    host_jobs: BTreeMap<String, usize>,
+    #[serde(default)]
+    fetch: FetchSettings,
+    #[serde(default)]
+    release_api: Option<String>,
+    #[serde(default)]
+    build_timeout: Option<u64>,
+}
+
+impl FileSettings {
+    fn load(path: &Path) -> Self {
+        let Ok(contents) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&contents).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
 }
 
 impl Config {
@@ -69,14 +169,61 @@ impl Config {
             .and_then(|s| s.parse().ok())
             .unwrap_or(604800);
         
+        // ZSH_MGR_OBJECT_CACHE: "1"/"on" for the default location, or a directory
+        let object_cache = match std::env::var("ZSH_MGR_OBJECT_CACHE") {
+            Ok(value) => match value.trim() {
+                "" | "0" | "off" | "false" => None,
+                "1" | "on" | "true" => Some(home.join(".cache/zsh-mgr/objects")),
+                path => Some(PathBuf::from(shellexpand::tilde(path).to_string())),
+            },
+            Err(_) => None,
+        };
+        
+        let settings = FileSettings::load(&Self::settings_file(&config_dir));
+        let ssh_identities = settings
+            .ssh_identities
+            .into_iter()
+            .map(|(host, keys)| {
+                let keys = keys
+                    .iter()
+                    .map(|key| PathBuf::from(shellexpand::tilde(key).to_string()))
+                    .collect();
+                (host, keys)
+            })
+            .collect();
+        
         Ok(Self {
             plugin_dir,
             config_dir,
             time_threshold,
             mgr_time_threshold,
+            object_cache,
+            credentials: CredentialSettings {
+                ssh_identities,
+                token_command: settings.token_command.filter(|cmd| !cmd.trim().is_empty()),
+            },
+            private_protocol: settings.private_protocol,
+            concurrency: ConcurrencySettings {
+                jobs: settings.jobs.filter(|jobs| *jobs > 0),
+                host_jobs: settings.host_jobs,
+            },
+            fetch: settings.fetch,
+            release_api: settings
+                .release_api
+                .filter(|api| !api.trim().is_empty())
+                .unwrap_or_else(default_release_api),
+            build_timeout: settings
+                .build_timeout
+                .filter(|secs| *secs > 0)
+                .unwrap_or_else(default_build_timeout),
         })
     }
     
+    /// `config.json` as written by `zsh-mgr install`
+    pub fn settings_file(config_dir: &Path) -> PathBuf {
+        config_dir.join("zsh-mgr").join("config.json")
+    }
+    
     /// Save configuration to file
     pub fn save(&self, path: &Path) -> Result<()> {
         let json = serde_json::to_string_pretty(self)?;
@@ -100,24 +247,93 @@ impl Config {
     /// Get timestamp file for a plugin
     pub fn timestamp_file(&self, plugin_name: &str) -> PathBuf {
         // Extract just the repo name (after last '/')
-        let repo_name = plugin_name.split('/').last().unwrap_or(plugin_name);
+        let repo_name = plugin_name.split('/').next_back().unwrap_or(plugin_name);
         self.plugin_dir.join(format!(".{}", repo_name))
     }
     
+    /// Log of past update runs, one JSON record per line (see `history`)
+    pub fn history_file(&self) -> PathBuf {
+        self.config_dir.join("zsh-mgr").join("history.jsonl")
+    }
+
+    /// Where the binaries of release plugins are linked; added to `PATH` by the generated
+    /// shell code
+    pub fn bin_dir(&self) -> PathBuf {
+        release::bin_dir(&self.plugin_dir)
+    }
+
     /// Get manager timestamp file
     pub fn manager_timestamp_file(&self) -> PathBuf {
         self.plugin_dir.join(".zsh-mgr")
     }
 }
 
+/// Where a plugin comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginKind {
+    /// A git repository cloned into the plugin directory
+    #[default]
+    Git,
+    /// A directory elsewhere on disk, linked into the plugin directory and never updated
+    Local,
+    /// A single file downloaded from a URL, downloaded again when it changes
+    Snippet,
+    /// An asset of the latest release of a repository, with binaries linked into `bin_dir`
+    Release,
+}
+
+impl PluginKind {
+    pub fn is_git(&self) -> bool {
+        *self == PluginKind::Git
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginInfo {
     pub name: String,
+    /// Remote URL, or the linked directory of a local plugin
     pub url: String,
     pub private: bool,
     pub flags: Option<String>,
     pub installed_at: u64,
     pub last_updated: u64,
+    /// Commit the plugin is pinned to; pinned plugins are skipped by `update`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<String>,
+    /// History depth of a shallow clone, kept by updates; `None` for a full clone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+    /// Directories checked out by a sparse checkout; empty for the whole tree
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sparse: Vec<String>,
+    /// Repository (e.g. `ohmyzsh/ohmyzsh`) of a plugin loaded from one of its subdirectories.
+    /// Its clone in `plugin_dir/<repo>` is shared by every plugin of that repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// Subdirectory of `repo` the plugin is loaded from (e.g. `plugins/git`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "PluginKind::is_git")]
+    pub kind: PluginKind,
+    /// SHA-256 of the file of a snippet or the asset of a release, as last downloaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// ETag the server sent with the last download of a snippet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Release tag a release plugin is installed from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Pattern of the asset a release plugin downloads, if not the default one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+    /// Binaries a release plugin links into the bin directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bins: Vec<String>,
+    /// Shell commands run in the plugin directory after it is installed or its HEAD changes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub build: Vec<String>,
 }
 
 impl PluginInfo {
@@ -134,6 +350,38 @@ impl PluginInfo {
             flags,
             installed_at: now,
             last_updated: now,
+            pinned: None,
+            depth: None,
+            sparse: Vec::new(),
+            repo: None,
+            path: None,
+            kind: PluginKind::Git,
+            checksum: None,
+            etag: None,
+            version: None,
+            asset: None,
+            bins: Vec::new(),
+            build: Vec::new(),
+        }
+    }
+
+    /// Name of a plugin living in subdirectory `path` of `repo`, which is also where it
+    /// is found below the plugin directory
+    pub fn subdir_name(repo: &str, path: &str) -> String {
+        format!("{}/{}", repo, path)
+    }
+
+    /// Name of the clone the plugin lives in: its repository for subdirectory plugins,
+    /// its own name otherwise
+    pub fn repo_name(&self) -> &str {
+        self.repo.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Arguments of the `plugin` line loading it from .zshrc, e.g. `user/repo path:plugins/git`
+    pub fn load_spec(&self) -> String {
+        match self.path {
+            Some(ref path) => format!("{} path:{}", self.repo_name(), path),
+            None => self.name.clone(),
         }
     }
 }
@@ -191,12 +439,85 @@ impl PluginList {
         &self.plugins
     }
     
+    /// Plugins whose clone is `repo`
+    pub fn plugins_of<'a>(&'a self, repo: &'a str) -> impl Iterator<Item = &'a PluginInfo> {
+        self.plugins.iter().filter(move |p| p.repo_name() == repo)
+    }
+    
+    /// One entry per clone, in the order the clones first appear: plugins loaded from
+    /// subdirectories of the same repository are merged into an entry named after it
+    pub fn repos(&self) -> Vec<PluginInfo> {
+        let mut repos: Vec<PluginInfo> = Vec::new();
+        for plugin in &self.plugins {
+            if repos.iter().any(|r| r.name == plugin.repo_name()) {
+                continue;
+            }
+            let mut repo = plugin.clone();
+            repo.name = plugin.repo_name().to_string();
+            repo.repo = None;
+            repo.path = None;
+            repos.push(repo);
+        }
+        repos
+    }
+    
+    /// Plugins sharing the clone of plugin (or repository) `name`. Update times, pins,
+    /// depth, flags and sparse directories belong to the clone, so they are set on all of them.
+    fn clone_entries(&mut self, name: &str) -> impl Iterator<Item = &mut PluginInfo> {
+        let repo = self
+            .get(name)
+            .map(|p| p.repo_name().to_string())
+            .unwrap_or_else(|| name.to_string());
+        self.plugins.iter_mut().filter(move |p| p.repo_name() == repo)
+    }
+    
     pub fn update_timestamp(&mut self, name: &str, timestamp: u64) {
-        if let Some(plugin) = self.plugins.iter_mut().find(|p| p.name == name) {
+        for plugin in self.clone_entries(name) {
             plugin.last_updated = timestamp;
         }
     }
     
+    /// Pin a plugin to a commit, or unpin it with `None`
+    pub fn set_pinned(&mut self, name: &str, pinned: Option<String>) {
+        for plugin in self.clone_entries(name) {
+            plugin.pinned = pinned.clone();
+        }
+    }
+    
+    pub fn set_depth(&mut self, name: &str, depth: Option<u32>) {
+        for plugin in self.clone_entries(name) {
+            plugin.depth = depth;
+        }
+    }
+    
+    pub fn set_flags(&mut self, name: &str, flags: Option<String>) {
+        for plugin in self.clone_entries(name) {
+            plugin.flags = flags.clone();
+        }
+    }
+    
+    /// Record the checksum and ETag of the last download of a snippet
+    pub fn set_snippet(&mut self, name: &str, checksum: String, etag: Option<String>) {
+        if let Some(plugin) = self.plugins.iter_mut().find(|p| p.name == name) {
+            plugin.checksum = Some(checksum);
+            plugin.etag = etag;
+        }
+    }
+    
+    /// Record the release a release plugin was updated to
+    pub fn set_release(&mut self, name: &str, version: String, checksum: String) {
+        if let Some(plugin) = self.plugins.iter_mut().find(|p| p.name == name) {
+            plugin.version = Some(version);
+            plugin.checksum = Some(checksum);
+        }
+    }
+    
+    pub fn set_sparse(&mut self, name: &str, sparse: Vec<String>) {
+        for plugin in self.clone_entries(name) {
+            plugin.sparse = sparse.clone();
+        }
+    }
+    
     /// Sync plugins.json from directories in plugin_dir
     pub fn sync_from_directory(config: &Config) -> Result<Self> {
         use std::collections::HashSet;
@@ -205,6 +526,23 @@ impl PluginList {
         let mut plugins = Vec::new();
         let mut seen = HashSet::new();
         
+        // Subdirectory plugins cannot be told from the clones, so they are kept from the
+        // previous list as long as their clone is still there. A clone only used through
+        // subdirectories is not a plugin of its own.
+        let previous: Vec<PluginInfo> = fs::read_to_string(&file_path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        let subdir_plugins: Vec<_> = previous
+            .iter()
+            .filter(|p| p.path.is_some() && config.plugin_dir.join(p.repo_name()).join(".git").exists())
+            .cloned()
+            .collect();
+        let shared_only = |path: &Path| {
+            subdir_plugins.iter().any(|sub| config.plugin_dir.join(sub.repo_name()) == path)
+                && !previous.iter().any(|p| p.path.is_none() && config.plugin_dir.join(&p.name) == path)
+        };
+        
         // Scan plugin directory for git repositories
         if config.plugin_dir.exists() {
             for entry in fs::read_dir(&config.plugin_dir)? {
@@ -221,10 +559,35 @@ impl PluginList {
                     continue;
                 }
                 
+                // Local plugins are links to directories elsewhere, git repositories or not
+                if entry.file_type()?.is_symlink() {
+                    let target = match fs::canonicalize(&path) {
+                        Ok(target) => target,
+                        Err(e) => {
+                            log::warn!("Skipping local plugin {}: {}", path.display(), e);
+                            continue;
+                        }
+                    };
+                    if seen.insert(dir_name.to_string()) {
+                        let mut plugin_info = PluginInfo::new(
+                            dir_name.to_string(),
+                            target.to_string_lossy().into_owned(),
+                            false,
+                            None,
+                        );
+                        plugin_info.kind = PluginKind::Local;
+                        plugins.push(plugin_info);
+                    }
+                    continue;
+                }
+                
                 // Check if it's a git repository
                 let git_dir = path.join(".git");
                 if git_dir.exists() {
                     // It's a flat structure plugin (e.g., fzf-tab/)
+                    if shared_only(&path) {
+                        continue;
+                    }
                     if let Some(plugin_info) = Self::extract_plugin_info(&path)? {
                         if seen.insert(plugin_info.name.clone()) {
                             plugins.push(plugin_info);
@@ -233,14 +596,12 @@ impl PluginList {
                 } else {
                     // Maybe it's a user/repo structure, check subdirectories
                     if let Ok(subdirs) = fs::read_dir(&path) {
-                        for subentry in subdirs {
-                            if let Ok(subentry) = subentry {
-                                let subpath = subentry.path();
-                                if subpath.is_dir() && subpath.join(".git").exists() {
-                                    if let Some(plugin_info) = Self::extract_plugin_info(&subpath)? {
-                                        if seen.insert(plugin_info.name.clone()) {
-                                            plugins.push(plugin_info);
-                                        }
+                        for subentry in subdirs.flatten() {
+                            let subpath = subentry.path();
+                            if subpath.is_dir() && subpath.join(".git").exists() && !shared_only(&subpath) {
+                                if let Some(plugin_info) = Self::extract_plugin_info(&subpath)? {
+                                    if seen.insert(plugin_info.name.clone()) {
+                                        plugins.push(plugin_info);
                                     }
                                 }
                             }
@@ -250,28 +611,38 @@ impl PluginList {
             }
         }
         
+        // Pins, private repositories served over HTTPS and build commands cannot be told from
+        // the clones either, so they are kept from the previous list
+        for plugin in &mut plugins {
+            if let Some(prev) = previous.iter().find(|p| p.name == plugin.name) {
+                plugin.pinned = prev.pinned.clone();
+                plugin.private |= prev.private;
+                plugin.build = prev.build.clone();
+            }
+        }
+        
+        plugins.extend(subdir_plugins);
+        
+        // Snippets and releases are plain files with nothing to tell where they came from
+        plugins.extend(previous.into_iter().filter(|p| match p.kind {
+            PluginKind::Snippet => snippet::snippet_file(&config.plugin_dir.join(&p.name)).exists(),
+            PluginKind::Release => config.plugin_dir.join(&p.name).is_dir(),
+            PluginKind::Git | PluginKind::Local => false,
+        }));
+        
         let plugin_list = Self { plugins, file_path };
         plugin_list.save()?;
         Ok(plugin_list)
     }
     
     fn extract_plugin_info(repo_path: &std::path::Path) -> Result<Option<PluginInfo>> {
-        use std::process::Command;
-        
         // Get remote URL
-        let output = Command::new("git")
-            .arg("-C")
-            .arg(repo_path)
-            .arg("remote")
-            .arg("get-url")
-            .arg("origin")
-            .output()?;
-        
-        if !output.status.success() {
-            return Ok(None);
-        }
-        
-        let url = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let url = match git2::Repository::open(repo_path)
+            .and_then(|repo| repo.find_remote("origin").map(|r| r.url().map(str::to_string)))
+        {
+            Ok(Some(url)) => url,
+            _ => return Ok(None),
+        };
         
         // Parse user/repo from URL
         // Formats: https://github.com/user/repo.git or git@github.com:user/repo.git
@@ -311,21 +682,26 @@ impl PluginList {
                 .as_secs()
         };
         
-        // Check for depth flag in .git/config
-        let git_config = repo_path.join(".git/config");
-        let flags = if git_config.exists() {
-            if let Ok(config_content) = fs::read_to_string(&git_config) {
-                if config_content.contains("depth = 1") {
-                    Some("--depth 1".to_string())
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        } else {
-            None
+        // Shallow, partial and sparse clones keep their shape; the flags let a reinstall
+        // clone them the same way
+        let repo = git2::Repository::open(repo_path).ok();
+        let depth = repo.as_ref().and_then(crate::clone::shallow_depth);
+        let filter = repo
+            .as_ref()
+            .and_then(|repo| repo.config().ok())
+            .and_then(|config| config.get_string("remote.origin.partialclonefilter").ok());
+        let sparse = match repo {
+            Some(ref repo) if crate::git_cli::is_sparse(repo) => crate::git_cli::sparse_patterns(repo_path),
+            _ => Vec::new(),
         };
+        let mut flags = Vec::new();
+        if let Some(depth) = depth {
+            flags.push(format!("--depth {}", depth));
+        }
+        if let Some(filter) = filter {
+            flags.push(format!("--filter={}", filter));
+        }
+        let flags = (!flags.is_empty()).then(|| flags.join(" "));
         
         Ok(Some(PluginInfo {
             name,
@@ -334,6 +710,159 @@ impl PluginList {
             flags,
             installed_at: last_updated,  // Use same timestamp for both
             last_updated,
+            pinned: None,
+            depth,
+            sparse,
+            repo: None,
+            path: None,
+            kind: PluginKind::Git,
+            checksum: None,
+            etag: None,
+            version: None,
+            asset: None,
+            bins: Vec::new(),
+            build: Vec::new(),
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{init_repo, test_config, TempDir};
+
+    fn plugin(name: &str) -> PluginInfo {
+        PluginInfo::new(name.to_string(), format!("https://github.com/u/{}.git", name), false, None)
+    }
+
+    fn subdir_plugin(repo: &str, path: &str) -> PluginInfo {
+        let mut info = plugin(&PluginInfo::subdir_name(repo, path));
+        info.repo = Some(repo.to_string());
+        info.path = Some(path.to_string());
+        info
+    }
+
+    #[test]
+    fn set_pinned_pins_and_unpins() {
+        let mut list = PluginList {
+            plugins: vec![plugin("a"), plugin("b")],
+            file_path: PathBuf::from("plugins.json"),
+        };
+
+        list.set_pinned("a", Some("abc123".to_string()));
+        assert_eq!(list.get("a").unwrap().pinned.as_deref(), Some("abc123"));
+        assert_eq!(list.get("b").unwrap().pinned, None);
+
+        list.set_pinned("a", None);
+        assert_eq!(list.get("a").unwrap().pinned, None);
+    }
+
+    #[test]
+    fn pinned_is_optional_in_plugins_json() {
+        let json = serde_json::to_value(plugin("a")).unwrap();
+        assert!(json.get("pinned").is_none());
+
+        let parsed: PluginInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.pinned, None);
+
+        let mut pinned = plugin("a");
+        pinned.pinned = Some("abc123".to_string());
+        let parsed: PluginInfo = serde_json::from_str(&serde_json::to_string(&pinned).unwrap()).unwrap();
+        assert_eq!(parsed.pinned.as_deref(), Some("abc123"));
+    }
+
+    #[test]
+    fn file_settings_are_read_leniently() {
+        let tmp = TempDir::new("config");
+        let path = tmp.path().join("config.json");
+
+        fs::write(&path, r#"{"token_command":"pass show $1","private_protocol":"https","other":1}"#).unwrap();
+        let settings = FileSettings::load(&path);
+        assert_eq!(settings.token_command.as_deref(), Some("pass show $1"));
+        assert_eq!(settings.private_protocol, PrivateProtocol::Https);
+
+        fs::write(&path, r#"{"private_protocol":"ftp"}"#).unwrap();
+        assert_eq!(FileSettings::load(&path).private_protocol, PrivateProtocol::Ssh);
+        assert_eq!(FileSettings::load(&tmp.path().join("missing.json")).token_command, None);
+    }
+
+    #[test]
+    fn subdirectory_plugins_share_clone_settings() {
+        let mut list = PluginList {
+            plugins: vec![
+                subdir_plugin("o/omz", "plugins/git"),
+                plugin("a"),
+                subdir_plugin("o/omz", "plugins/sudo"),
+            ],
+            file_path: PathBuf::from("plugins.json"),
+        };
+
+        let repos: Vec<String> = list.repos().into_iter().map(|r| r.name).collect();
+        assert_eq!(repos, ["o/omz", "a"]);
+        assert_eq!(list.plugins_of("o/omz").count(), 2);
+
+        list.set_pinned("o/omz/plugins/git", Some("abc123".to_string()));
+        list.update_timestamp("o/omz", 42);
+        for plugin in list.plugins_of("o/omz") {
+            assert_eq!(plugin.pinned.as_deref(), Some("abc123"));
+            assert_eq!(plugin.last_updated, 42);
+        }
+        assert_eq!(list.get("a").unwrap().pinned, None);
+        assert_eq!(list.get("o/omz/plugins/sudo").unwrap().load_spec(), "o/omz path:plugins/sudo");
+        assert_eq!(list.get("a").unwrap().load_spec(), "a");
+    }
+
+    #[test]
+    fn sync_keeps_subdirectory_plugins_of_existing_clones() {
+        let tmp = TempDir::new("config");
+        let config = test_config(tmp.path());
+        init_repo(&config.plugin_dir.join("o/omz"));
+        init_repo(&config.plugin_dir.join("u/flat"))
+            .remote("origin", "https://github.com/u/flat.git")
+            .unwrap();
+        let previous = vec![subdir_plugin("o/omz", "plugins/git"), subdir_plugin("gone/repo", "plugins/x")];
+        fs::write(config.plugin_list_file(), serde_json::to_string(&previous).unwrap()).unwrap();
+
+        let list = PluginList::sync_from_directory(&config).unwrap();
+        let mut names: Vec<&str> = list.list().iter().map(|p| p.name.as_str()).collect();
+        names.sort();
+        // The shared clone is not a plugin of its own, and plugins of removed clones are dropped
+        assert_eq!(names, ["o/omz/plugins/git", "u/flat"]);
+    }
+
+    #[test]
+    fn sync_finds_local_plugins_by_their_links() {
+        let tmp = TempDir::new("config");
+        let config = test_config(tmp.path());
+        let source = tmp.path().join("work");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&config.plugin_dir).unwrap();
+        std::os::unix::fs::symlink(&source, config.plugin_dir.join("mine")).unwrap();
+
+        let list = PluginList::sync_from_directory(&config).unwrap();
+        let mine = list.get("mine").unwrap();
+        assert_eq!(mine.kind, PluginKind::Local);
+        assert_eq!(mine.url, fs::canonicalize(&source).unwrap().to_str().unwrap());
+
+        let json = serde_json::to_value(mine).unwrap();
+        assert_eq!(json["kind"], "local");
+        assert!(serde_json::to_value(plugin("a")).unwrap().get("kind").is_none());
+    }
+
+    #[test]
+    fn sync_keeps_snippets_whose_file_is_there() {
+        let tmp = TempDir::new("config");
+        let config = test_config(tmp.path());
+        let mut kept = plugin("kept");
+        kept.kind = PluginKind::Snippet;
+        let mut gone = plugin("gone");
+        gone.kind = PluginKind::Snippet;
+        fs::create_dir_all(config.plugin_dir.join("kept")).unwrap();
+        fs::write(snippet::snippet_file(&config.plugin_dir.join("kept")), "echo kept\n").unwrap();
+        fs::write(config.plugin_list_file(), serde_json::to_string(&[kept, gone]).unwrap()).unwrap();
+
+        let list = PluginList::sync_from_directory(&config).unwrap();
+        let names: Vec<&str> = list.list().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["kept"]);
+    }
+}
@@ -1,90 +1,388 @@
 use git2::{Cred, CredentialType, RemoteCallbacks, Repository};
+use log::{debug, warn};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::env;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::askpass::PassphraseCache;
+use crate::config::CredentialSettings;
+use crate::ssh_config::{wildcard_match, SshConfig, SshUrl};
+use crate::tokens::{http_host, TokenStore, DEFAULT_TOKEN_LOGIN};
+
+/// Git config key listing additional SSH keys for a single plugin repository
+pub const IDENTITY_FILE_KEY: &str = "zsh-mgr.identityFile";
+
+/// One way of authenticating, tried in order until the server accepts one
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum AuthMethod {
+    Key(PathBuf),
+    Agent,
+    Token,
+    CredentialHelper,
+}
+
+impl AuthMethod {
+    fn allowed(&self, allowed_types: CredentialType) -> bool {
+        match self {
+            AuthMethod::Key(_) | AuthMethod::Agent => allowed_types.contains(CredentialType::SSH_KEY),
+            AuthMethod::Token | AuthMethod::CredentialHelper => {
+                allowed_types.contains(CredentialType::USER_PASS_PLAINTEXT)
+            }
+        }
+    }
+}
 
 /// Manages authentication credentials for Git operations
 /// Follows OOP principles with encapsulated credential logic
 #[derive(Clone)]
 pub struct CredentialManager {
     home_dir: PathBuf,
+    ssh_config: SshConfig,
+    /// Keys configured in zsh-mgr per host pattern
+    host_identities: BTreeMap<String, Vec<PathBuf>>,
+    /// Host aliases from `~/.ssh/config`, keyed by the real host name `resolve_url` rewrote them to
+    aliases: Arc<Mutex<HashMap<String, String>>>,
+    /// Shared by every clone of this manager, so each key is asked for once per batch
+    passphrases: Arc<PassphraseCache>,
+    /// HTTPS tokens, shared like the passphrases
+    tokens: Arc<TokenStore>,
+    /// Method the server last accepted per host, tried first by later operations of the batch
+    accepted: Arc<Mutex<HashMap<String, AuthMethod>>>,
 }
 
 impl CredentialManager {
     /// Create a new CredentialManager instance
     pub fn new() -> Result<Self, String> {
-        let home_dir = env::var("HOME")
+        let home_dir: PathBuf = env::var("HOME")
             .map_err(|_| "HOME environment variable not set".to_string())?
             .into();
-        Ok(Self { home_dir })
+        let ssh_config = SshConfig::load(&home_dir);
+        Ok(Self {
+            tokens: Arc::new(TokenStore::new(home_dir.clone(), None)),
+            home_dir,
+            ssh_config,
+            host_identities: BTreeMap::new(),
+            aliases: Arc::new(Mutex::new(HashMap::new())),
+            passphrases: Arc::new(PassphraseCache::new()),
+            accepted: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Apply the configured SSH keys per host pattern (e.g. `"github.com"` or `"*.corp"`),
+    /// tried before the ones from `~/.ssh/config`, and the HTTPS token command
+    pub fn with_settings(mut self, settings: &CredentialSettings) -> Self {
+        self.host_identities = settings.ssh_identities.clone();
+        self.tokens = Arc::new(TokenStore::new(self.home_dir.clone(), settings.token_command.clone()));
+        self
+    }
+
+    /// Apply `HostName`, `Port` and `User` from `~/.ssh/config` to an SSH URL, since libgit2
+    /// connects to the host exactly as written. Other URLs are returned unchanged.
+    pub fn resolve_url(&self, url: &str) -> String {
+        let Some(parsed) = SshUrl::parse(url) else {
+            return url.to_string();
+        };
+        let host_config = self.ssh_config.lookup(&parsed.host, &self.home_dir);
+        if host_config.hostname.is_none() && host_config.port.is_none() {
+            return url.to_string();
+        }
+
+        let resolved = SshUrl {
+            user: parsed.user.or(host_config.user),
+            host: host_config.hostname.unwrap_or_else(|| parsed.host.clone()),
+            port: parsed.port.or(host_config.port),
+            path: parsed.path,
+        };
+        if let Ok(mut aliases) = self.aliases.lock() {
+            aliases.insert(resolved.host.clone(), parsed.host);
+        }
+        resolved.to_url()
+    }
+
+    /// SSH keys configured for a plugin repository through `zsh-mgr.identityFile`
+    pub fn repo_identities(repo: &Repository) -> Vec<PathBuf> {
+        let Ok(config) = repo.config() else {
+            return Vec::new();
+        };
+        Self::identities_from_config(&config)
+    }
+
+    fn identities_from_config(config: &git2::Config) -> Vec<PathBuf> {
+        let mut identities = Vec::new();
+        if let Ok(mut entries) = config.multivar(IDENTITY_FILE_KEY, None) {
+            while let Some(Ok(entry)) = entries.next() {
+                if let Some(value) = entry.value() {
+                    identities.push(PathBuf::from(shellexpand::tilde(value).to_string()));
+                }
+            }
+        }
+        identities
     }
 
     /// Create RemoteCallbacks configured with credential handlers
     pub fn create_callbacks<'a>(&self, repo: &Repository) -> RemoteCallbacks<'a> {
         // Capture owned Config so the closure does not borrow `repo`
         let config = repo.config().ok();
-        let home_dir = self.home_dir.clone();
+        let identities = config
+            .as_ref()
+            .map(Self::identities_from_config)
+            .unwrap_or_default();
+        self.callbacks_with_config(config, identities)
+    }
 
-        let mut callbacks = RemoteCallbacks::new();
+    /// Create RemoteCallbacks for operations without a repository yet (e.g. cloning),
+    /// using the user's global git configuration for the credential helper
+    pub fn create_clone_callbacks<'a>(&self, identities: &[PathBuf]) -> RemoteCallbacks<'a> {
+        self.callbacks_with_config(git2::Config::open_default().ok(), identities.to_vec())
+    }
+
+    /// The `~/.ssh/config` alias `host` was resolved from, or `host` itself. Host keys and
+    /// `Host` blocks are configured for the name the user wrote, not the real host name.
+    fn alias_of(&self, host: String) -> String {
+        self.aliases
+            .lock()
+            .ok()
+            .and_then(|aliases| aliases.get(&host).cloned())
+            .unwrap_or(host)
+    }
 
-        callbacks.credentials(move |_url, username_from_url, _allowed_types| {
-            // SSH Key authentication
-            if _allowed_types.contains(CredentialType::SSH_KEY) {
-                let username = username_from_url.unwrap_or("git");
-
-                // Try id_ed25519 first (more modern and secure)
-                let ssh_key_ed = home_dir.join(".ssh/id_ed25519");
-                if ssh_key_ed.exists() {
-                    eprintln!("🔑 Trying SSH key: {}", ssh_key_ed.display());
-                    match Cred::ssh_key(username, None, &ssh_key_ed, None) {
-                        Ok(c) => {
-                            eprintln!("✓ Using SSH key: {}", ssh_key_ed.display());
-                            return Ok(c);
-                        }
-                        Err(e) => eprintln!("✗ Failed to load {}: {}", ssh_key_ed.display(), e),
-                    }
+    /// Authentication attempts for `url`: the plugin's own keys, keys configured for the host, then
+    /// `IdentityFile` entries from `~/.ssh/config` (or the default keys if there are none), the agent
+    /// unless `IdentitiesOnly` is set, an HTTPS token and finally the credential helper.
+    /// The method accepted earlier in the batch for the same host comes first.
+    fn auth_attempts(&self, url: &str, identities: &[PathBuf]) -> (Option<String>, VecDeque<AuthMethod>) {
+        let mut attempts = VecDeque::new();
+        let mut config_user = None;
+
+        if let Some(parsed) = SshUrl::parse(url) {
+            let alias = self.alias_of(parsed.host);
+            let host_config = self.ssh_config.lookup(&alias, &self.home_dir);
+
+            let mut keys: Vec<PathBuf> = identities.to_vec();
+            for (pattern, files) in &self.host_identities {
+                if wildcard_match(pattern, &alias) {
+                    keys.extend(files.iter().cloned());
                 }
+            }
+            keys.extend(host_config.identity_files.iter().cloned());
+            if keys.is_empty() {
+                keys.push(self.home_dir.join(".ssh/id_ed25519"));
+                keys.push(self.home_dir.join(".ssh/id_rsa"));
+            }
 
-                // Fallback to id_rsa
-                let ssh_key_rsa = home_dir.join(".ssh/id_rsa");
-                if ssh_key_rsa.exists() {
-                    eprintln!("🔑 Trying SSH key: {}", ssh_key_rsa.display());
-                    match Cred::ssh_key(username, None, &ssh_key_rsa, None) {
-                        Ok(c) => {
-                            eprintln!("✓ Using SSH key: {}", ssh_key_rsa.display());
-                            return Ok(c);
-                        }
-                        Err(e) => eprintln!("✗ Failed to load {}: {}", ssh_key_rsa.display(), e),
-                    }
+            for key in keys.into_iter().filter(|key| key.exists()) {
+                let method = AuthMethod::Key(key);
+                if !attempts.contains(&method) {
+                    attempts.push_back(method);
                 }
+            }
+            if !host_config.identities_only {
+                attempts.push_back(AuthMethod::Agent);
+            }
+            config_user = host_config.user;
+        }
+        attempts.push_back(AuthMethod::Token);
+        attempts.push_back(AuthMethod::CredentialHelper);
 
-                // Try SSH agent as last resort
-                eprintln!("🔑 Trying SSH agent");
-                match Cred::ssh_key_from_agent(username) {
-                    Ok(c) => {
-                        eprintln!("✓ Using SSH agent");
-                        return Ok(c);
-                    }
-                    Err(e) => eprintln!("✗ SSH agent failed: {}", e),
+        let accepted = self
+            .accepted
+            .lock()
+            .ok()
+            .and_then(|accepted| accepted.get(&host_of(url)).cloned());
+        if let Some(method) = accepted {
+            if let Some(pos) = attempts.iter().position(|m| *m == method) {
+                debug!("Trying {:?} first for {}, it worked before", method, url);
+                if let Some(method) = attempts.remove(pos) {
+                    attempts.push_front(method);
                 }
             }
+        }
+
+        (config_user, attempts)
+    }
+
+    /// Credential for one attempt, or `None` if it cannot be used (e.g. no token for the host)
+    fn credential(
+        &self,
+        method: &AuthMethod,
+        url: &str,
+        username: Option<&str>,
+        config: Option<&git2::Config>,
+    ) -> Option<Cred> {
+        let result = match method {
+            AuthMethod::Key(key) => {
+                debug!("Trying SSH key {} for {}", key.display(), url);
+                let passphrase = self.passphrases.passphrase(key);
+                Cred::ssh_key(username.unwrap_or("git"), None, key, passphrase.as_deref())
+            }
+            AuthMethod::Agent => {
+                debug!("Trying SSH agent for {}", url);
+                Cred::ssh_key_from_agent(username.unwrap_or("git"))
+            }
+            AuthMethod::Token => {
+                let token = self.tokens.token_for_url(url)?;
+                debug!("Trying access token for {}", url);
+                let login = token.login.as_deref().or(username).unwrap_or(DEFAULT_TOKEN_LOGIN);
+                Cred::userpass_plaintext(login, &token.token)
+            }
+            AuthMethod::CredentialHelper => {
+                debug!("Trying credential helper for {}", url);
+                Cred::credential_helper(config?, url, username)
+            }
+        };
+        result
+            .map_err(|e| debug!("{:?} unavailable for {}: {}", method, url, e))
+            .ok()
+    }
+
+    fn callbacks_with_config<'a>(&self, config: Option<git2::Config>, identities: Vec<PathBuf>) -> RemoteCallbacks<'a> {
+        let manager = self.clone();
+        // libgit2 calls back again after a rejected credential, so each call moves on to the next attempt
+        let mut attempts: Option<(Option<String>, VecDeque<AuthMethod>)> = None;
+        let mut offered: Option<AuthMethod> = None;
+
+        let mut callbacks = RemoteCallbacks::new();
 
-            // Username/Password authentication
-            if _allowed_types.contains(CredentialType::USER_PASS_PLAINTEXT) {
-                eprintln!("🔑 Attempting username/password authentication");
-                if let Some(ref cfg) = config {
-                    if let Ok(c) = Cred::credential_helper(cfg, _url, username_from_url) {
-                        eprintln!("✓ Using credential helper");
-                        return Ok(c);
-                    }
+        callbacks.credentials(move |url, username_from_url, allowed_types| {
+            let (config_user, attempts) =
+                attempts.get_or_insert_with(|| manager.auth_attempts(url, &identities));
+
+            if allowed_types.contains(CredentialType::USERNAME) {
+                return Cred::username(config_user.as_deref().unwrap_or("git"));
+            }
+
+            let host = host_of(url);
+            if let Some(rejected) = offered.take() {
+                debug!("{:?} rejected by {}", rejected, host);
+                manager.forget(&host, &rejected);
+            }
+
+            let username = username_from_url.or(config_user.as_deref());
+            while let Some(method) = attempts.pop_front() {
+                if !method.allowed(allowed_types) {
+                    continue;
+                }
+                if let Some(cred) = manager.credential(&method, url, username, config.as_ref()) {
+                    // Remembered now, as libgit2 does not report success; a rejection undoes it
+                    manager.remember(&host, &method);
+                    offered = Some(method);
+                    return Ok(cred);
                 }
             }
 
-            eprintln!("✗ No valid credentials found");
+            warn!("No valid credentials found for {}", url);
             Err(git2::Error::from_str("No valid credentials available"))
         });
 
         callbacks
     }
+
+    /// Environment for running the `git` executable against `url` with the same credentials:
+    /// the plugin's and the host's SSH keys through `GIT_SSH_COMMAND`, and the HTTPS token
+    /// through a credential helper reading it from the environment, so it never shows up in
+    /// the process list. `~/.ssh/config` and the agent are left to `ssh` itself.
+    pub fn git_env(&self, url: &str, identities: &[PathBuf]) -> Vec<(String, String)> {
+        let mut env = Vec::new();
+
+        if let Some(parsed) = SshUrl::parse(url) {
+            let alias = self.alias_of(parsed.host);
+            let mut keys: Vec<PathBuf> = identities.to_vec();
+            for (pattern, files) in &self.host_identities {
+                if wildcard_match(pattern, &alias) {
+                    keys.extend(files.iter().cloned());
+                }
+            }
+            let keys: Vec<String> = keys
+                .iter()
+                .filter(|key| key.exists())
+                .map(|key| format!("-i '{}'", key.to_string_lossy().replace('\'', "'\\''")))
+                .collect();
+            // git runs outside the terminal's foreground process group, where ssh would be
+            // stopped as soon as it prompted, so it has to fail instead; a command the user
+            // configured is left alone unless keys have to be added
+            let custom = env::var_os("GIT_SSH_COMMAND").is_some()
+                || git2::Config::open_default().is_ok_and(|config| config.get_string("core.sshCommand").is_ok());
+            if !keys.is_empty() || !custom {
+                let mut command = vec!["ssh".to_string(), "-o BatchMode=yes".to_string()];
+                command.extend(keys);
+                env.push(("GIT_SSH_COMMAND".to_string(), command.join(" ")));
+            }
+            return env;
+        }
+
+        // git applies `url.<base>.insteadOf` before asking for credentials, so the token is
+        // looked up for the rewritten URL, like libgit2 does
+        let url = git2::Config::open_default()
+            .map(|config| rewrite_url(&config, url))
+            .unwrap_or_else(|_| url.to_string());
+        let Some(token) = self.tokens.token_for_url(&url) else {
+            return env;
+        };
+        // Appended to config passed the same way by the caller's environment, if any
+        let base: usize = env::var("GIT_CONFIG_COUNT")
+            .ok()
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0);
+        let helper = r#"!f() { test "$1" = get && echo "username=$ZSH_MGR_GIT_USERNAME" && echo "password=$ZSH_MGR_GIT_PASSWORD"; }; f"#;
+        // An empty helper first clears the helpers from the user's configuration
+        for (i, value) in ["", helper].into_iter().enumerate() {
+            env.push((format!("GIT_CONFIG_KEY_{}", base + i), "credential.helper".to_string()));
+            env.push((format!("GIT_CONFIG_VALUE_{}", base + i), value.to_string()));
+        }
+        env.push(("GIT_CONFIG_COUNT".to_string(), (base + 2).to_string()));
+        env.push((
+            "ZSH_MGR_GIT_USERNAME".to_string(),
+            token.login.unwrap_or_else(|| DEFAULT_TOKEN_LOGIN.to_string()),
+        ));
+        env.push(("ZSH_MGR_GIT_PASSWORD".to_string(), token.token));
+        env
+    }
+
+    fn remember(&self, host: &str, method: &AuthMethod) {
+        if let Ok(mut accepted) = self.accepted.lock() {
+            accepted.insert(host.to_string(), method.clone());
+        }
+    }
+
+    fn forget(&self, host: &str, method: &AuthMethod) {
+        if let Ok(mut accepted) = self.accepted.lock() {
+            if accepted.get(host) == Some(method) {
+                accepted.remove(host);
+            }
+        }
+    }
+}
+
+/// `url` with the longest matching `url.<base>.insteadOf` prefix replaced by its base
+fn rewrite_url(config: &git2::Config, url: &str) -> String {
+    let mut best: Option<(String, String)> = None;
+    if let Ok(mut entries) = config.entries(Some(r"url\..*\.insteadof")) {
+        while let Some(Ok(entry)) = entries.next() {
+            let (Some(name), Some(prefix)) = (entry.name(), entry.value()) else {
+                continue;
+            };
+            let Some(base) = name.strip_prefix("url.").and_then(|n| n.strip_suffix(".insteadof")) else {
+                continue;
+            };
+            let longer = best.as_ref().is_none_or(|(best_prefix, _)| prefix.len() > best_prefix.len());
+            if url.starts_with(prefix) && longer {
+                best = Some((prefix.to_string(), base.to_string()));
+            }
+        }
+    }
+    match best {
+        Some((prefix, base)) => format!("{}{}", base, &url[prefix.len()..]),
+        None => url.to_string(),
+    }
+}
+
+/// Host of an SSH or HTTP(S) URL, used to share accepted methods between operations
+pub(crate) fn host_of(url: &str) -> String {
+    SshUrl::parse(url)
+        .map(|u| u.host)
+        .or_else(|| http_host(url))
+        .unwrap_or_else(|| url.to_string())
 }
 
 impl Default for CredentialManager {
@@ -92,3 +390,211 @@ impl Default for CredentialManager {
         Self::new().expect("Failed to initialize CredentialManager")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::fs;
+
+    const SSH_CONFIG: &str = "\
+Host gh
+    HostName github.com
+    Port 2222
+    User git
+    IdentityFile ~/.ssh/gh_key
+Host strict
+    IdentityFile ~/.ssh/gh_key
+    IdentitiesOnly yes
+";
+
+    fn manager(home: &std::path::Path) -> CredentialManager {
+        let mut manager = CredentialManager::new().unwrap();
+        manager.home_dir = home.to_path_buf();
+        manager.ssh_config = SshConfig::parse(SSH_CONFIG);
+        manager
+    }
+
+    fn key(path: PathBuf) -> AuthMethod {
+        AuthMethod::Key(path)
+    }
+
+    #[test]
+    fn resolve_url_applies_the_ssh_config() {
+        let tmp = TempDir::new("credentials");
+        let manager = manager(tmp.path());
+        assert_eq!(manager.resolve_url("gh:user/repo.git"), "ssh://git@github.com:2222/user/repo.git");
+        assert_eq!(manager.resolve_url("me@gh:user/repo"), "ssh://me@github.com:2222/user/repo");
+        assert_eq!(manager.resolve_url("git@gitlab.com:user/repo"), "git@gitlab.com:user/repo");
+        assert_eq!(manager.resolve_url("https://gh/user/repo"), "https://gh/user/repo");
+    }
+
+    #[test]
+    fn auth_attempts_order_keys_by_source() {
+        let tmp = TempDir::new("credentials");
+        let ssh = tmp.path().join(".ssh");
+        fs::create_dir_all(&ssh).unwrap();
+        for key in ["plugin_key", "host_key", "gh_key"] {
+            fs::write(ssh.join(key), "").unwrap();
+        }
+
+        let host_identities = BTreeMap::from([
+            ("g?".to_string(), vec![ssh.join("host_key"), ssh.join("missing_key")]),
+            ("gitlab.com".to_string(), vec![ssh.join("gh_key")]),
+        ]);
+        let settings = CredentialSettings {
+            ssh_identities: host_identities,
+            ..Default::default()
+        };
+        let manager = manager(tmp.path()).with_settings(&settings);
+        // The alias is remembered, so patterns and `Host gh` still match after resolving
+        let url = manager.resolve_url("gh:user/repo");
+
+        let (user, attempts) = manager.auth_attempts(&url, &[ssh.join("plugin_key"), ssh.join("gh_key")]);
+        assert_eq!(user.as_deref(), Some("git"));
+        assert_eq!(
+            attempts,
+            [
+                key(ssh.join("plugin_key")),
+                key(ssh.join("gh_key")),
+                key(ssh.join("host_key")),
+                AuthMethod::Agent,
+                AuthMethod::Token,
+                AuthMethod::CredentialHelper,
+            ]
+        );
+    }
+
+    #[test]
+    fn auth_attempts_skip_the_agent_with_identities_only() {
+        let tmp = TempDir::new("credentials");
+        let manager = manager(tmp.path());
+
+        let (_, attempts) = manager.auth_attempts("git@strict:user/repo", &[]);
+        assert_eq!(attempts, [AuthMethod::Token, AuthMethod::CredentialHelper]);
+
+        // Without any configured key, the default keys are tried (none exist here) and then the agent
+        let (_, attempts) = manager.auth_attempts("git@example.com:user/repo", &[]);
+        assert_eq!(attempts, [AuthMethod::Agent, AuthMethod::Token, AuthMethod::CredentialHelper]);
+
+        let (_, attempts) = manager.auth_attempts("https://example.com/user/repo", &[]);
+        assert_eq!(attempts, [AuthMethod::Token, AuthMethod::CredentialHelper]);
+    }
+
+    #[test]
+    fn accepted_method_is_tried_first_by_every_clone() {
+        let tmp = TempDir::new("credentials");
+        let manager = manager(tmp.path());
+        let shared = manager.clone();
+
+        shared.remember("example.com", &AuthMethod::CredentialHelper);
+        let (_, attempts) = manager.auth_attempts("https://example.com/user/repo", &[]);
+        assert_eq!(attempts, [AuthMethod::CredentialHelper, AuthMethod::Token]);
+        // Other hosts keep the default order
+        let (_, attempts) = manager.auth_attempts("https://other.com/user/repo", &[]);
+        assert_eq!(attempts, [AuthMethod::Token, AuthMethod::CredentialHelper]);
+
+        manager.forget("example.com", &AuthMethod::Token);
+        assert_eq!(shared.auth_attempts("https://example.com/u/r", &[]).1[0], AuthMethod::CredentialHelper);
+        manager.forget("example.com", &AuthMethod::CredentialHelper);
+        assert_eq!(shared.auth_attempts("https://example.com/u/r", &[]).1[0], AuthMethod::Token);
+    }
+
+    #[test]
+    fn methods_are_offered_for_matching_credential_types() {
+        assert!(AuthMethod::Agent.allowed(CredentialType::SSH_KEY));
+        assert!(!AuthMethod::Agent.allowed(CredentialType::USER_PASS_PLAINTEXT));
+        assert!(AuthMethod::Token.allowed(CredentialType::USER_PASS_PLAINTEXT | CredentialType::SSH_KEY));
+        assert!(!AuthMethod::CredentialHelper.allowed(CredentialType::SSH_KEY));
+    }
+
+    #[test]
+    fn host_of_handles_ssh_and_https_urls() {
+        assert_eq!(host_of("git@github.com:u/repo.git"), "github.com");
+        assert_eq!(host_of("ssh://git@example.com:2222/u/repo"), "example.com");
+        assert_eq!(host_of("https://me@GitLab.com:443/u/repo"), "gitlab.com");
+        assert_eq!(host_of("/local/path"), "/local/path");
+    }
+
+    #[test]
+    fn repo_identities_reads_every_configured_key() {
+        let tmp = TempDir::new("credentials");
+        let path = tmp.path().join("config");
+        fs::write(&path, "[zsh-mgr]\n\tidentityFile = /keys/a\n\tidentityFile = ~/keys/b\n").unwrap();
+
+        let identities = CredentialManager::identities_from_config(&git2::Config::open(&path).unwrap());
+        let home = PathBuf::from(shellexpand::tilde("~").to_string());
+        assert_eq!(identities, [PathBuf::from("/keys/a"), home.join("keys/b")]);
+    }
+
+    fn git_config(dir: &std::path::Path, contents: &str) -> git2::Config {
+        let path = dir.join("gitconfig");
+        fs::write(&path, contents).unwrap();
+        git2::Config::open(&path).unwrap().snapshot().unwrap()
+    }
+
+    #[test]
+    fn rewrite_url_uses_the_longest_matching_prefix() {
+        let tmp = TempDir::new("credentials");
+        let config = git_config(
+            tmp.path(),
+            "[url \"git@github.com:\"]\n\tinsteadOf = https://github.com/\n\
+             [url \"https://mirror.example.com/work/\"]\n\tinsteadOf = https://github.com/work/\n",
+        );
+        assert_eq!(rewrite_url(&config, "https://github.com/user/repo"), "git@github.com:user/repo");
+        assert_eq!(
+            rewrite_url(&config, "https://github.com/work/repo"),
+            "https://mirror.example.com/work/repo"
+        );
+        assert_eq!(rewrite_url(&config, "https://gitlab.com/user/repo"), "https://gitlab.com/user/repo");
+    }
+
+    #[test]
+    fn rewrite_url_accepts_several_prefixes_per_base() {
+        let tmp = TempDir::new("credentials");
+        let config = git_config(tmp.path(), "[url \"https://git.example.com/\"]\n\tinsteadOf = ex:\n\tinsteadOf = example:\n");
+        assert_eq!(rewrite_url(&config, "ex:repo"), "https://git.example.com/repo");
+        assert_eq!(rewrite_url(&config, "example:repo"), "https://git.example.com/repo");
+    }
+
+    #[test]
+    fn git_env_passes_ssh_keys_on_the_command_line() {
+        let tmp = TempDir::new("credentials");
+        let plugin_key = tmp.path().join("it's key");
+        let host_key = tmp.path().join("host_key");
+        fs::write(&plugin_key, "").unwrap();
+        fs::write(&host_key, "").unwrap();
+        let settings = CredentialSettings {
+            ssh_identities: BTreeMap::from([("*.example.com".to_string(), vec![host_key.clone()])]),
+            ..Default::default()
+        };
+        let manager = manager(tmp.path()).with_settings(&settings);
+
+        let env = manager.git_env("git@git.example.com:u/repo", &[plugin_key.clone(), tmp.path().join("missing")]);
+        let expected = format!(
+            "ssh -o BatchMode=yes -i '{}' -i '{}'",
+            plugin_key.to_string_lossy().replace('\'', "'\\''"),
+            host_key.display()
+        );
+        assert_eq!(env, [("GIT_SSH_COMMAND".to_string(), expected)]);
+        let other = manager.git_env("git@other.com:u/repo", &[]);
+        assert!(other.iter().all(|(_, command)| !command.contains("-i ")));
+    }
+
+    #[test]
+    fn git_env_hands_tokens_to_a_credential_helper() {
+        let tmp = TempDir::new("credentials");
+        fs::write(tmp.path().join(".netrc"), "machine git.example.test login me password secret\n").unwrap();
+        let mut manager = manager(tmp.path());
+        manager.tokens = Arc::new(TokenStore::new(tmp.path().to_path_buf(), None));
+
+        let env: HashMap<String, String> = manager.git_env("https://git.example.test/u/repo", &[]).into_iter().collect();
+        assert_eq!(env["ZSH_MGR_GIT_USERNAME"], "me");
+        assert_eq!(env["ZSH_MGR_GIT_PASSWORD"], "secret");
+        // The token itself never ends up in the helper's command line
+        assert!(env.values().filter(|value| value.contains("secret")).count() == 1);
+        assert!(env.values().any(|value| value.contains("$ZSH_MGR_GIT_PASSWORD")));
+
+        assert!(manager.git_env("https://no-token.example.test/u/repo", &[]).is_empty());
+    }
+}
@@ -0,0 +1,786 @@
+use anyhow::Result;
+use colored::Colorize;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use super::cancel::CancelToken;
+use super::events::{millis, EventStream, UpdateEvent};
+use super::limits::HostLimiter;
+use super::repo::{no_upstream_message, PendingChanges, RepoUpdater};
+use super::result::{BatchUpdateResults, FetchInfo, FetchResult, MergeInfo, MergeType, UpdateResult};
+use crate::config::{ConcurrencySettings, CredentialSettings};
+use crate::credentials_manager::{host_of, CredentialManager};
+use crate::fetch_options::FetchSettings;
+use crate::object_cache::ObjectCache;
+use crate::progress::{ProgressDisplay, RepoStatus};
+
+#[derive(Debug, Clone)]
+pub struct UpdateConfig {
+    pub quiet: bool,
+    pub verbose: bool,
+    /// Only fetch, leaving every working tree untouched
+    pub fetch_only: bool,
+    /// Redraw one status line per repository instead of printing a line per event
+    pub progress: bool,
+    /// Shared object cache to fetch through (see `ObjectCache`)
+    pub object_cache: Option<PathBuf>,
+    /// SSH keys per host pattern and the HTTPS token command
+    pub credentials: CredentialSettings,
+    /// Parallel jobs and per-host fetch limits
+    pub concurrency: ConcurrencySettings,
+    /// Pruning, tags and refspecs, unless a repository overrides them
+    pub fetch: FetchSettings,
+}
+
+impl UpdateConfig {
+    pub fn new(quiet: bool, verbose: bool) -> Self {
+        Self {
+            quiet,
+            verbose,
+            fetch_only: false,
+            progress: false,
+            object_cache: None,
+            credentials: CredentialSettings::default(),
+            concurrency: ConcurrencySettings::default(),
+            fetch: FetchSettings::default(),
+        }
+    }
+
+    pub fn with_fetch_only(mut self, fetch_only: bool) -> Self {
+        self.fetch_only = fetch_only;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn with_object_cache(mut self, object_cache: Option<PathBuf>) -> Self {
+        self.object_cache = object_cache;
+        self
+    }
+
+    pub fn with_credentials(mut self, credentials: CredentialSettings) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    pub fn with_concurrency(mut self, concurrency: ConcurrencySettings) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn with_fetch(mut self, fetch: FetchSettings) -> Self {
+        self.fetch = fetch;
+        self
+    }
+}
+
+/// Updates many repositories in parallel, sharing credentials and the object cache
+pub struct BatchUpdater {
+    config: Arc<UpdateConfig>,
+    credentials: Arc<CredentialManager>,
+    object_cache: Option<Arc<ObjectCache>>,
+    progress: Option<Arc<ProgressDisplay>>,
+    /// Name shown for each repository, the directory name unless set with `with_labels`. Names
+    /// need not be unique, the position of a path is its row in the progress display.
+    labels: Vec<(PathBuf, String)>,
+    /// History depth of shallow clones, set with `with_depths`
+    depths: Vec<(PathBuf, u32)>,
+    events: Option<Arc<EventStream>>,
+    cancel: CancelToken,
+    /// Own pool sized by `jobs`, so the global rayon pool is left alone
+    pool: rayon::ThreadPool,
+    hosts: HostLimiter<Job>,
+}
+
+/// A repository ready to fetch, waiting for or holding a fetch slot of its host
+struct Job {
+    /// Position in the paths given to the batch, where its result goes
+    index: usize,
+    path: PathBuf,
+    host: String,
+    updater: RepoUpdater,
+    old_head: Option<git2::Oid>,
+    start: Instant,
+}
+
+/// A repository after it was opened: ready to fetch, or already done with its result
+enum Prepared<R> {
+    Ready(Job),
+    Finished(R),
+}
+
+type Fetched = Result<(FetchInfo, Option<PendingChanges>), git2::Error>;
+
+impl BatchUpdater {
+    pub fn new(config: Arc<UpdateConfig>, repo_paths: &[PathBuf]) -> Result<Self> {
+        let credentials = Arc::new(CredentialManager::new()
+            .map_err(|e| anyhow::anyhow!("Failed to create credential manager: {}", e))?
+            .with_settings(&config.credentials));
+        let object_cache = config
+            .object_cache
+            .clone()
+            .map(|root| Arc::new(ObjectCache::new(root, Arc::clone(&credentials))));
+        let labels = repo_paths
+            .iter()
+            .map(|path| (path.clone(), repo_name(path)))
+            .collect();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(config.concurrency.jobs.unwrap_or(0))
+            .build()?;
+        let hosts = HostLimiter::new(config.concurrency.host_jobs.clone());
+        let updater = Self {
+            config,
+            credentials,
+            object_cache,
+            progress: None,
+            labels,
+            depths: Vec::new(),
+            events: None,
+            cancel: CancelToken::new(),
+            pool,
+            hosts,
+        };
+        Ok(updater.with_progress_display())
+    }
+
+    /// Show `labels` (e.g. plugin names) instead of directory names, in the order of the paths
+    pub fn with_labels(mut self, repo_paths: &[PathBuf], labels: Vec<String>) -> Self {
+        self.labels = repo_paths.iter().cloned().zip(labels).collect();
+        self.with_progress_display()
+    }
+
+    /// Depth to keep for each shallow clone, in the order of the paths; clones without one
+    /// keep their current depth
+    pub fn with_depths(mut self, repo_paths: &[PathBuf], depths: Vec<Option<u32>>) -> Self {
+        self.depths = repo_paths
+            .iter()
+            .zip(depths)
+            .filter_map(|(path, depth)| Some((path.clone(), depth?)))
+            .collect();
+        self
+    }
+
+    /// Report every step of every repository to an event stream
+    pub fn with_events(mut self, events: Option<Arc<EventStream>>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Stop early once `cancel` is set: repositories not started yet are skipped, fetches in
+    /// flight are aborted and fetched commits are not merged. A merge in progress always finishes.
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// The live progress table, if `UpdateConfig::progress` asked for one
+    pub fn progress(&self) -> Option<Arc<ProgressDisplay>> {
+        self.progress.clone()
+    }
+
+    fn emit(&self, event: UpdateEvent) {
+        if let Some(ref events) = self.events {
+            events.emit(event);
+        }
+    }
+
+    fn with_progress_display(mut self) -> Self {
+        if self.config.progress {
+            let names = self.labels.iter().map(|(_, label)| label.clone()).collect();
+            self.progress = Some(Arc::new(ProgressDisplay::new(names)));
+        }
+        self
+    }
+
+    /// Row of `path` in the progress display
+    fn row(&self, path: &Path) -> Option<usize> {
+        self.labels.iter().position(|(p, _)| p == path)
+    }
+
+    fn label(&self, path: &Path) -> String {
+        self.labels
+            .iter()
+            .find(|(p, _)| p == path)
+            .map(|(_, label)| label.clone())
+            .unwrap_or_else(|| repo_name(path))
+    }
+
+    fn open(&self, path: &Path) -> Result<RepoUpdater, git2::Error> {
+        let updater = RepoUpdater::new(path.to_path_buf(), self.credentials.clone())?
+            .with_object_cache(self.object_cache.clone())
+            .with_cancel(self.cancel.clone())
+            .with_fetch_settings(self.config.fetch.clone())
+            .with_depth(self.depths.iter().find(|(p, _)| p == path).map(|(_, depth)| *depth));
+        Ok(match (&self.progress, self.row(path)) {
+            (Some(progress), Some(row)) => {
+                let progress = Arc::clone(progress);
+                updater.with_progress(Box::new(move |transfer| progress.update_transfer(row, transfer)))
+            }
+            _ => updater,
+        })
+    }
+
+    /// Show a status change, either in the progress display or as a line of its own
+    fn report(&self, path: &Path, status: RepoStatus) {
+        if let Some(ref progress) = self.progress {
+            if let Some(row) = self.row(path) {
+                progress.update_status(row, status);
+            }
+            return;
+        }
+        if self.config.quiet {
+            return;
+        }
+        match status {
+            RepoStatus::Fetching if self.config.fetch_only => {
+                eprintln!("{} Fetching {}...", "🔄".cyan(), path.display())
+            }
+            RepoStatus::Fetching => eprintln!("{} Updating {}...", "🔄".cyan(), path.display()),
+            RepoStatus::Success => eprintln!("{} {} - Updated successfully", "✓".green(), path.display()),
+            RepoStatus::Skipped(ref reason) => eprintln!("{} {} - {}", "⏭".yellow(), path.display(), reason),
+            RepoStatus::Failed(ref message) => eprintln!("{} {} - {}", "✗".red(), path.display(), message),
+            _ => {}
+        }
+    }
+
+    fn job(&self, index: usize, path: &Path, updater: RepoUpdater, start: Instant) -> Job {
+        let host = updater.remote_url().map(|url| host_of(&url)).unwrap_or_default();
+        Job { index, path: path.to_path_buf(), host, old_head: updater.head_id(), updater, start }
+    }
+
+    /// Run `job` once its host has a free fetch slot. Over the limit it is shown as waiting and
+    /// queued, leaving the worker to other repositories, until a job of the same host finishes
+    /// fetching and hands over its slot.
+    fn dispatch<'s, T, F, D>(&'s self, scope: &rayon::Scope<'s>, job: Job, fetch: &'s F, done: &'s D)
+    where
+        F: Fn(&mut Job) -> T + Sync,
+        D: Fn(Job, T) + Sync,
+    {
+        let (path, host) = (job.path.clone(), job.host.clone());
+        let job = self.hosts.submit(&host, job, || {
+            self.report(&path, RepoStatus::Waiting);
+            self.emit(UpdateEvent::Waiting { plugin: self.label(&path), host: host.clone() });
+        });
+        if let Some(job) = job {
+            self.run(scope, job, fetch, done);
+        }
+    }
+
+    /// `fetch` runs while `job` holds the slot of its host, `done` once it was given back
+    fn run<'s, T, F, D>(&'s self, scope: &rayon::Scope<'s>, mut job: Job, fetch: &'s F, done: &'s D)
+    where
+        F: Fn(&mut Job) -> T + Sync,
+        D: Fn(Job, T) + Sync,
+    {
+        let fetched = fetch(&mut job);
+        // Merging is local, let the next repository on this host start fetching
+        if let Some(next) = self.hosts.release(&job.host) {
+            scope.spawn(move |scope| self.run(scope, next, fetch, done));
+        }
+        done(job, fetched);
+    }
+
+    /// Prepare every repository in parallel and fetch the ones that are ready within the limits
+    /// of their host. Results are in the order of `repo_paths`.
+    fn run_all<R, T>(
+        &self,
+        repo_paths: &[PathBuf],
+        prepare: impl Fn(usize, &Path) -> Prepared<R> + Sync,
+        fetch: impl Fn(&mut Job) -> T + Sync,
+        done: impl Fn(Job, T) -> R + Sync,
+    ) -> Vec<R>
+    where
+        R: Send,
+    {
+        let results: Vec<Mutex<Option<R>>> = repo_paths.iter().map(|_| Mutex::new(None)).collect();
+        let store = |index: usize, result: R| {
+            if let Ok(mut slot) = results[index].lock() {
+                *slot = Some(result);
+            }
+        };
+        let done = |job: Job, fetched: T| {
+            let index = job.index;
+            store(index, done(job, fetched));
+        };
+
+        self.pool.install(|| {
+            rayon::scope(|scope| {
+                for (index, path) in repo_paths.iter().enumerate() {
+                    let (prepare, fetch, done, store) = (&prepare, &fetch, &done, &store);
+                    scope.spawn(move |scope| match prepare(index, path) {
+                        Prepared::Ready(job) => self.dispatch(scope, job, fetch, done),
+                        Prepared::Finished(result) => store(index, result),
+                    });
+                }
+            })
+        });
+        results.into_iter().filter_map(|slot| slot.into_inner().ok().flatten()).collect()
+    }
+
+    fn start(&self) {
+        if let Some(ref progress) = self.progress {
+            progress.initial_draw();
+        }
+    }
+
+    /// Fetch and merge every repository in parallel (fetch only with `fetch_only`)
+    pub fn update_all(&self, repo_paths: &[PathBuf]) -> BatchUpdateResults {
+        let start = Instant::now();
+        self.start();
+        let results = self.run_all(
+            repo_paths,
+            |index, path| self.prepare_update(index, path),
+            |job: &mut Job| {
+                self.begin_fetch(job)?;
+                self.emit(UpdateEvent::Fetching { plugin: self.label(&job.path) });
+                job.updater.fetch()
+            },
+            |job, fetched| self.merge(job, fetched),
+        );
+
+        BatchUpdateResults::new(results, start.elapsed())
+    }
+    
+    /// Fetch phase: fetch every repository in parallel without merging anything
+    pub fn fetch_all(&self, repo_paths: &[PathBuf]) -> Vec<FetchResult> {
+        self.start();
+        self.run_all(
+            repo_paths,
+            |index, path| self.prepare_fetch(index, path),
+            |job: &mut Job| {
+                self.begin_fetch(job)?;
+                job.updater.fetch()
+            },
+            |job, fetched| self.fetched(job, fetched),
+        )
+    }
+
+    /// Apply phase: merge a previously fetched commit into each repository in parallel
+    pub fn apply_all(&self, targets: &[(PathBuf, git2::Oid)]) -> BatchUpdateResults {
+        let start = Instant::now();
+        let results: Vec<UpdateResult> = self.pool.install(|| {
+            targets
+                .par_iter()
+                .map(|(path, target)| self.apply_single(path, *target))
+                .collect()
+        });
+
+        BatchUpdateResults::new(results, start.elapsed())
+    }
+
+    /// Start of the fetch of a job that just got its slot, unless the batch was cancelled meanwhile
+    fn begin_fetch(&self, job: &Job) -> Result<(), git2::Error> {
+        if self.cancel.is_cancelled() {
+            return Err(git2::Error::from_str(INTERRUPTED));
+        }
+        self.report(&job.path, RepoStatus::Fetching);
+        Ok(())
+    }
+
+    fn prepare_fetch(&self, index: usize, path: &Path) -> Prepared<FetchResult> {
+        let start = Instant::now();
+        if self.cancel.is_cancelled() {
+            return Prepared::Finished(self.fetch_failed(path, INTERRUPTED.to_string(), start));
+        }
+        match self.open(path) {
+            Ok(updater) => Prepared::Ready(self.job(index, path, updater, start)),
+            Err(e) => Prepared::Finished(self.fetch_failed(path, format!("Failed to open repository: {}", e), start)),
+        }
+    }
+
+    fn fetched(&self, job: Job, fetched: Fetched) -> FetchResult {
+        match fetched {
+            Ok((info, pending)) => {
+                if self.progress.is_some() {
+                    self.report(&job.path, RepoStatus::Success);
+                }
+                FetchResult {
+                    repo_path: job.path,
+                    success: true,
+                    fetch_info: Some(info),
+                    pending,
+                    duration: job.start.elapsed(),
+                    error: None,
+                }
+            }
+            Err(_) if self.cancel.is_cancelled() => self.fetch_failed(&job.path, INTERRUPTED.to_string(), job.start),
+            Err(e) => self.fetch_failed(&job.path, e.to_string(), job.start),
+        }
+    }
+
+    fn fetch_failed(&self, path: &Path, message: String, start: Instant) -> FetchResult {
+        self.report(path, RepoStatus::Failed(message.clone()));
+        FetchResult {
+            repo_path: path.to_path_buf(),
+            success: false,
+            fetch_info: None,
+            pending: None,
+            duration: start.elapsed(),
+            error: Some(message),
+        }
+    }
+
+    fn apply_single(&self, repo_path: &Path, target: git2::Oid) -> UpdateResult {
+        let start = Instant::now();
+        let path = repo_path.to_path_buf();
+        if self.cancel.is_cancelled() {
+            return self.interrupted(path, None, start);
+        }
+
+        let outcome = self.open(&path).and_then(|mut updater| {
+            let old_head = updater.head_id();
+            let merge = updater.apply(target)?;
+            Ok((updater.branch()?, merge, old_head, updater.head_id()))
+        });
+
+        match outcome {
+            Ok((branch, merge, old_head, head)) => {
+                let mut result = self.finish(path, branch, None, Some(merge), head, start);
+                result.old_head = old_head.map(|oid| oid.to_string());
+                result
+            }
+            Err(e) => self.fail(path, e.to_string(), start),
+        }
+    }
+
+    /// Open a repository for `update_all` and announce it. Repositories that have nothing to
+    /// fetch come back as their result.
+    fn prepare_update(&self, index: usize, repo_path: &Path) -> Prepared<UpdateResult> {
+        let start = Instant::now();
+        let path = repo_path.to_path_buf();
+        let plugin = self.label(&path);
+        if self.cancel.is_cancelled() {
+            return Prepared::Finished(self.interrupted(path, None, start));
+        }
+
+        let updater = match self.open(&path) {
+            Ok(updater) => updater,
+            Err(e) => {
+                self.emit(UpdateEvent::Started {
+                    plugin,
+                    path: path.display().to_string(),
+                    branch: None,
+                    head: None,
+                });
+                return Prepared::Finished(self.fail(path, format!("Failed to open repository: {}", e), start));
+            }
+        };
+
+        let old_head = updater.head_id();
+        self.emit(UpdateEvent::Started {
+            plugin,
+            path: path.display().to_string(),
+            branch: updater.branch().ok(),
+            head: old_head.map(|oid| oid.to_string()),
+        });
+
+        match updater.upstream() {
+            Ok(Some(_)) => Prepared::Ready(self.job(index, &path, updater, start)),
+            Ok(None) => {
+                let branch = updater.branch().unwrap_or_default();
+                let merge = MergeInfo::new(MergeType::NoUpstream);
+                let mut result = self.finish(path, branch, None, Some(merge), old_head, start);
+                result.old_head = result.new_head.clone();
+                Prepared::Finished(result)
+            }
+            Err(e) => Prepared::Finished(self.fail(path, e.to_string(), start)),
+        }
+    }
+
+    /// Merge what the job fetched, after it gave its fetch slot back
+    fn merge(&self, job: Job, fetched: Fetched) -> UpdateResult {
+        let Job { path, mut updater, old_head, start, .. } = job;
+        let plugin = self.label(&path);
+        let outcome = fetched.and_then(|(info, pending)| {
+            if self.cancel.is_cancelled() {
+                return Ok(None);
+            }
+            let branch = updater.branch()?;
+            self.emit(UpdateEvent::Received {
+                plugin: plugin.clone(),
+                objects: info.objects_received,
+                bytes: info.bytes_received,
+            });
+
+            let merge = match pending {
+                _ if self.config.fetch_only => None,
+                Some(pending) => {
+                    self.report(&path, RepoStatus::Merging);
+                    self.emit(UpdateEvent::Merging {
+                        plugin: plugin.clone(),
+                        from: pending.local.to_string(),
+                        to: pending.remote.to_string(),
+                    });
+                    Some(updater.apply(pending.remote)?)
+                }
+                None => Some(MergeInfo::new(MergeType::UpToDate)),
+            };
+            Ok(Some((branch, info, merge)))
+        });
+
+        let mut result = match outcome {
+            Ok(Some((branch, info, merge))) => {
+                let head = updater.head_id();
+                self.finish(path, branch, Some(info), merge, head, start)
+            }
+            // Either nothing was merged or the fetch was aborted, so the tree is untouched
+            Ok(None) => return self.interrupted(path, old_head, start),
+            Err(_) if self.cancel.is_cancelled() => return self.interrupted(path, old_head, start),
+            Err(e) => self.fail(path, e.to_string(), start),
+        };
+        result.old_head = old_head.map(|oid| oid.to_string());
+        result
+    }
+
+    /// Result for a repository that was skipped or stopped before changing anything
+    fn interrupted(&self, path: PathBuf, head: Option<git2::Oid>, start: Instant) -> UpdateResult {
+        self.report(&path, RepoStatus::Failed(INTERRUPTED.to_string()));
+        self.emit(UpdateEvent::Interrupted {
+            plugin: self.label(&path),
+            duration_ms: millis(start.elapsed()),
+        });
+        let mut result = UpdateResult::failed(path, INTERRUPTED.to_string(), start.elapsed());
+        result.old_head = head.map(|oid| oid.to_string());
+        result.new_head = result.old_head.clone();
+        result.interrupted = true;
+        result
+    }
+
+    fn finish(
+        &self,
+        path: PathBuf,
+        branch: String,
+        fetch_info: Option<FetchInfo>,
+        merge_info: Option<MergeInfo>,
+        head: Option<git2::Oid>,
+        start: Instant,
+    ) -> UpdateResult {
+        let conflicts = merge_info.as_ref().is_some_and(|m| m.conflicts);
+        let error = conflicts.then(|| "Merge conflicts, local branch left unchanged".to_string());
+        let duration = start.elapsed();
+        match error {
+            Some(ref message) => {
+                self.report(&path, RepoStatus::Failed(message.clone()));
+                self.emit(UpdateEvent::Failed {
+                    plugin: self.label(&path),
+                    error: message.clone(),
+                    duration_ms: millis(duration),
+                });
+            }
+            None if merge_info.as_ref().is_some_and(|m| m.merge_type == MergeType::NoUpstream) => {
+                self.report(&path, RepoStatus::Skipped(no_upstream_message(&branch)));
+                self.emit(UpdateEvent::Done {
+                    plugin: self.label(&path),
+                    merge_type: Some(MergeType::NoUpstream),
+                    head: head.map(|oid| oid.to_string()),
+                    duration_ms: millis(duration),
+                });
+            }
+            None => {
+                self.report(&path, RepoStatus::Success);
+                self.emit(UpdateEvent::Done {
+                    plugin: self.label(&path),
+                    merge_type: merge_info.as_ref().map(|m| m.merge_type),
+                    head: head.map(|oid| oid.to_string()),
+                    duration_ms: millis(duration),
+                });
+            }
+        }
+        UpdateResult {
+            repo_path: path,
+            branch,
+            success: !conflicts,
+            fetch_info,
+            merge_info,
+            duration,
+            error,
+            old_head: None,
+            new_head: head.map(|oid| oid.to_string()),
+            interrupted: false,
+            builds: Vec::new(),
+        }
+    }
+
+    fn fail(&self, path: PathBuf, message: String, start: Instant) -> UpdateResult {
+        self.report(&path, RepoStatus::Failed(message.clone()));
+        self.emit(UpdateEvent::Failed {
+            plugin: self.label(&path),
+            error: message.clone(),
+            duration_ms: millis(start.elapsed()),
+        });
+        UpdateResult::failed(path, message, start.elapsed())
+    }
+}
+
+const INTERRUPTED: &str = "Interrupted";
+
+/// Name shown for a repository in the progress display
+fn repo_name(path: &Path) -> String {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{clone_repo, commit_file, init_repo, SharedBuffer, TempDir};
+
+    #[test]
+    fn update_all_reports_each_repository() {
+        let tmp = TempDir::new("engine-batch");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        let remote = commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        let paths = vec![tmp.path().join("clone"), tmp.path().join("missing")];
+
+        let config = Arc::new(UpdateConfig::new(true, false));
+        let results = BatchUpdater::new(config, &paths).unwrap().update_all(&paths);
+
+        assert_eq!((results.total, results.successful, results.failed), (2, 1, 1));
+        let updated = &results.results[0];
+        assert_eq!(updated.branch, "main");
+        assert_eq!(updated.merge_info.as_ref().unwrap().merge_type, MergeType::FastForward);
+        assert_eq!(clone.head().unwrap().target(), Some(remote));
+        assert!(results.results[1].error.as_ref().unwrap().starts_with("Failed to open repository"));
+    }
+
+    #[test]
+    fn fetch_only_leaves_the_working_tree_alone() {
+        let tmp = TempDir::new("engine-batch");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        let local = clone.head().unwrap().target();
+        let remote = commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        let paths = vec![tmp.path().join("clone")];
+
+        let config = Arc::new(UpdateConfig::new(true, false).with_fetch_only(true));
+        let updater = BatchUpdater::new(config, &paths).unwrap();
+        let results = updater.update_all(&paths);
+
+        assert!(results.results[0].success);
+        assert!(results.results[0].merge_info.is_none());
+        assert_eq!(clone.head().unwrap().target(), local);
+
+        // The two-phase API applies what an earlier fetch found
+        let fetched = updater.fetch_all(&paths);
+        assert_eq!(fetched[0].pending.as_ref().unwrap().remote, remote);
+        let applied = updater.apply_all(&[(paths[0].clone(), remote)]);
+        assert_eq!(applied.successful, 1);
+        assert_eq!(clone.head().unwrap().target(), Some(remote));
+    }
+
+    #[test]
+    fn events_follow_each_repository_under_its_label() {
+        let tmp = TempDir::new("engine-batch");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        let local = clone.head().unwrap().target().unwrap();
+        let remote = commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        let paths = vec![tmp.path().join("clone")];
+
+        let buffer = SharedBuffer::default();
+        let events = Arc::new(EventStream::new(Box::new(buffer.clone())));
+        BatchUpdater::new(Arc::new(UpdateConfig::new(true, false)), &paths)
+            .unwrap()
+            .with_labels(&paths, vec!["u/plugin".to_string()])
+            .with_events(Some(events))
+            .update_all(&paths);
+
+        let lines = buffer.json_lines();
+        let kinds: Vec<_> = lines.iter().map(|l| l["event"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["started", "fetching", "received", "merging", "done"]);
+        assert!(lines.iter().all(|l| l["plugin"] == "u/plugin"));
+        assert_eq!(lines[0]["branch"], "main");
+        assert_eq!(lines[0]["head"], local.to_string());
+        assert_eq!(lines[3]["from"], local.to_string());
+        assert_eq!(lines[3]["to"], remote.to_string());
+        assert_eq!(lines[4]["head"], remote.to_string());
+    }
+
+    #[test]
+    fn cancelled_batches_leave_repositories_untouched() {
+        let tmp = TempDir::new("engine-batch");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        let local = clone.head().unwrap().target().unwrap();
+        commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        let paths = vec![tmp.path().join("clone")];
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let buffer = SharedBuffer::default();
+        let results = BatchUpdater::new(Arc::new(UpdateConfig::new(true, false)), &paths)
+            .unwrap()
+            .with_events(Some(Arc::new(EventStream::new(Box::new(buffer.clone())))))
+            .with_cancel(cancel)
+            .update_all(&paths);
+
+        assert_eq!(results.interrupted(), 1);
+        let result = &results.results[0];
+        assert!(result.interrupted && !result.success);
+        assert_eq!(result.error.as_deref(), Some(INTERRUPTED));
+        assert_eq!(clone.head().unwrap().target(), Some(local));
+        assert_eq!(buffer.json_lines()[0]["event"], "interrupted");
+    }
+
+    #[test]
+    fn repositories_without_upstream_are_skipped() {
+        let tmp = TempDir::new("engine-batch");
+        init_repo(&tmp.path().join("local"));
+        let paths = vec![tmp.path().join("local")];
+
+        let config = Arc::new(UpdateConfig::new(true, false));
+        let results = BatchUpdater::new(config, &paths).unwrap().update_all(&paths);
+
+        let result = &results.results[0];
+        assert!(result.success);
+        assert_eq!(result.merge_info.as_ref().unwrap().merge_type, MergeType::NoUpstream);
+        assert_eq!(result.old_head, result.new_head);
+    }
+
+    #[test]
+    fn repositories_over_the_host_limit_are_fetched_in_turn() {
+        let tmp = TempDir::new("engine-batch");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let paths: Vec<_> = (0..4).map(|i| tmp.path().join(format!("clone{}", i))).collect();
+        let clones: Vec<_> = paths.iter().map(|path| clone_repo(&tmp.path().join("upstream"), path)).collect();
+        let remote = commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+
+        let concurrency = ConcurrencySettings {
+            jobs: Some(2),
+            host_jobs: [("*".to_string(), 1)].into(),
+        };
+        let buffer = SharedBuffer::default();
+        let config = Arc::new(UpdateConfig::new(true, false).with_concurrency(concurrency));
+        let results = BatchUpdater::new(config, &paths)
+            .unwrap()
+            .with_events(Some(Arc::new(EventStream::new(Box::new(buffer.clone())))))
+            .update_all(&paths);
+
+        assert_eq!(results.successful, 4);
+        for (clone, result) in clones.iter().zip(&results.results) {
+            assert_eq!(clone.head().unwrap().target(), Some(remote));
+            assert_eq!(result.new_head, Some(remote.to_string()));
+        }
+        let lines = buffer.json_lines();
+        assert_eq!(lines.iter().filter(|l| l["event"] == "done").count(), 4);
+    }
+
+    #[test]
+    fn repositories_with_the_same_name_get_their_own_row() {
+        let paths = vec![PathBuf::from("/a/foo"), PathBuf::from("/b/foo")];
+        let updater = BatchUpdater::new(Arc::new(UpdateConfig::new(true, false)), &paths).unwrap();
+
+        assert_eq!(updater.label(&paths[0]), updater.label(&paths[1]));
+        assert_eq!(updater.row(&paths[0]), Some(0));
+        assert_eq!(updater.row(&paths[1]), Some(1));
+        assert_eq!(updater.row(Path::new("/c/foo")), None);
+    }
+}
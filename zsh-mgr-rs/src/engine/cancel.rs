@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag telling a running batch to stop, e.g. set from a Ctrl-C handler.
+///
+/// Repositories that have not started are skipped and fetches in flight are aborted, but a
+/// merge that already stashed local changes always runs to the end so the stash is restored.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_the_flag() {
+        let token = CancelToken::new();
+        let worker = token.clone();
+        assert!(!worker.is_cancelled());
+
+        token.cancel();
+        assert!(worker.is_cancelled());
+        assert!(!CancelToken::new().is_cancelled());
+    }
+}
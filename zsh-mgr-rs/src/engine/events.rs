@@ -0,0 +1,197 @@
+use serde::{Serialize, Serializer};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::result::MergeType;
+
+/// Version of the event schema, bumped whenever a field is removed or changes meaning.
+/// Adding fields or event types does not change it, so consumers should ignore unknown ones.
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+/// Something that happened while updating one plugin
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum UpdateEvent {
+    /// The plugin was picked up; `head` is the commit checked out before the update
+    Started {
+        plugin: String,
+        path: String,
+        branch: Option<String>,
+        head: Option<String>,
+    },
+    /// Queued because `host` already has as many fetches running as allowed
+    Waiting {
+        plugin: String,
+        host: String,
+    },
+    Fetching {
+        plugin: String,
+    },
+    /// The fetch finished
+    Received {
+        plugin: String,
+        objects: usize,
+        bytes: usize,
+    },
+    /// New commits are being merged, moving the branch from `from` to `to`
+    Merging {
+        plugin: String,
+        from: String,
+        to: String,
+    },
+    Done {
+        plugin: String,
+        #[serde(serialize_with = "merge_type_name")]
+        merge_type: Option<MergeType>,
+        head: Option<String>,
+        duration_ms: u64,
+    },
+    Failed {
+        plugin: String,
+        error: String,
+        duration_ms: u64,
+    },
+    /// The update was cancelled before this plugin changed; its working tree is untouched
+    Interrupted {
+        plugin: String,
+        duration_ms: u64,
+    },
+    /// The build commands of a plugin ran after its HEAD changed
+    Built {
+        plugin: String,
+        success: bool,
+        error: Option<String>,
+        duration_ms: u64,
+    },
+}
+
+/// One line of the stream: the schema version and a timestamp around the event itself
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    v: u32,
+    /// Milliseconds since the Unix epoch
+    ts: u64,
+    #[serde(flatten)]
+    event: &'a UpdateEvent,
+}
+
+/// Writes update events as newline-delimited JSON, one object per line, flushed as they happen
+pub struct EventStream {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl EventStream {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self { out: Mutex::new(out) }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(Box::new(io::stdout()))
+    }
+
+    pub fn emit(&self, event: UpdateEvent) {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let record = EventRecord { v: EVENT_SCHEMA_VERSION, ts, event: &event };
+
+        let Ok(line) = serde_json::to_string(&record) else {
+            return;
+        };
+        if let Ok(mut out) = self.out.lock() {
+            // A closed pipe (e.g. the widget exited) must not abort the update
+            let _ = writeln!(out, "{}", line).and_then(|_| out.flush());
+        }
+    }
+}
+
+/// Merge types are written in snake_case like the rest of the event, unlike in `--json` output
+fn merge_type_name<S: Serializer>(merge_type: &Option<MergeType>, serializer: S) -> Result<S::Ok, S::Error> {
+    let name = merge_type.map(|merge_type| match merge_type {
+        MergeType::FastForward => "fast_forward",
+        MergeType::Normal => "normal",
+        MergeType::Reset => "reset",
+        MergeType::UpToDate => "up_to_date",
+        MergeType::NoUpstream => "no_upstream",
+        MergeType::Replaced => "replaced",
+        MergeType::Skipped => "skipped",
+        MergeType::None => "none",
+    });
+    name.serialize(serializer)
+}
+
+pub(crate) fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::SharedBuffer;
+
+    #[test]
+    fn each_event_is_one_versioned_json_line() {
+        let buffer = SharedBuffer::default();
+        let stream = EventStream::new(Box::new(buffer.clone()));
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+
+        stream.emit(UpdateEvent::Received { plugin: "u/repo".to_string(), objects: 3, bytes: 512 });
+        stream.emit(UpdateEvent::Failed {
+            plugin: "u/other".to_string(),
+            error: "line one\nline two".to_string(),
+            duration_ms: 7,
+        });
+
+        let text = buffer.contents();
+        assert_eq!(text.lines().count(), 2);
+        let lines = buffer.json_lines();
+
+        assert_eq!(lines[0]["v"], EVENT_SCHEMA_VERSION);
+        assert!(lines[0]["ts"].as_u64().unwrap() >= before);
+        assert_eq!(lines[0]["event"], "received");
+        assert_eq!(lines[0]["plugin"], "u/repo");
+        assert_eq!(lines[0]["objects"], 3);
+        assert_eq!(lines[0]["bytes"], 512);
+
+        assert_eq!(lines[1]["event"], "failed");
+        assert_eq!(lines[1]["error"], "line one\nline two");
+        assert_eq!(lines[1]["duration_ms"], 7);
+    }
+
+    #[test]
+    fn optional_fields_are_written_as_null() {
+        let buffer = SharedBuffer::default();
+        EventStream::new(Box::new(buffer.clone())).emit(UpdateEvent::Started {
+            plugin: "u/repo".to_string(),
+            path: "/plugins/u/repo".to_string(),
+            branch: None,
+            head: None,
+        });
+
+        let line = &buffer.json_lines()[0];
+        assert_eq!(line["event"], "started");
+        assert!(line["branch"].is_null());
+        assert!(line["head"].is_null());
+    }
+
+    #[test]
+    fn merge_types_are_written_in_snake_case() {
+        let buffer = SharedBuffer::default();
+        let stream = EventStream::new(Box::new(buffer.clone()));
+        for merge_type in [Some(MergeType::FastForward), Some(MergeType::NoUpstream), None] {
+            stream.emit(UpdateEvent::Done {
+                plugin: "u/repo".to_string(),
+                merge_type,
+                head: None,
+                duration_ms: 0,
+            });
+        }
+
+        let lines = buffer.json_lines();
+        assert_eq!(lines[0]["merge_type"], "fast_forward");
+        assert_eq!(lines[1]["merge_type"], "no_upstream");
+        assert!(lines[2]["merge_type"].is_null());
+    }
+}
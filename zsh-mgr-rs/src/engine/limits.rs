@@ -0,0 +1,127 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::ssh_config::wildcard_match;
+
+/// Caps the number of concurrent fetches per host, e.g. at most 4 to `github.com`. Jobs over the
+/// limit wait in a queue of their host rather than blocking a worker thread, and take over the
+/// slot of the next job of that host to finish fetching.
+#[derive(Debug)]
+pub struct HostLimiter<J> {
+    /// Maximum per host pattern (`"github.com"`, `"*.corp"`, `"*"`); hosts matching none are unlimited
+    limits: BTreeMap<String, usize>,
+    hosts: Mutex<HashMap<String, HostQueue<J>>>,
+}
+
+/// Jobs fetching from one host and the ones waiting for a slot
+#[derive(Debug)]
+struct HostQueue<J> {
+    active: usize,
+    waiting: VecDeque<J>,
+}
+
+impl<J> Default for HostQueue<J> {
+    fn default() -> Self {
+        Self { active: 0, waiting: VecDeque::new() }
+    }
+}
+
+impl<J> HostLimiter<J> {
+    pub fn new(limits: BTreeMap<String, usize>) -> Self {
+        Self { limits, hosts: Mutex::new(HashMap::new()) }
+    }
+
+    /// Limit for `host`: an exact entry wins, then the most specific matching pattern, i.e. the
+    /// one with the most literal characters, so `"*.corp"` applies before `"*"`
+    fn limit(&self, host: &str) -> Option<usize> {
+        let literals = |pattern: &str| pattern.chars().filter(|c| !matches!(c, '*' | '?')).count();
+        self.limits
+            .iter()
+            .find(|(pattern, _)| pattern.eq_ignore_ascii_case(host))
+            .or_else(|| {
+                self.limits
+                    .iter()
+                    .filter(|(pattern, _)| wildcard_match(pattern, host))
+                    .max_by_key(|(pattern, _)| literals(pattern))
+            })
+            .map(|(_, limit)| (*limit).max(1))
+    }
+
+    /// Take a slot for `host` and hand `job` back to be run now, or queue it if the host is at
+    /// its limit. `on_wait` runs before the job is queued.
+    pub fn submit(&self, host: &str, job: J, on_wait: impl FnOnce()) -> Option<J> {
+        let limit = self.limit(host);
+        let Ok(mut hosts) = self.hosts.lock() else {
+            return Some(job);
+        };
+        let queue = hosts.entry(host.to_string()).or_default();
+        if limit.is_some_and(|limit| queue.active >= limit) {
+            on_wait();
+            queue.waiting.push_back(job);
+            return None;
+        }
+        queue.active += 1;
+        Some(job)
+    }
+
+    /// Give back a slot of `host`. The job queued first takes it over and is returned to be run.
+    pub fn release(&self, host: &str) -> Option<J> {
+        let mut hosts = self.hosts.lock().ok()?;
+        let queue = hosts.get_mut(host)?;
+        let next = queue.waiting.pop_front();
+        if next.is_none() {
+            queue.active = queue.active.saturating_sub(1);
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(limits: &[(&str, usize)]) -> HostLimiter<u32> {
+        HostLimiter::new(limits.iter().map(|(p, l)| (p.to_string(), *l)).collect())
+    }
+
+    #[test]
+    fn exact_hosts_win_over_patterns() {
+        let limiter = limiter(&[("*.example.com", 2), ("git.example.com", 5), ("zero.com", 0)]);
+        assert_eq!(limiter.limit("git.example.com"), Some(5));
+        assert_eq!(limiter.limit("GIT.example.com"), Some(5));
+        assert_eq!(limiter.limit("other.example.com"), Some(2));
+        assert_eq!(limiter.limit("zero.com"), Some(1));
+        assert_eq!(limiter.limit("github.com"), None);
+    }
+
+    #[test]
+    fn the_most_specific_pattern_wins() {
+        let limiter = limiter(&[("*", 8), ("*.corp", 2), ("git?.eu.corp", 1)]);
+        assert_eq!(limiter.limit("git.corp"), Some(2));
+        assert_eq!(limiter.limit("git1.eu.corp"), Some(1));
+        assert_eq!(limiter.limit("github.com"), Some(8));
+    }
+
+    #[test]
+    fn jobs_over_the_limit_are_queued_and_take_over_freed_slots() {
+        let limiter = limiter(&[("github.com", 2)]);
+        let never = || panic!("should not wait");
+
+        assert_eq!(limiter.submit("github.com", 1, never), Some(1));
+        assert_eq!(limiter.submit("github.com", 2, never), Some(2));
+        // Unlimited hosts never wait
+        assert_eq!(limiter.submit("gitlab.com", 3, never), Some(3));
+        let mut waited = 0;
+        assert_eq!(limiter.submit("github.com", 4, || waited += 1), None);
+        assert_eq!(limiter.submit("github.com", 5, || waited += 1), None);
+        assert_eq!(waited, 2);
+
+        // Queued jobs run in order, each in the slot the previous one gave back
+        assert_eq!(limiter.release("github.com"), Some(4));
+        assert_eq!(limiter.release("github.com"), Some(5));
+        assert_eq!(limiter.hosts.lock().unwrap()["github.com"].active, 2);
+        assert_eq!(limiter.release("github.com"), None);
+        assert_eq!(limiter.hosts.lock().unwrap()["github.com"].active, 1);
+        assert_eq!(limiter.submit("github.com", 6, never), Some(6));
+    }
+}
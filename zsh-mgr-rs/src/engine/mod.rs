@@ -0,0 +1,13 @@
+mod batch;
+mod cancel;
+mod events;
+mod limits;
+mod repo;
+mod result;
+
+pub use batch::{BatchUpdater, UpdateConfig};
+pub use cancel::CancelToken;
+pub use events::{EventStream, UpdateEvent, EVENT_SCHEMA_VERSION};
+pub use repo::{PendingChanges, RepoUpdater, TransferProgress, Upstream, REMOTE_OVERRIDE_KEY};
+pub(crate) use result::duration_secs;
+pub use result::{BatchUpdateResults, FetchInfo, FetchResult, MergeInfo, MergeType, UpdateResult};
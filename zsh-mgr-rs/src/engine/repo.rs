@@ -0,0 +1,1154 @@
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+use git2::{Error, FetchOptions, Oid, Remote, Repository};
+use std::sync::{Arc, Mutex};
+
+use super::cancel::CancelToken;
+use super::result::{FetchInfo, MergeInfo, MergeType};
+use crate::clone::{shallow_depth, CloneProgress};
+use crate::credentials_manager::CredentialManager;
+use crate::fetch_options::{FetchOverrides, FetchSettings, TagMode};
+use crate::git_cli;
+use crate::object_cache::ObjectCache;
+
+// Taken from https://github.com/rust-lang/git2-rs/blob/master/examples/pull.rs
+
+/// Maximum number of commits kept in `PendingChanges::commits` for display
+const MAX_PENDING_COMMITS: usize = 20;
+
+/// Git config key of a plugin naming the remote to update from, overriding `branch.<name>.remote`
+pub const REMOTE_OVERRIDE_KEY: &str = "zsh-mgr.remote";
+
+/// Remote branch the checked out branch is updated from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+    pub remote: String,
+    /// Branch name on the remote, from `branch.<name>.merge`
+    pub branch: String,
+}
+
+impl Upstream {
+    fn tracking_ref(&self) -> String {
+        format!("refs/remotes/{}/{}", self.remote, self.branch)
+    }
+}
+
+/// Changes fetched from the remote that are not merged into the local branch yet
+#[derive(Debug, Clone)]
+pub struct PendingChanges {
+    pub branch: String,
+    pub local: Oid,
+    pub remote: Oid,
+    /// One-line summaries ("<short sha> <subject>"), newest first
+    pub commits: Vec<String>,
+    pub total_commits: usize,
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// Result of fetching the current branch: its name, the transfer stats and, if the fetched
+/// commit is not merged yet, the local and fetched commits
+struct Fetched {
+    branch: String,
+    info: FetchInfo,
+    target: Option<(Oid, Oid)>,
+}
+
+/// Receives transfer progress while fetching
+pub type TransferProgress = Box<dyn Fn(CloneProgress) + Send + Sync>;
+
+pub struct RepoUpdater {
+    // repo_path: PathBuf,
+    credentials: Arc<CredentialManager>,
+    object_cache: Option<Arc<ObjectCache>>,
+    progress: Option<TransferProgress>,
+    cancel: CancelToken,
+    /// Fetch settings from `config.json`, before the plugin's own overrides
+    fetch_settings: FetchSettings,
+    /// History depth to keep for a shallow clone
+    depth: Option<u32>,
+    repo: Repository,
+}
+
+impl RepoUpdater {
+    pub fn new(repo_path: PathBuf, credentials: Arc<CredentialManager>) -> Result<Self, git2::Error> {
+        let repo = Repository::open(&repo_path)?;
+        Ok(Self {
+            credentials,
+            object_cache: None,
+            progress: None,
+            cancel: CancelToken::new(),
+            fetch_settings: FetchSettings::default(),
+            depth: None,
+            repo,
+        })
+    }
+
+    /// Fetch through a shared object cache instead of directly from `origin`
+    pub fn with_object_cache(mut self, object_cache: Option<Arc<ObjectCache>>) -> Self {
+        self.object_cache = object_cache;
+        self
+    }
+
+    /// Report objects and deltas received while fetching
+    pub fn with_progress(mut self, progress: TransferProgress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Abort fetches in flight once `cancel` is set
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Prune, tag and refspec settings that apply unless the plugin overrides them
+    pub fn with_fetch_settings(mut self, fetch_settings: FetchSettings) -> Self {
+        self.fetch_settings = fetch_settings;
+        self
+    }
+
+    /// Fetch a shallow clone with this depth; without it the clone's current depth is kept
+    pub fn with_depth(mut self, depth: Option<u32>) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    fn report(&self, progress: CloneProgress) {
+        if let Some(ref report) = self.progress {
+            report(progress);
+        }
+    }
+
+    /// Fetch the upstream branch into the object cache, then update the remote's tracking refs
+    /// from it. The repository is linked to the cache first, so no objects are copied.
+    fn fetch_via_cache(
+        &self,
+        cache: &ObjectCache,
+        upstream: &Upstream,
+        settings: &FetchSettings,
+    ) -> Result<(git2::AnnotatedCommit<'_>, FetchInfo), git2::Error> {
+        // The configured URL, before insteadOf rewriting, so the cache entry matches the one used at clone time
+        let url = self.repo.config()?.get_string(&format!("remote.{}.url", upstream.remote))?;
+
+        debug!("Fetching {} through object cache", upstream.remote);
+        let identities = CredentialManager::repo_identities(&self.repo);
+        let info = Mutex::new(FetchInfo::default());
+        let report = self.progress.as_deref();
+        let cache_repo = cache.fetch(&url, &identities, &|progress| {
+            if let Some(report) = report {
+                report(progress);
+            }
+            if let CloneProgress::Receiving { received_objects, received_bytes, .. } = progress {
+                if let Ok(mut info) = info.lock() {
+                    info.objects_received = received_objects;
+                    info.bytes_received = received_bytes;
+                }
+            }
+        })?;
+        cache.link(&self.repo, &cache_repo)?;
+        ObjectCache::copy_refs(&self.repo, &cache_repo, &upstream.remote, Some(&upstream.branch))?;
+        if settings.prune {
+            ObjectCache::prune_refs(&self.repo, &cache_repo, &upstream.remote)?;
+        }
+
+        let oid = self.repo.refname_to_id(&upstream.tracking_ref())?;
+        let info = info.into_inner().unwrap_or_default();
+        Ok((self.repo.find_annotated_commit(oid)?, info))
+    }
+
+    fn do_fetch<'repo>(
+        &'repo self,
+        refs: &[&str],
+        remote: &mut Remote<'repo>,
+        settings: &FetchSettings,
+        depth: Option<i32>,
+    ) -> Result<FetchInfo, git2::Error> {
+        let mut callbacks = self.credentials.create_callbacks(&self.repo);
+        callbacks.transfer_progress(|stats| {
+            if let Some(progress) = CloneProgress::from_transfer(&stats) {
+                self.report(progress);
+            }
+            // Returning false makes libgit2 abort the transfer; refs are only updated at the end
+            !self.cancel.is_cancelled()
+        });
+
+        let mut fo = FetchOptions::new();
+        fo.remote_callbacks(callbacks);
+        settings.apply(&mut fo);
+        if let Some(depth) = depth {
+            fo.depth(depth);
+        }
+        debug!("Fetching {} for repo", remote.name().unwrap_or("origin"));
+        remote.fetch(refs, Some(&mut fo), None)?;
+
+        // If there are local objects (we got a thin pack), then tell the user
+        // how many objects we saved from having to cross the network.
+        let stats = remote.stats();
+        if stats.local_objects() > 0 {
+            debug!(
+                "Received {}/{} objects in {} bytes (used {} local objects)",
+                stats.indexed_objects(),
+                stats.total_objects(),
+                stats.received_bytes(),
+                stats.local_objects()
+            );
+        } else {
+            debug!(
+                "Received {}/{} objects in {} bytes",
+                stats.indexed_objects(),
+                stats.total_objects(),
+                stats.received_bytes()
+            );
+        }
+        Ok(FetchInfo {
+            objects_received: stats.received_objects(),
+            bytes_received: stats.received_bytes(),
+        })
+    }
+
+    /// Fetch with the `git` executable, which keeps a partial clone partial. git reads
+    /// `~/.ssh/config` itself, so `url` is used as configured. Transfer stats are not available.
+    fn fetch_with_git(
+        &self,
+        url: &str,
+        remote: &str,
+        refs: &[String],
+        settings: &FetchSettings,
+        depth: Option<i32>,
+    ) -> Result<FetchInfo, Error> {
+        let mut args = vec!["fetch".to_string(), "--quiet".to_string()];
+        if settings.prune {
+            args.push("--prune".to_string());
+        }
+        match settings.tags {
+            TagMode::All => args.push("--tags".to_string()),
+            TagMode::None => args.push("--no-tags".to_string()),
+            TagMode::Follow => {}
+        }
+        if let Some(depth) = depth {
+            args.push(format!("--depth={}", depth));
+        }
+        args.push(remote.to_string());
+        args.extend(refs.iter().cloned());
+
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        debug!("Fetching {} for repo with git", remote);
+        git_cli::run(Some(self.workdir()?), &args, &self.git_env(url))?;
+        Ok(FetchInfo::default())
+    }
+
+    /// Merge with the `git` executable, which downloads missing files of a partial clone and
+    /// keeps the sparse checkout. Local changes are stashed around the merge like in `apply`.
+    fn merge_with_git(&self, target: Oid) -> Result<MergeInfo, Error> {
+        let workdir = self.workdir()?;
+        let url = self.remote_url().unwrap_or_default();
+        let env = self.git_env(&url);
+
+        let dirty = !git_cli::run(Some(workdir), &["status", "--porcelain"], &[])?.trim().is_empty();
+        if dirty {
+            debug!("Local changes detected — creating stash (include untracked)");
+            let args = ["stash", "push", "--include-untracked", "--quiet", "-m", "zsh-mgr update"];
+            git_cli::run(Some(workdir), &args, &env)?;
+        }
+
+        let result = self.git_merge(workdir, target, &env);
+
+        if dirty {
+            if let Err(e) = git_cli::run(Some(workdir), &["stash", "pop", "--quiet"], &env) {
+                // A conflicting pop keeps the stash; leave the tree clean, as libgit2 does
+                warn!("Failed to pop stash: {}. You can restore manually with 'git stash list'", e);
+                git_cli::run(Some(workdir), &["reset", "--hard", "--quiet"], &[])?;
+                return Err(e);
+            }
+        }
+        result
+    }
+
+    fn git_merge(&self, workdir: &Path, target: Oid, env: &[(String, String)]) -> Result<MergeInfo, Error> {
+        let head = self.head_id().ok_or_else(|| Error::from_str("HEAD has no target"))?;
+        let (head_hex, target_hex) = (head.to_string(), target.to_string());
+        // Asked to git, since the fetch may have changed the shallow boundary libgit2 loaded
+        let merge_base = |extra: &[&str]| {
+            let args: Vec<&str> = [&["merge-base"], extra, &[head_hex.as_str(), target_hex.as_str()]].concat();
+            git_cli::run(Some(workdir), &args, &[]).is_ok()
+        };
+
+        // Same rule as `do_merge` for shallow clones whose histories no longer connect
+        if self.is_shallow() && !merge_base(&[]) {
+            if !self.was_upstream_commit(head) {
+                return Err(Error::from_str(
+                    "Shallow history diverged from the upstream branch and the local branch has \
+                     commits of its own; run 'zsh-mgr unshallow' on the plugin and update again",
+                ));
+            }
+            git_cli::run(Some(workdir), &["reset", "--hard", "--quiet", &target_hex], env)?;
+            return Ok(MergeInfo::new(MergeType::Reset));
+        }
+
+        let merge_type = if merge_base(&["--is-ancestor"]) {
+            MergeType::FastForward
+        } else {
+            MergeType::Normal
+        };
+        match git_cli::run(Some(workdir), &["merge", "--no-edit", "--quiet", &target_hex], env) {
+            Ok(_) => Ok(MergeInfo::new(merge_type)),
+            Err(_) if self.repo.path().join("MERGE_HEAD").exists() => {
+                warn!("Merge conflicts detected in {}", self.repo.path().display());
+                git_cli::run(Some(workdir), &["merge", "--abort"], env)?;
+                Ok(MergeInfo {
+                    merge_type: MergeType::Normal,
+                    conflicts: true,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn workdir(&self) -> Result<&Path, Error> {
+        self.repo
+            .workdir()
+            .ok_or_else(|| Error::from_str("Repository has no working tree"))
+    }
+
+    /// Credentials for running git against `url`, with the plugin's own SSH keys
+    fn git_env(&self, url: &str) -> Vec<(String, String)> {
+        self.credentials
+            .git_env(url, &CredentialManager::repo_identities(&self.repo))
+    }
+
+    /// Commit FETCH_HEAD marks for merging; tags fetched along are listed there as well
+    fn fetch_head_merge(&self) -> Result<Oid, Error> {
+        let mut merge = None;
+        self.repo.fetchhead_foreach(|_, _, oid, is_merge| {
+            if is_merge && merge.is_none() {
+                merge = Some(*oid);
+            }
+            true
+        })?;
+        merge.ok_or_else(|| Error::from_str("The fetch did not return the upstream branch"))
+    }
+
+    /// Depth to fetch with: fetching a shallow clone without one would download the full
+    /// history of every new commit
+    fn fetch_depth(&self) -> Option<i32> {
+        if !self.is_shallow() {
+            return None;
+        }
+        self.depth.or_else(|| shallow_depth(&self.repo)).map(|depth| depth as i32)
+    }
+
+    fn fast_forward(
+        &self,
+        lb: &mut git2::Reference,
+        rc: &git2::AnnotatedCommit,
+    ) -> Result<(), git2::Error> {
+        let name = match lb.name() {
+            Some(s) => s.to_string(),
+            _none => String::from_utf8_lossy(lb.name_bytes()).to_string(),
+        };
+        let msg = format!("Fast-Forward: Setting {} to id: {}", name, rc.id());
+        debug!("{}", msg);
+        lb.set_target(rc.id(), &msg)?;
+        self.repo.set_head(&name)?;
+        self.repo.checkout_head(Some(
+            git2::build::CheckoutBuilder::default()
+                // For some reason the force is required to make the working directory actually get updated
+                // I suspect we should be adding some logic to handle dirty working directory states
+                // but this is just an example so maybe not.
+                .force(),
+        ))?;
+        Ok(())
+    }
+
+    /// Merge `remote` into `local` with a merge commit; returns `false` if there were conflicts
+    fn normal_merge(
+        &self,
+        local: &git2::AnnotatedCommit,
+        remote: &git2::AnnotatedCommit,
+    ) -> Result<bool, git2::Error> {
+        let local_tree = self.repo.find_commit(local.id())?.tree()?;
+        let remote_tree = self.repo.find_commit(remote.id())?.tree()?;
+        let ancestor = self.repo
+            .find_commit(self.repo.merge_base(local.id(), remote.id())?)?
+            .tree()?;
+        let mut idx = self.repo.merge_trees(&ancestor, &local_tree, &remote_tree, None)?;
+
+        if idx.has_conflicts() {
+            warn!("Merge conflicts detected in {}", self.repo.path().display());
+            self.repo.checkout_index(Some(&mut idx), None)?;
+            return Ok(false);
+        }
+        let result_tree = self.repo.find_tree(idx.write_tree_to(&self.repo)?)?;
+        // now create the merge commit
+        let msg = format!("Merge: {} into {}", remote.id(), local.id());
+        let sig = self.repo.signature()?;
+        let local_commit = self.repo.find_commit(local.id())?;
+        let remote_commit = self.repo.find_commit(remote.id())?;
+        // Do our merge commit and set current branch head to that commit.
+        let _merge_commit = self.repo.commit(
+            Some("HEAD"),
+            &sig,
+            &sig,
+            &msg,
+            &result_tree,
+            &[&local_commit, &remote_commit],
+        )?;
+        // Set working tree to match head.
+        self.repo.checkout_head(None)?;
+        Ok(true)
+    }
+
+    fn do_merge<'repo>(
+        &self,
+        remote_branch: &str,
+        fetch_commit: git2::AnnotatedCommit<'repo>,
+    ) -> Result<MergeInfo, git2::Error> {
+        // 1. do a merge analysis
+        let analysis = self.repo.merge_analysis(&[&fetch_commit])?;
+
+        // 2. Do the appropriate merge
+        if analysis.0.is_fast_forward() {
+            debug!("Doing a fast forward");
+            // do a fast forward
+            let refname = format!("refs/heads/{}", remote_branch);
+            match self.repo.find_reference(&refname) {
+                Ok(mut r) => {
+                    self.fast_forward(&mut r, &fetch_commit)?;
+                }
+                Err(_) => {
+                    // The branch doesn't exist so just set the reference to the
+                    // commit directly. Usually this is because you are pulling
+                    // into an empty repository.
+                    self.repo.reference(
+                        &refname,
+                        fetch_commit.id(),
+                        true,
+                        &format!("Setting {} to {}", remote_branch, fetch_commit.id()),
+                    )?;
+                    self.repo.set_head(&refname)?;
+                    self.repo.checkout_head(Some(
+                        git2::build::CheckoutBuilder::default()
+                            .allow_conflicts(true)
+                            .conflict_style_merge(true)
+                            .force(),
+                    ))?;
+                }
+            };
+            Ok(MergeInfo::new(MergeType::FastForward))
+        } else if analysis.0.is_normal() {
+            let head_commit = self.repo.reference_to_annotated_commit(&self.repo.head()?)?;
+            // A shallow clone may lack the commits both histories share. Resetting is only
+            // safe if the branch has no commits of its own, i.e. it is at a commit the
+            // upstream branch pointed to before.
+            if self.is_shallow() && self.repo.merge_base(head_commit.id(), fetch_commit.id()).is_err() {
+                if !self.was_upstream_commit(head_commit.id()) {
+                    return Err(Error::from_str(
+                        "Shallow history diverged from the upstream branch and the local branch has \
+                         commits of its own; run 'zsh-mgr unshallow' on the plugin and update again",
+                    ));
+                }
+                debug!("Shallow history without merge base — resetting to fetched commit");
+                let refname = format!("refs/heads/{}", remote_branch);
+                let mut reference = self.repo.find_reference(&refname)?;
+                let msg = format!("Shallow update: resetting {} to {}", refname, fetch_commit.id());
+                reference.set_target(fetch_commit.id(), &msg)?;
+                self.repo.set_head(&refname)?;
+                self.repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
+                Ok(MergeInfo::new(MergeType::Reset))
+            } else {
+                // do a normal merge
+                let merged = self.normal_merge(&head_commit, &fetch_commit)?;
+                Ok(MergeInfo {
+                    merge_type: MergeType::Normal,
+                    conflicts: !merged,
+                })
+            }
+        } else if analysis.0.is_up_to_date() {
+            debug!("Nothing to do...");
+            Ok(MergeInfo::new(MergeType::UpToDate))
+        } else {
+            debug!("Nothing to do...");
+            Ok(MergeInfo::new(MergeType::None))
+        }
+    }
+
+    /// Whether the upstream tracking branch pointed at `oid` at some point, according to its reflog
+    fn was_upstream_commit(&self, oid: Oid) -> bool {
+        let Ok(Some(upstream)) = self.upstream() else {
+            return false;
+        };
+        let Ok(reflog) = self.repo.reflog(&upstream.tracking_ref()) else {
+            return false;
+        };
+        reflog.iter().any(|entry| entry.id_new() == oid || entry.id_old() == oid)
+    }
+
+    /// Check if the repository is a shallow clone
+    fn is_shallow(&self) -> bool {
+        self.repo.is_shallow()
+    }
+
+    fn get_current_branch(&self) -> Result<String, Error> {
+        let head = self.repo.head()?;
+        let branch = head
+            .shorthand()
+            .ok_or_else(|| git2::Error::from_str("HEAD has no shorthand (detached)"))?
+            .to_string();
+
+        debug!("Current branch shorthand: {}", branch);
+
+        Ok(branch)
+    }
+
+    fn is_stash_needed(&self) -> Result<bool, Error> {
+        let statuses = self.repo.statuses(None)?;
+        let dirty = statuses.iter().any(|entry| {
+            let s = entry.status();
+            s.is_wt_modified()
+                || s.is_wt_new()
+                || s.is_wt_deleted()
+                || s.is_wt_renamed()
+                || s.is_wt_typechange()
+                || s.is_index_modified()
+                || s.is_index_deleted()
+                || s.is_index_renamed()
+                || s.is_index_new()
+        });
+        Ok(dirty)
+    }
+
+    fn stash_working_directory(&mut self) -> Result<Option<git2::Oid>, Error> {
+        let sig = self.repo.signature()?;
+        let msg = "autostash by git-update";
+        match self.repo.stash_save(&sig, msg, Some(git2::StashFlags::INCLUDE_UNTRACKED)) {
+            Ok(oid) => Ok(Some(oid)),
+            Err(e) => {
+                warn!("Failed to create stash: {} — continuing without stashing", e);
+                Ok(None)
+            }
+        }
+    }
+
+    fn stash_pop(&mut self, stashed_oid: git2::Oid) -> Result<(), Error> {
+        let mut found_index: Option<usize> = None;
+        // Iterate stash list to find the oid -> get its index
+        let _ = self.repo.stash_foreach(|i, _name, oid| {
+            if oid == &stashed_oid {
+                found_index = Some(i);
+                return false; // stop iteration
+            }
+            true
+        });
+
+        if let Some(idx) = found_index {
+            match self.repo.stash_pop(idx, None) {
+                Ok(_) => {
+                    debug!("Restored stashed changes (popped stash index {})", idx);
+                    Ok(())
+                },
+                Err(e) => {
+                    warn!("Failed to pop stash index {}: {}. You can restore manually with 'git stash list'", idx, e);
+                    Err(e)
+                },
+            }
+        } else {
+            warn!("Could not find stash corresponding to oid {}. You can inspect with 'git stash list'", stashed_oid);
+            Ok(())
+        }
+    }
+
+    /// Collect the commit log and diff stats between the local HEAD and the fetched commit
+    fn pending_changes(&self, branch: &str, local: Oid, remote: Oid) -> Result<PendingChanges, Error> {
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push(remote)?;
+        revwalk.hide(local)?;
+
+        let mut commits = Vec::new();
+        let mut total_commits = 0;
+        for oid in revwalk {
+            let oid = oid?;
+            total_commits += 1;
+            if commits.len() < MAX_PENDING_COMMITS {
+                let commit = self.repo.find_commit(oid)?;
+                let short = commit.as_object().short_id()?;
+                commits.push(format!(
+                    "{} {}",
+                    short.as_str().unwrap_or_default(),
+                    commit.summary().unwrap_or_default()
+                ));
+            }
+        }
+
+        let local_tree = self.repo.find_commit(local)?.tree()?;
+        let remote_tree = self.repo.find_commit(remote)?.tree()?;
+        let diff = self.repo.diff_tree_to_tree(Some(&local_tree), Some(&remote_tree), None)?;
+        // Counting lines needs the file contents, which a partial clone would have to download
+        let (insertions, deletions) = if git_cli::needs_git(&self.repo) {
+            (0, 0)
+        } else {
+            let stats = diff.stats()?;
+            (stats.insertions(), stats.deletions())
+        };
+
+        Ok(PendingChanges {
+            branch: branch.to_string(),
+            local,
+            remote,
+            commits,
+            total_commits,
+            files_changed: diff.deltas().len(),
+            insertions,
+            deletions,
+        })
+    }
+
+    /// Fetch the upstream of the current branch, noting the local and fetched commits
+    /// if the fetched commit is not merged yet
+    fn fetch_target(&self) -> Result<Fetched, Error> {
+        let current_branch = self.get_current_branch()?;
+        let upstream = self
+            .upstream()?
+            .ok_or_else(|| Error::from_str(&no_upstream_message(&current_branch)))?;
+
+        debug!("Current branch: {}, upstream: {}/{}", current_branch, upstream.remote, upstream.branch);
+
+        let settings = self.fetch_settings.merged(&FetchOverrides::load(&self.repo));
+        let use_git = git_cli::needs_git(&self.repo);
+
+        // Shallow clones keep fetching directly, the cache would deepen them. The cache also
+        // holds every branch and tag, so refspecs and tag choices need a direct fetch too,
+        // and partial clones would no longer be partial.
+        let use_cache = !self.is_shallow()
+            && !use_git
+            && settings.tags == TagMode::All
+            && settings.refspecs.is_empty();
+        let (fetch_commit, info) = match self.object_cache {
+            Some(ref cache) if use_cache => self.fetch_via_cache(cache, &upstream, &settings)?,
+            _ => {
+                let url = self.repo.config()?.get_string(&format!("remote.{}.url", upstream.remote))?;
+                let connect_url = self.credentials.resolve_url(&url);
+                let mut refs = settings.refspecs.clone();
+                if refs.is_empty() && settings.prune {
+                    // Pruning only considers refs the fetch could update, so fetch every
+                    // branch the remote is configured for, like `git fetch --prune`
+                    let remote = self.repo.find_remote(&upstream.remote)?;
+                    refs = remote.fetch_refspecs()?.iter().flatten().map(str::to_string).collect();
+                }
+                // Without explicit refspecs only the upstream branch is fetched and FETCH_HEAD is
+                // its commit, otherwise the commit is read from the updated tracking branch
+                let explicit = !refs.is_empty();
+
+                let info = if use_git {
+                    if !explicit {
+                        refs.push(upstream.branch.clone());
+                    }
+                    self.fetch_with_git(&url, &upstream.remote, &refs, &settings, self.fetch_depth())?
+                } else if connect_url == url {
+                    if !explicit {
+                        refs.push(upstream.branch.clone());
+                    }
+                    let refs: Vec<&str> = refs.iter().map(String::as_str).collect();
+                    let mut remote = self.repo.find_remote(&upstream.remote)?;
+                    self.do_fetch(&refs, &mut remote, &settings, self.fetch_depth())?
+                } else {
+                    // ~/.ssh/config points the host elsewhere; fetch through an anonymous
+                    // remote and update the tracking branch explicitly
+                    if !explicit {
+                        refs.push(format!("+refs/heads/{}:{}", upstream.branch, upstream.tracking_ref()));
+                    }
+                    let refs: Vec<&str> = refs.iter().map(String::as_str).collect();
+                    let mut remote = self.repo.remote_anonymous(&connect_url)?;
+                    self.do_fetch(&refs, &mut remote, &settings, self.fetch_depth())?
+                };
+
+                let fetched = if explicit {
+                    self.repo.refname_to_id(&upstream.tracking_ref()).map_err(|_| {
+                        Error::from_str(&format!("The configured refspecs do not update {}", upstream.tracking_ref()))
+                    })?
+                } else {
+                    self.fetch_head_merge()?
+                };
+                (self.repo.find_annotated_commit(fetched)?, info)
+            }
+        };
+
+        let analysis = self.repo.merge_analysis(&[&fetch_commit])?;
+        let target = if analysis.0.is_up_to_date() {
+            None
+        } else {
+            let local_oid = self
+                .repo
+                .head()?
+                .target()
+                .ok_or_else(|| git2::Error::from_str("HEAD has no target"))?;
+            Some((local_oid, fetch_commit.id()))
+        };
+
+        Ok(Fetched {
+            branch: current_branch,
+            info,
+            target,
+        })
+    }
+
+    /// Name of the checked out branch
+    pub fn branch(&self) -> Result<String, Error> {
+        self.get_current_branch()
+    }
+
+    /// Remote branch the current branch is updated from: the remote named by
+    /// `zsh-mgr.remote` if set, otherwise `branch.<name>.remote`, and the branch named by
+    /// `branch.<name>.merge`. `None` if the branch does not track a remote branch.
+    pub fn upstream(&self) -> Result<Option<Upstream>, Error> {
+        let branch = self.get_current_branch()?;
+        let config = self.repo.config()?.snapshot()?;
+
+        let remote = config
+            .get_string(REMOTE_OVERRIDE_KEY)
+            .ok()
+            .map(|remote| remote.trim().to_string())
+            .filter(|remote| !remote.is_empty())
+            .or_else(|| config.get_string(&format!("branch.{}.remote", branch)).ok())
+            // "." tracks a local branch, there is nothing to fetch
+            .filter(|remote| remote != ".");
+        let Some(remote) = remote else {
+            return Ok(None);
+        };
+
+        // With only the override set, the branch of the same name is used
+        let merge = config.get_string(&format!("branch.{}.merge", branch)).ok();
+        let remote_branch = match merge {
+            Some(ref merge) => merge.strip_prefix("refs/heads/").unwrap_or(merge).to_string(),
+            None => branch,
+        };
+
+        if self.repo.find_remote(&remote).is_err() {
+            return Err(Error::from_str(&format!("Remote '{}' does not exist", remote)));
+        }
+        Ok(Some(Upstream { remote, branch: remote_branch }))
+    }
+
+    /// URL of the remote updates are fetched from
+    pub fn remote_url(&self) -> Option<String> {
+        let remote = self.upstream().ok().flatten()?.remote;
+        self.repo.config().ok()?.get_string(&format!("remote.{}.url", remote)).ok()
+    }
+
+    /// Commit currently checked out
+    pub fn head_id(&self) -> Option<Oid> {
+        self.repo.head().ok().and_then(|head| head.target())
+    }
+
+    /// Fetch phase: download the upstream branch without touching the working tree.
+    /// Returns the transfer stats and the changes that `apply` would merge, or `None` if the
+    /// branch is up to date.
+    pub fn fetch(&mut self) -> Result<(FetchInfo, Option<PendingChanges>), Error> {
+        let fetched = self.fetch_target()?;
+        let pending = match fetched.target {
+            Some((local, remote)) => Some(self.pending_changes(&fetched.branch, local, remote)?),
+            None => None,
+        };
+        Ok((fetched.info, pending))
+    }
+
+    /// Apply phase: merge `target` (a previously fetched commit) into the current branch,
+    /// stashing and restoring local changes around the merge.
+    pub fn apply(&mut self, target: Oid) -> Result<MergeInfo, Error> {
+        if git_cli::needs_git(&self.repo) {
+            return self.merge_with_git(target);
+        }
+        let current_branch = self.get_current_branch()?;
+
+        let mut stashed_oid: Option<git2::Oid> = None;
+        if self.is_stash_needed()? {
+            debug!("Local changes detected — creating stash (include untracked)");
+            stashed_oid = self.stash_working_directory()?;
+        }
+
+        let result = self
+            .repo
+            .find_annotated_commit(target)
+            .and_then(|commit| self.do_merge(&current_branch, commit));
+
+        // Ensure working tree matches HEAD (force) so it's clean after merge
+        let _ = self.repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()));
+
+        // If we stashed, try to pop the stash now
+        if let Some(oid) = stashed_oid {
+            self.stash_pop(oid)?;
+        }
+
+        // Return merge result
+        result
+    }
+
+    /// Fetch the complete history of a shallow clone, turning it into a full one.
+    /// Returns `None` if the clone is not shallow.
+    pub fn unshallow(&mut self) -> Result<Option<FetchInfo>, Error> {
+        if !self.is_shallow() {
+            return Ok(None);
+        }
+        let remote_name = match self.upstream()? {
+            Some(upstream) => upstream.remote,
+            None => "origin".to_string(),
+        };
+        let remote = self.repo.find_remote(&remote_name)?;
+        if git_cli::needs_git(&self.repo) {
+            let url = remote.url().unwrap_or_default().to_string();
+            let args = ["fetch", "--quiet", "--unshallow", remote_name.as_str()];
+            git_cli::run(Some(self.workdir()?), &args, &self.git_env(&url))?;
+            return Ok(Some(FetchInfo::default()));
+        }
+        let refs: Vec<String> = remote.fetch_refspecs()?.iter().flatten().map(str::to_string).collect();
+        let refs: Vec<&str> = refs.iter().map(String::as_str).collect();
+        let url = remote
+            .url()
+            .ok_or_else(|| Error::from_str(&format!("Remote '{}' has no URL", remote_name)))?
+            .to_string();
+        let connect_url = self.credentials.resolve_url(&url);
+
+        let settings = self.fetch_settings.merged(&FetchOverrides::load(&self.repo));
+        let mut remote = if connect_url == url {
+            remote
+        } else {
+            self.repo.remote_anonymous(&connect_url)?
+        };
+        // libgit2's GIT_FETCH_DEPTH_UNSHALLOW
+        let info = self.do_fetch(&refs, &mut remote, &settings, Some(i32::MAX))?;
+        Ok(Some(info))
+    }
+
+    /// Fetch and apply in one go, returning the branch, the transfer stats and how it was merged
+    pub fn run(&mut self) -> Result<(String, FetchInfo, MergeInfo), Error> {
+        let fetched = self.fetch_target()?;
+        let merge = match fetched.target {
+            Some((_, remote)) => self.apply(remote)?,
+            None => MergeInfo::new(MergeType::UpToDate),
+        };
+        Ok((fetched.branch, fetched.info, merge))
+    }
+}
+
+/// Why a branch without tracking configuration is not updated
+pub(crate) fn no_upstream_message(branch: &str) -> String {
+    format!("No upstream configured for branch '{}'", branch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{clone_repo, commit_file, init_repo, shallow_clone, GitDaemon, TempDir};
+
+    fn updater(path: &std::path::Path) -> RepoUpdater {
+        RepoUpdater::new(path.to_path_buf(), Arc::new(CredentialManager::default())).unwrap()
+    }
+
+    #[test]
+    fn fetch_reports_pending_changes_without_merging() {
+        let tmp = TempDir::new("engine-repo");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        let local = clone.head().unwrap().target().unwrap();
+
+        commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        let remote = commit_file(&upstream, "b.zsh", "echo b\necho b\n", "Add b");
+
+        let (info, pending) = updater(&tmp.path().join("clone")).fetch().unwrap();
+        let pending = pending.unwrap();
+        assert!(info.objects_received > 0);
+        assert_eq!(pending.branch, "main");
+        assert_eq!(pending.local, local);
+        assert_eq!(pending.remote, remote);
+        assert_eq!(pending.total_commits, 2);
+        assert!(pending.commits[0].ends_with(" Add b"));
+        assert!(pending.commits[1].ends_with(" Add a"));
+        assert_eq!(pending.files_changed, 2);
+        assert_eq!(pending.insertions, 3);
+        assert_eq!(pending.deletions, 0);
+
+        assert_eq!(clone.head().unwrap().target(), Some(local));
+        assert!(!tmp.path().join("clone/a.zsh").exists());
+    }
+
+    #[test]
+    fn apply_fast_forwards_to_the_fetched_commit() {
+        let tmp = TempDir::new("engine-repo");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        let remote = commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+
+        let mut updater = updater(&tmp.path().join("clone"));
+        let pending = updater.fetch().unwrap().1.unwrap();
+        let merge = updater.apply(pending.remote).unwrap();
+
+        assert_eq!(merge.merge_type, MergeType::FastForward);
+        assert!(!merge.conflicts);
+        assert_eq!(clone.head().unwrap().target(), Some(remote));
+        assert!(tmp.path().join("clone/a.zsh").exists());
+        assert!(updater.fetch().unwrap().1.is_none());
+    }
+
+    #[test]
+    fn run_merges_diverged_branches() {
+        let tmp = TempDir::new("engine-repo");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        let remote = commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        let local = commit_file(&clone, "local.zsh", "echo local\n", "Local change");
+
+        let (branch, _, merge) = updater(&tmp.path().join("clone")).run().unwrap();
+
+        assert_eq!(branch, "main");
+        assert_eq!(merge.merge_type, MergeType::Normal);
+        assert!(!merge.conflicts);
+        let head = clone.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.parent_ids().collect::<Vec<_>>(), [local, remote]);
+        assert!(tmp.path().join("clone/a.zsh").exists());
+    }
+
+    #[test]
+    fn conflicts_leave_the_branch_unchanged() {
+        let tmp = TempDir::new("engine-repo");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        commit_file(&upstream, "README.md", "upstream\n", "Upstream edit");
+        let local = commit_file(&clone, "README.md", "local\n", "Local edit");
+
+        let (_, _, merge) = updater(&tmp.path().join("clone")).run().unwrap();
+
+        assert_eq!(merge.merge_type, MergeType::Normal);
+        assert!(merge.conflicts);
+        assert_eq!(clone.head().unwrap().target(), Some(local));
+        assert_eq!(std::fs::read_to_string(tmp.path().join("clone/README.md")).unwrap(), "local\n");
+    }
+
+    #[test]
+    fn run_is_up_to_date_without_new_commits() {
+        let tmp = TempDir::new("engine-repo");
+        init_repo(&tmp.path().join("upstream"));
+        clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+
+        let (_, _, merge) = updater(&tmp.path().join("clone")).run().unwrap();
+        assert_eq!(merge.merge_type, MergeType::UpToDate);
+    }
+
+    #[test]
+    fn upstream_follows_the_branch_tracking_configuration() {
+        let tmp = TempDir::new("engine-repo");
+        init_repo(&tmp.path().join("upstream"));
+        let fork = init_repo(&tmp.path().join("fork"));
+        let dev = commit_file(&fork, "dev.zsh", "echo dev\n", "Add dev");
+        fork.branch("dev", &fork.find_commit(dev).unwrap(), false).unwrap();
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        clone.remote("fork", tmp.path().join("fork").to_str().unwrap()).unwrap();
+        let mut config = clone.config().unwrap();
+        config.set_str("branch.main.remote", "fork").unwrap();
+        config.set_str("branch.main.merge", "refs/heads/dev").unwrap();
+
+        let mut updater = updater(&tmp.path().join("clone"));
+        let upstream = updater.upstream().unwrap().unwrap();
+        assert_eq!(upstream, Upstream { remote: "fork".into(), branch: "dev".into() });
+        assert!(updater.remote_url().unwrap().ends_with("fork"));
+
+        let (_, pending) = updater.fetch().unwrap();
+        assert_eq!(pending.unwrap().remote, dev);
+        assert_eq!(clone.refname_to_id("refs/remotes/fork/dev").unwrap(), dev);
+    }
+
+    #[test]
+    fn remote_override_replaces_the_tracked_remote() {
+        let tmp = TempDir::new("engine-repo");
+        init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        clone.remote("mirror", tmp.path().join("upstream").to_str().unwrap()).unwrap();
+
+        let updater = updater(&tmp.path().join("clone"));
+        clone.config().unwrap().set_str(REMOTE_OVERRIDE_KEY, " mirror ").unwrap();
+        assert_eq!(
+            updater.upstream().unwrap(),
+            Some(Upstream { remote: "mirror".into(), branch: "main".into() })
+        );
+
+        clone.config().unwrap().set_str(REMOTE_OVERRIDE_KEY, "gone").unwrap();
+        assert!(updater.upstream().unwrap_err().message().contains("'gone' does not exist"));
+    }
+
+    #[test]
+    fn branches_without_upstream_are_not_fetched() {
+        let tmp = TempDir::new("engine-repo");
+        let repo = init_repo(&tmp.path().join("local"));
+
+        let mut updater = updater(&tmp.path().join("local"));
+        assert_eq!(updater.upstream().unwrap(), None);
+        assert_eq!(updater.fetch().unwrap_err().message(), no_upstream_message("main"));
+
+        // "." tracks another local branch
+        let mut config = repo.config().unwrap();
+        config.set_str("branch.main.remote", ".").unwrap();
+        config.set_str("branch.main.merge", "refs/heads/other").unwrap();
+        assert_eq!(updater.upstream().unwrap(), None);
+    }
+
+    #[test]
+    fn prune_removes_deleted_remote_branches() {
+        let tmp = TempDir::new("engine-repo");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let head = upstream.head().unwrap().peel_to_commit().unwrap();
+        upstream.branch("gone", &head, false).unwrap();
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        assert!(clone.find_reference("refs/remotes/origin/gone").is_ok());
+        upstream.find_branch("gone", git2::BranchType::Local).unwrap().delete().unwrap();
+
+        updater(&tmp.path().join("clone")).run().unwrap();
+        assert!(clone.find_reference("refs/remotes/origin/gone").is_ok());
+
+        let settings = FetchSettings { prune: true, ..Default::default() };
+        updater(&tmp.path().join("clone")).with_fetch_settings(settings).run().unwrap();
+        assert!(clone.find_reference("refs/remotes/origin/gone").is_err());
+    }
+
+    #[test]
+    fn tag_mode_none_skips_tags() {
+        let tmp = TempDir::new("engine-repo");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        let commit = commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        upstream.tag_lightweight("v1", upstream.find_commit(commit).unwrap().as_object(), false).unwrap();
+
+        let settings = FetchSettings { tags: TagMode::None, ..Default::default() };
+        updater(&tmp.path().join("clone")).with_fetch_settings(settings).run().unwrap();
+        assert_eq!(clone.head().unwrap().target(), Some(commit));
+        assert!(clone.find_reference("refs/tags/v1").is_err());
+
+        updater(&tmp.path().join("clone")).run().unwrap();
+        assert!(clone.find_reference("refs/tags/v1").is_ok());
+    }
+
+    #[test]
+    fn refspecs_replace_the_upstream_branch_fetch() {
+        let tmp = TempDir::new("engine-repo");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        let commit = commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        let mut config = clone.config().unwrap();
+        config.set_multivar(crate::fetch_options::REFSPEC_KEY, "^$", "+refs/heads/main:refs/remotes/origin/main").unwrap();
+        config.set_multivar(crate::fetch_options::REFSPEC_KEY, "^$", "+refs/heads/main:refs/mirror/main").unwrap();
+
+        let (_, _, merge) = updater(&tmp.path().join("clone")).run().unwrap();
+        assert_eq!(merge.merge_type, MergeType::FastForward);
+        assert_eq!(clone.head().unwrap().target(), Some(commit));
+        assert_eq!(clone.refname_to_id("refs/mirror/main").unwrap(), commit);
+    }
+
+    /// Upstream with three commits, served over `git://` so clones of it can be shallow
+    fn shallow_upstream(tmp: &TempDir) -> (Repository, GitDaemon) {
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        commit_file(&upstream, "b.zsh", "echo b\n", "Add b");
+        (upstream, GitDaemon::start(tmp.path()))
+    }
+
+    /// Point `main` of `upstream` at a new root commit, as a force push rewriting history would
+    fn rewrite_history(upstream: &Repository) -> Oid {
+        let tree = upstream.head().unwrap().peel_to_tree().unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        let root = upstream.commit(None, &signature, &signature, "Rewritten", &tree, &[]).unwrap();
+        upstream.reference("refs/heads/main", root, true, "force push").unwrap();
+        root
+    }
+
+    #[test]
+    fn updates_keep_the_shallow_depth() {
+        let tmp = TempDir::new("engine-repo");
+        let (upstream, daemon) = shallow_upstream(&tmp);
+        let clone = shallow_clone(&daemon.url("upstream"), &tmp.path().join("clone"), 2);
+        let head = commit_file(&upstream, "c.zsh", "echo c\n", "Add c");
+
+        let (_, _, merge) = updater(&tmp.path().join("clone")).run().unwrap();
+        assert_eq!(merge.merge_type, MergeType::FastForward);
+        assert_eq!(clone.head().unwrap().target(), Some(head));
+        assert_eq!(shallow_depth(&clone), Some(2));
+
+        // The depth recorded in plugins.json wins over the clone's current one
+        let head = commit_file(&upstream, "d.zsh", "echo d\n", "Add d");
+        updater(&tmp.path().join("clone")).with_depth(Some(1)).run().unwrap();
+        assert_eq!(clone.head().unwrap().target(), Some(head));
+        assert_eq!(shallow_depth(&clone), Some(1));
+    }
+
+    #[test]
+    fn unshallow_fetches_the_full_history() {
+        let tmp = TempDir::new("engine-repo");
+        let (_upstream, daemon) = shallow_upstream(&tmp);
+        let clone = shallow_clone(&daemon.url("upstream"), &tmp.path().join("clone"), 1);
+
+        let mut updater = updater(&tmp.path().join("clone"));
+        assert!(updater.unshallow().unwrap().is_some());
+        assert!(!clone.is_shallow());
+        assert_eq!(shallow_depth(&clone), None);
+
+        // A fresh handle, the old one keeps the shallow boundary it loaded
+        let clone = Repository::open(tmp.path().join("clone")).unwrap();
+        let mut walk = clone.revwalk().unwrap();
+        walk.push_head().unwrap();
+        assert_eq!(walk.count(), 3);
+        assert!(updater.unshallow().unwrap().is_none());
+    }
+
+    #[test]
+    fn rewritten_shallow_history_resets_a_branch_without_own_commits() {
+        let tmp = TempDir::new("engine-repo");
+        let (upstream, daemon) = shallow_upstream(&tmp);
+        let clone = shallow_clone(&daemon.url("upstream"), &tmp.path().join("clone"), 1);
+        let root = rewrite_history(&upstream);
+
+        let (_, _, merge) = updater(&tmp.path().join("clone")).run().unwrap();
+        assert_eq!(merge.merge_type, MergeType::Reset);
+        assert_eq!(clone.head().unwrap().target(), Some(root));
+    }
+
+    #[test]
+    fn rewritten_shallow_history_keeps_local_commits() {
+        let tmp = TempDir::new("engine-repo");
+        let (upstream, daemon) = shallow_upstream(&tmp);
+        let clone = shallow_clone(&daemon.url("upstream"), &tmp.path().join("clone"), 1);
+        let local = commit_file(&clone, "local.zsh", "echo local\n", "Local change");
+        rewrite_history(&upstream);
+
+        let err = updater(&tmp.path().join("clone")).run().unwrap_err();
+        assert!(err.message().contains("zsh-mgr unshallow"));
+        assert_eq!(clone.head().unwrap().target(), Some(local));
+    }
+
+    #[test]
+    fn partial_clones_are_updated_with_git() {
+        let tmp = TempDir::new("engine-repo");
+        let (upstream, daemon) = shallow_upstream(&tmp);
+        upstream.config().unwrap().set_bool("uploadpack.allowFilter", true).unwrap();
+        let clone = crate::clone::RepoCloner::new(Arc::new(CredentialManager::default()))
+            .clone_repo(
+                &daemon.url("upstream"),
+                &tmp.path().join("clone"),
+                &crate::clone::CloneOptions::parse("--filter=blob:none").unwrap(),
+                &|_| {},
+            )
+            .unwrap();
+        assert!(git_cli::is_partial(&clone));
+        std::fs::write(tmp.path().join("clone/untracked.zsh"), "local\n").unwrap();
+        let head = commit_file(&upstream, "c.zsh", "echo c\n", "Add c");
+
+        let mut updater = updater(&tmp.path().join("clone"));
+        let (_, pending) = updater.fetch().unwrap();
+        let pending = pending.unwrap();
+        assert_eq!((pending.remote, pending.files_changed, pending.insertions), (head, 1, 0));
+
+        assert_eq!(updater.apply(head).unwrap().merge_type, MergeType::FastForward);
+        assert_eq!(clone.head().unwrap().target(), Some(head));
+        assert_eq!(std::fs::read_to_string(tmp.path().join("clone/c.zsh")).unwrap(), "echo c\n");
+        // Local changes are stashed around the merge and kept
+        assert!(tmp.path().join("clone/untracked.zsh").exists());
+        assert!(git_cli::is_partial(&clone));
+    }
+}
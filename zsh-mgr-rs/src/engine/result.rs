@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::repo::{no_upstream_message, PendingChanges};
+use crate::build::BuildResult;
+
+/// Transfer statistics of a fetch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FetchInfo {
+    pub objects_received: usize,
+    pub bytes_received: usize,
+}
+
+/// How the fetched commit was integrated into the local branch. `--json` output keeps the
+/// variant names; the event stream writes them in snake_case (see `UpdateEvent::Done`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeType {
+    FastForward,
+    Normal,
+    /// Shallow clone without a merge base, reset to the fetched commit
+    Reset,
+    UpToDate,
+    /// The branch tracks no remote branch, so nothing was fetched
+    NoUpstream,
+    /// A snippet or release was replaced by a newer download
+    Replaced,
+    /// Left as it was on purpose, i.e. skipped or pinned during `update --review`
+    Skipped,
+    None,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeInfo {
+    pub merge_type: MergeType,
+    /// The merge had conflicts and was abandoned, leaving the branch unchanged
+    pub conflicts: bool,
+}
+
+impl MergeInfo {
+    pub fn new(merge_type: MergeType) -> Self {
+        Self { merge_type, conflicts: false }
+    }
+}
+
+/// Outcome of updating a single repository
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateResult {
+    pub repo_path: PathBuf,
+    pub branch: String,
+    pub success: bool,
+    pub fetch_info: Option<FetchInfo>,
+    pub merge_info: Option<MergeInfo>,
+    #[serde(with = "duration_secs")]
+    pub duration: Duration,
+    pub error: Option<String>,
+    /// Commit checked out before and after the update
+    #[serde(default)]
+    pub old_head: Option<String>,
+    #[serde(default)]
+    pub new_head: Option<String>,
+    /// Stopped by a cancellation before completing; the repository was left as it was
+    #[serde(default)]
+    pub interrupted: bool,
+    /// Build commands run because HEAD changed, one result per plugin of the repository
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub builds: Vec<BuildResult>,
+}
+
+impl UpdateResult {
+    pub(crate) fn failed(repo_path: PathBuf, error: String, duration: Duration) -> Self {
+        Self {
+            repo_path,
+            branch: String::from("unknown"),
+            success: false,
+            fetch_info: None,
+            merge_info: None,
+            duration,
+            error: Some(error),
+            old_head: None,
+            new_head: None,
+            interrupted: false,
+            builds: Vec::new(),
+        }
+    }
+
+    /// One-line description for summaries
+    pub fn message(&self) -> String {
+        if let Some(ref error) = self.error {
+            return error.clone();
+        }
+        match self.merge_info.as_ref().map(|m| m.merge_type) {
+            Some(MergeType::UpToDate) => "Already up to date".to_string(),
+            Some(MergeType::NoUpstream) => no_upstream_message(&self.branch),
+            Some(MergeType::Skipped) => "Skipped".to_string(),
+            Some(MergeType::None) | None => "Fetched".to_string(),
+            Some(_) => "Updated successfully".to_string(),
+        }
+    }
+
+    /// Whether the update moved HEAD to another commit
+    pub fn head_changed(&self) -> bool {
+        self.success && self.new_head.is_some() && self.old_head != self.new_head
+    }
+
+    /// Whether a build command of a plugin of this repository failed
+    pub fn built_with_errors(&self) -> bool {
+        self.builds.iter().any(|b| !b.success)
+    }
+
+    /// Whether new commits were merged into the working tree
+    pub fn updated(&self) -> bool {
+        self.success
+            && matches!(
+                self.merge_info.as_ref().map(|m| m.merge_type),
+                Some(MergeType::FastForward | MergeType::Normal | MergeType::Reset | MergeType::Replaced)
+            )
+    }
+}
+
+/// Outcome of the fetch phase for a single repository
+#[derive(Debug)]
+pub struct FetchResult {
+    pub repo_path: PathBuf,
+    pub success: bool,
+    pub fetch_info: Option<FetchInfo>,
+    /// Changes waiting to be applied, `None` if the repository is up to date
+    pub pending: Option<PendingChanges>,
+    pub duration: Duration,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchUpdateResults {
+    pub total: usize,
+    pub successful: usize,
+    pub failed: usize,
+    /// Wall-clock time of the whole batch
+    #[serde(with = "duration_secs")]
+    pub elapsed: Duration,
+    pub results: Vec<UpdateResult>,
+}
+
+impl BatchUpdateResults {
+    pub fn new(results: Vec<UpdateResult>, elapsed: Duration) -> Self {
+        let successful = results.iter().filter(|r| r.success).count();
+        Self {
+            total: results.len(),
+            successful,
+            failed: results.len() - successful,
+            elapsed,
+            results,
+        }
+    }
+
+    /// Repositories that received new commits
+    pub fn updated(&self) -> usize {
+        self.results.iter().filter(|r| r.updated()).count()
+    }
+
+    /// Number of repositories whose branch tracks no remote branch
+    pub fn no_upstream(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.merge_info.as_ref().is_some_and(|m| m.merge_type == MergeType::NoUpstream))
+            .count()
+    }
+
+    /// Number of repositories left as they were during a review
+    pub fn skipped(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.merge_info.as_ref().is_some_and(|m| m.merge_type == MergeType::Skipped))
+            .count()
+    }
+
+    /// Number of repositories whose build failed
+    pub fn built_with_errors(&self) -> usize {
+        self.results.iter().filter(|r| r.built_with_errors()).count()
+    }
+
+    /// Number of repositories skipped or stopped by a cancellation
+    pub fn interrupted(&self) -> usize {
+        self.results.iter().filter(|r| r.interrupted).count()
+    }
+}
+
+/// Durations are serialized as fractional seconds
+pub(crate) mod duration_secs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        duration.as_secs_f64().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let secs = f64::deserialize(deserializer)?;
+        Ok(Duration::from_secs_f64(secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(merge_type: Option<MergeType>) -> UpdateResult {
+        UpdateResult {
+            repo_path: PathBuf::from("/plugins/repo"),
+            branch: "main".to_string(),
+            success: true,
+            fetch_info: Some(FetchInfo::default()),
+            merge_info: merge_type.map(MergeInfo::new),
+            duration: Duration::from_millis(1500),
+            error: None,
+            old_head: None,
+            new_head: None,
+            interrupted: false,
+            builds: Vec::new(),
+        }
+    }
+
+    fn build(success: bool) -> BuildResult {
+        BuildResult {
+            plugin: "repo".to_string(),
+            success,
+            output: String::new(),
+            error: (!success).then(|| "`make` exited with status 2".to_string()),
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn message_describes_the_outcome() {
+        assert_eq!(result(Some(MergeType::UpToDate)).message(), "Already up to date");
+        assert_eq!(result(Some(MergeType::FastForward)).message(), "Updated successfully");
+        assert_eq!(result(None).message(), "Fetched");
+        assert_eq!(result(Some(MergeType::Skipped)).message(), "Skipped");
+        assert!(!result(Some(MergeType::Skipped)).updated());
+
+        let failed = UpdateResult::failed(PathBuf::from("/plugins/repo"), "boom".to_string(), Duration::ZERO);
+        assert!(!failed.success);
+        assert_eq!(failed.branch, "unknown");
+        assert_eq!(failed.message(), "boom");
+    }
+
+    #[test]
+    fn batch_results_count_failures_and_serialize_seconds() {
+        let failed = UpdateResult::failed(PathBuf::from("/plugins/bad"), "boom".to_string(), Duration::ZERO);
+        let results = vec![
+            result(Some(MergeType::Normal)),
+            result(Some(MergeType::UpToDate)),
+            result(Some(MergeType::Skipped)),
+            failed,
+        ];
+        let batch = BatchUpdateResults::new(results, Duration::from_secs(2));
+        assert_eq!((batch.total, batch.successful, batch.failed), (4, 3, 1));
+        assert_eq!((batch.updated(), batch.skipped()), (1, 1));
+
+        let json = serde_json::to_value(&batch).unwrap();
+        assert_eq!(json["elapsed"], 2.0);
+        assert_eq!(json["results"][0]["duration"], 1.5);
+        assert_eq!(json["results"][0]["merge_info"]["merge_type"], "Normal");
+        let parsed: BatchUpdateResults = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.results[0].duration, Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn builds_follow_head_changes() {
+        let moved = |old: char, new: char| {
+            let mut result = result(Some(MergeType::FastForward));
+            result.old_head = Some(old.to_string().repeat(40));
+            result.new_head = Some(new.to_string().repeat(40));
+            result
+        };
+        assert!(moved('a', 'b').head_changed());
+        assert!(!moved('a', 'a').head_changed());
+        let mut failed = moved('a', 'b');
+        failed.success = false;
+        assert!(!failed.head_changed());
+
+        let mut built = moved('a', 'b');
+        built.builds = vec![build(true), build(false)];
+        assert!(built.built_with_errors());
+        let batch = BatchUpdateResults::new(vec![built, moved('a', 'a')], Duration::ZERO);
+        assert_eq!(batch.built_with_errors(), 1);
+        assert!(serde_json::to_value(&batch).unwrap()["results"][1].get("builds").is_none());
+    }
+}
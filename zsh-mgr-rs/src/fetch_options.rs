@@ -0,0 +1,213 @@
+use git2::{AutotagOption, Error, FetchOptions, FetchPrune, Repository};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Git config key of a plugin turning pruning of deleted remote branches on or off
+pub const PRUNE_KEY: &str = "zsh-mgr.prune";
+
+/// Git config key of a plugin choosing which tags are fetched (`all`, `none`, `follow`)
+pub const TAGS_KEY: &str = "zsh-mgr.tags";
+
+/// Git config key of a plugin listing refspecs to fetch instead of the upstream branch
+pub const REFSPEC_KEY: &str = "zsh-mgr.refspec";
+
+/// Which tags a fetch downloads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMode {
+    /// Every tag on the remote
+    #[default]
+    All,
+    /// No tags at all
+    None,
+    /// Only tags pointing at fetched commits, like plain `git fetch`
+    Follow,
+}
+
+impl TagMode {
+    fn autotag(self) -> AutotagOption {
+        match self {
+            TagMode::All => AutotagOption::All,
+            TagMode::None => AutotagOption::None,
+            TagMode::Follow => AutotagOption::Auto,
+        }
+    }
+}
+
+impl fmt::Display for TagMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TagMode::All => "all",
+            TagMode::None => "none",
+            TagMode::Follow => "follow",
+        })
+    }
+}
+
+impl FromStr for TagMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "all" => Ok(TagMode::All),
+            "none" => Ok(TagMode::None),
+            "follow" => Ok(TagMode::Follow),
+            other => Err(format!("Invalid tag mode '{}' (expected all, none or follow)", other)),
+        }
+    }
+}
+
+/// How plugins are fetched, set for every plugin under `fetch` in `config.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetchSettings {
+    /// Delete remote-tracking branches that no longer exist on the remote
+    #[serde(default)]
+    pub prune: bool,
+    #[serde(default)]
+    pub tags: TagMode,
+    /// Refspecs to fetch instead of the upstream branch; the upstream's tracking branch
+    /// must be among the refs they update
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub refspecs: Vec<String>,
+}
+
+impl FetchSettings {
+    /// These settings with a plugin's own overrides applied on top
+    pub fn merged(&self, overrides: &FetchOverrides) -> Self {
+        Self {
+            prune: overrides.prune.unwrap_or(self.prune),
+            tags: overrides.tags.unwrap_or(self.tags),
+            refspecs: if overrides.refspecs.is_empty() {
+                self.refspecs.clone()
+            } else {
+                overrides.refspecs.clone()
+            },
+        }
+    }
+
+    /// Set pruning and tag download on `options`
+    pub fn apply(&self, options: &mut FetchOptions<'_>) {
+        options.download_tags(self.tags.autotag());
+        // Without our own setting, git's `fetch.prune` and `remote.<name>.prune` still apply
+        options.prune(if self.prune { FetchPrune::On } else { FetchPrune::Unspecified });
+    }
+}
+
+/// Fetch settings of a single plugin, stored in its git config under `zsh-mgr.*`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchOverrides {
+    pub prune: Option<bool>,
+    pub tags: Option<TagMode>,
+    pub refspecs: Vec<String>,
+}
+
+impl FetchOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.prune.is_none() && self.tags.is_none() && self.refspecs.is_empty()
+    }
+
+    /// Read the overrides of a plugin repository; invalid values are ignored
+    pub fn load(repo: &Repository) -> Self {
+        let Ok(config) = repo.config() else {
+            return Self::default();
+        };
+
+        let mut refspecs = Vec::new();
+        if let Ok(mut entries) = config.multivar(REFSPEC_KEY, None) {
+            while let Some(Ok(entry)) = entries.next() {
+                if let Some(value) = entry.value() {
+                    refspecs.push(value.to_string());
+                }
+            }
+        }
+
+        Self {
+            prune: config.get_bool(PRUNE_KEY).ok(),
+            tags: config.get_string(TAGS_KEY).ok().and_then(|tags| tags.parse().ok()),
+            refspecs,
+        }
+    }
+
+    /// Write the overrides that are set into the repository's local git config
+    pub fn store(&self, repo: &Repository) -> Result<(), Error> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+        if let Some(prune) = self.prune {
+            config.set_bool(PRUNE_KEY, prune)?;
+        }
+        if let Some(tags) = self.tags {
+            config.set_str(TAGS_KEY, &tags.to_string())?;
+        }
+        for refspec in &self.refspecs {
+            config.set_multivar(REFSPEC_KEY, "^$", refspec)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{init_repo, TempDir};
+
+    #[test]
+    fn tag_modes_parse_and_display() {
+        for mode in [TagMode::All, TagMode::None, TagMode::Follow] {
+            assert_eq!(mode.to_string().parse::<TagMode>(), Ok(mode));
+        }
+        assert_eq!(" Follow ".parse::<TagMode>(), Ok(TagMode::Follow));
+        assert!("some".parse::<TagMode>().unwrap_err().contains("'some'"));
+    }
+
+    #[test]
+    fn overrides_replace_only_what_they_set() {
+        let global = FetchSettings {
+            prune: true,
+            tags: TagMode::None,
+            refspecs: vec!["+refs/heads/*:refs/remotes/origin/*".into()],
+        };
+        assert_eq!(global.merged(&FetchOverrides::default()), global);
+
+        let overrides = FetchOverrides {
+            prune: Some(false),
+            tags: None,
+            refspecs: vec!["+refs/heads/main:refs/remotes/origin/main".into()],
+        };
+        let merged = global.merged(&overrides);
+        assert!(!merged.prune);
+        assert_eq!(merged.tags, TagMode::None);
+        assert_eq!(merged.refspecs, overrides.refspecs);
+    }
+
+    #[test]
+    fn overrides_round_trip_through_the_repository_config() {
+        let tmp = TempDir::new("fetch-options");
+        let repo = init_repo(tmp.path());
+        assert!(FetchOverrides::load(&repo).is_empty());
+
+        let overrides = FetchOverrides {
+            prune: Some(true),
+            tags: Some(TagMode::Follow),
+            refspecs: vec!["+refs/heads/a:refs/remotes/origin/a".into(), "+refs/heads/b:refs/remotes/origin/b".into()],
+        };
+        overrides.store(&repo).unwrap();
+        assert_eq!(FetchOverrides::load(&repo), overrides);
+
+        // Unknown tag modes are ignored rather than failing the update
+        repo.config().unwrap().set_str(TAGS_KEY, "some").unwrap();
+        assert_eq!(FetchOverrides::load(&repo).tags, None);
+    }
+
+    #[test]
+    fn settings_are_read_from_config_json() {
+        let settings: FetchSettings = serde_json::from_str(r#"{"prune": true, "tags": "follow"}"#).unwrap();
+        assert_eq!(
+            settings,
+            FetchSettings { prune: true, tags: TagMode::Follow, refspecs: Vec::new() }
+        );
+        assert_eq!(serde_json::from_str::<FetchSettings>("{}").unwrap(), FetchSettings::default());
+    }
+}
@@ -0,0 +1,137 @@
+use git2::{Error, Repository};
+use log::debug;
+use std::path::Path;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+/// Whether `filter` (as in `git clone --filter=<filter>`) is one zsh-mgr can work with:
+/// only file contents may be left out, libgit2 still needs every commit and tree
+pub fn is_supported_filter(filter: &str) -> bool {
+    filter == "blob:none"
+        || filter
+            .strip_prefix("blob:limit=")
+            .is_some_and(|limit| !limit.is_empty())
+}
+
+/// Whether a plugin is a partial clone or has a sparse checkout. libgit2 supports neither
+/// and would download or check out every file, so such plugins are fetched and merged
+/// with the `git` executable.
+pub fn needs_git(repo: &Repository) -> bool {
+    is_partial(repo) || is_sparse(repo)
+}
+
+/// Whether some objects of `repo` were left out by a `--filter` clone
+pub fn is_partial(repo: &Repository) -> bool {
+    let Ok(config) = repo.config().and_then(|mut config| config.snapshot()) else {
+        return false;
+    };
+    if config.get_string("extensions.partialclone").is_ok() {
+        return true;
+    }
+    let Ok(mut entries) = config.entries(Some(r"remote\..*\.promisor")) else {
+        return false;
+    };
+    while let Some(Ok(entry)) = entries.next() {
+        if entry.value().is_some_and(|value| value == "true") {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether only part of the tree of `repo` is checked out
+pub fn is_sparse(repo: &Repository) -> bool {
+    repo.config()
+        .and_then(|config| config.get_bool("core.sparsecheckout"))
+        .unwrap_or(false)
+}
+
+/// Patterns of a sparse checkout, as listed by `git sparse-checkout list`
+pub fn sparse_patterns(repo_path: &Path) -> Vec<String> {
+    run(Some(repo_path), &["sparse-checkout", "list"], &[])
+        .map(|output| output.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Run `git` in `dir` (or the current directory) with extra environment variables and
+/// return its standard output. The last line git printed becomes the error.
+pub fn run(dir: Option<&Path>, args: &[&str], env: &[(String, String)]) -> Result<String, Error> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    debug!("Running git {}", args.join(" "));
+    let output = command
+        .args(args)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        // Never wait for a password on the terminal; credentials come from `env`
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        // In a process group of its own, so Ctrl-C only cancels the batch and never kills
+        // git halfway through a stash or a merge
+        .process_group(0)
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Error::from_str(
+                "git is not installed; partial clones and sparse checkouts need the git executable",
+            ),
+            _ => Error::from_str(&format!("Failed to run git: {}", e)),
+        })?;
+
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    // Some commands, like `stash pop`, report conflicts on stdout
+    let last_line = |bytes: &[u8]| {
+        String::from_utf8_lossy(bytes)
+            .lines()
+            .rev()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string)
+    };
+    let message = last_line(&output.stderr)
+        .or_else(|| last_line(&output.stdout))
+        .unwrap_or_else(|| format!("exited with {}", output.status));
+    Err(Error::from_str(&format!("git {} failed: {}", args[0], message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{init_repo, TempDir};
+
+    #[test]
+    fn only_blob_filters_are_supported() {
+        assert!(is_supported_filter("blob:none"));
+        assert!(is_supported_filter("blob:limit=1m"));
+        assert!(!is_supported_filter("blob:limit="));
+        assert!(!is_supported_filter("tree:0"));
+    }
+
+    #[test]
+    fn partial_and_sparse_clones_are_detected_from_the_config() {
+        let tmp = TempDir::new("git-cli");
+        let repo = init_repo(tmp.path());
+        assert!(!needs_git(&repo));
+
+        repo.config().unwrap().set_bool("remote.origin.promisor", true).unwrap();
+        assert!(is_partial(&repo) && !is_sparse(&repo));
+        repo.config().unwrap().set_bool("remote.origin.promisor", false).unwrap();
+        assert!(!is_partial(&repo));
+
+        repo.config().unwrap().set_bool("core.sparseCheckout", true).unwrap();
+        assert!(is_sparse(&repo) && needs_git(&repo));
+    }
+
+    #[test]
+    fn run_returns_stdout_or_the_last_error_line() {
+        let tmp = TempDir::new("git-cli");
+        init_repo(tmp.path());
+
+        let branch = run(Some(tmp.path()), &["rev-parse", "--abbrev-ref", "HEAD"], &[]).unwrap();
+        assert_eq!(branch.trim(), "main");
+        let err = run(Some(tmp.path()), &["rev-parse", "--verify", "missing"], &[]).unwrap_err();
+        assert_eq!(err.message(), "git rev-parse failed: fatal: Needed a single revision");
+    }
+}
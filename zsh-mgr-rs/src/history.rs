@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::engine::{BatchUpdateResults, MergeType, UpdateResult};
+
+/// What happened to one plugin during an update run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Updated,
+    UpToDate,
+    /// The branch tracks no remote branch
+    NoUpstream,
+    /// Skipped or pinned during `update --review`
+    Skipped,
+    Failed,
+    /// Skipped or stopped by Ctrl-C before anything changed
+    Interrupted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub plugin: String,
+    pub outcome: Outcome,
+    /// Commit checked out before and after the update
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub error: Option<String>,
+    /// Why the build commands of the plugin failed after the update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_error: Option<String>,
+}
+
+impl HistoryEntry {
+    fn new(plugin: String, result: &UpdateResult) -> Self {
+        let outcome = if result.interrupted {
+            Outcome::Interrupted
+        } else if !result.success {
+            Outcome::Failed
+        } else if result.merge_info.as_ref().is_some_and(|m| m.merge_type == MergeType::NoUpstream) {
+            Outcome::NoUpstream
+        } else if result.merge_info.as_ref().is_some_and(|m| m.merge_type == MergeType::Skipped) {
+            Outcome::Skipped
+        } else if result.updated() {
+            Outcome::Updated
+        } else {
+            Outcome::UpToDate
+        };
+        Self {
+            plugin,
+            outcome,
+            from: result.old_head.clone(),
+            to: result.new_head.clone(),
+            error: result.error.clone(),
+            build_error: result.builds.iter().find_map(|b| b.error.clone()),
+        }
+    }
+}
+
+/// One update run, appended as a line to `history.jsonl`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    /// Seconds since the Unix epoch when the run finished
+    pub ts: u64,
+    /// The run was cancelled, so some plugins were not updated
+    pub interrupted: bool,
+    pub elapsed_ms: u64,
+    pub plugins: Vec<HistoryEntry>,
+}
+
+impl HistoryRecord {
+    /// Build a record from batch results, naming each repository after the matching entry in `names`
+    pub fn new(names: &[String], results: &BatchUpdateResults, interrupted: bool) -> Self {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            ts,
+            interrupted,
+            elapsed_ms: results.elapsed.as_millis() as u64,
+            plugins: names
+                .iter()
+                .zip(results.results.iter())
+                .map(|(name, result)| HistoryEntry::new(name.clone(), result))
+                .collect(),
+        }
+    }
+
+    /// Append the record to the history file, creating it if needed
+    pub fn append(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{MergeInfo, MergeType};
+    use crate::test_support::TempDir;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn result(merge_type: MergeType, interrupted: bool) -> UpdateResult {
+        let mut result = UpdateResult::failed(PathBuf::from("/plugins/repo"), String::new(), Duration::ZERO);
+        result.success = !interrupted;
+        result.error = None;
+        result.merge_info = Some(MergeInfo::new(merge_type));
+        result.old_head = Some("old".to_string());
+        result.new_head = Some("new".to_string());
+        result.interrupted = interrupted;
+        result
+    }
+
+    #[test]
+    fn entries_classify_each_result() {
+        let failed = UpdateResult::failed(PathBuf::from("/plugins/d"), "boom".to_string(), Duration::ZERO);
+        let results = BatchUpdateResults::new(
+            vec![
+                result(MergeType::FastForward, false),
+                result(MergeType::UpToDate, false),
+                result(MergeType::None, true),
+                failed,
+                result(MergeType::Skipped, false),
+            ],
+            Duration::from_millis(1200),
+        );
+        let names: Vec<_> = ["a", "b", "c", "d", "e"].iter().map(|n| n.to_string()).collect();
+
+        let record = HistoryRecord::new(&names, &results, true);
+        assert!(record.interrupted);
+        assert_eq!(record.elapsed_ms, 1200);
+        let outcomes: Vec<_> = record.plugins.iter().map(|p| p.outcome).collect();
+        assert_eq!(
+            outcomes,
+            [Outcome::Updated, Outcome::UpToDate, Outcome::Interrupted, Outcome::Failed, Outcome::Skipped]
+        );
+        assert_eq!(record.plugins[0].plugin, "a");
+        assert_eq!(record.plugins[0].from.as_deref(), Some("old"));
+        assert_eq!(record.plugins[0].to.as_deref(), Some("new"));
+        assert_eq!(record.plugins[3].error.as_deref(), Some("boom"));
+    }
+
+    #[test]
+    fn append_writes_one_line_per_run() {
+        let tmp = TempDir::new("history");
+        let path = tmp.path().join("state/history.jsonl");
+        let results = BatchUpdateResults::new(vec![result(MergeType::Normal, false)], Duration::ZERO);
+        let record = HistoryRecord::new(&["a".to_string()], &results, false);
+
+        record.append(&path).unwrap();
+        record.append(&path).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        let parsed: HistoryRecord = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+        assert_eq!(parsed.plugins[0].outcome, Outcome::Updated);
+        assert!(contents.contains(r#""outcome":"updated""#));
+    }
+}
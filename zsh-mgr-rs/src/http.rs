@@ -0,0 +1,39 @@
+use anyhow::{bail, Context, Result};
+use std::io::Read;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTP client for downloads that do not go through git
+pub fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(TIMEOUT)
+        .timeout_read(TIMEOUT)
+        .user_agent(concat!("zsh-mgr/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// Send `request`, turning error statuses into errors that name the URL
+pub fn call(request: ureq::Request) -> Result<ureq::Response> {
+    let url = request.url().to_string();
+    match request.call() {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(code, _)) => bail!("Failed to download {}: HTTP {}", url, code),
+        Err(e) => bail!("Failed to download {}: {}", url, e),
+    }
+}
+
+/// Read the body of `response`, refusing anything larger than `max_size` bytes
+pub fn read_body(response: ureq::Response, max_size: u64) -> Result<Vec<u8>> {
+    let url = response.get_url().to_string();
+    let mut content = Vec::new();
+    response
+        .into_reader()
+        .take(max_size + 1)
+        .read_to_end(&mut content)
+        .with_context(|| format!("Failed to download {}", url))?;
+    if content.len() as u64 > max_size {
+        bail!("{} is larger than {} MiB", url, max_size / 1024 / 1024);
+    }
+    Ok(content)
+}
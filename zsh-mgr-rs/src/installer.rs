@@ -0,0 +1,1137 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::build::{self, BuildResult};
+use crate::clone::{shallow_depth, CloneOptions, CloneProgress, RepoCloner};
+use crate::config::{Config, PluginInfo, PluginKind, PluginList};
+use crate::credentials_manager::CredentialManager;
+use crate::engine::CancelToken;
+use crate::fetch_options::FetchOverrides;
+use crate::git_cli;
+use crate::object_cache::ObjectCache;
+use crate::progress::{ProgressDisplay, RepoStatus};
+use crate::release::{self, Extracted, ReleaseClient, ReleaseSpec};
+use crate::snippet;
+use crate::tokens::TokenStore;
+
+/// Where the objects of a plugin come from
+#[derive(Debug, Clone, Default)]
+pub enum InstallSource {
+    /// Clone from the plugin's remote URL
+    #[default]
+    Remote,
+    /// Clone offline from a `git bundle` file, e.g. one extracted by `import-bundle`.
+    /// For snippets, a copy of the file itself; for releases, the extracted release directory,
+    /// which is moved into place.
+    Bundle(PathBuf),
+}
+
+/// A plugin to install, as given to `add` or listed in default-plugins.txt
+#[derive(Debug, Clone)]
+pub struct InstallRequest {
+    /// Plugin in format "user/repo"
+    pub name: String,
+    /// Git clone flags
+    pub flags: Option<String>,
+    /// Private repository (use SSH unless `https` is set)
+    pub private: bool,
+    /// Clone a private repository over HTTPS, authenticated with a token
+    pub https: bool,
+    pub source: InstallSource,
+    /// Remote URL, when it is not the GitHub URL derived from the name
+    pub url: Option<String>,
+    /// Commit to check out after cloning; the plugin is registered as pinned to it
+    pub pin: Option<String>,
+    /// SSH keys to try for this plugin before the host's keys
+    pub identity_files: Vec<PathBuf>,
+    /// Fetch settings of this plugin, overriding the ones from `config.json`
+    pub fetch: FetchOverrides,
+    /// Directories to check out instead of the whole tree
+    pub sparse: Vec<String>,
+    /// Subdirectory of the repository to load the plugin from; `name` is then the repository,
+    /// whose clone is shared with its other plugins
+    pub path: Option<String>,
+    /// Directory to link into the plugin directory instead of cloning
+    pub local: Option<PathBuf>,
+    /// Download `url` as a single-file snippet instead of cloning it
+    pub snippet: bool,
+    /// Install an asset of the latest release of the repository instead of cloning it
+    pub release: Option<ReleaseSpec>,
+    /// Shell commands to run in the plugin directory once it is installed
+    pub build: Vec<String>,
+}
+
+impl InstallRequest {
+    pub fn new(name: String, flags: Option<String>, private: bool) -> Self {
+        Self {
+            name,
+            flags,
+            private,
+            https: false,
+            source: InstallSource::Remote,
+            url: None,
+            pin: None,
+            identity_files: Vec::new(),
+            fetch: FetchOverrides::default(),
+            sparse: Vec::new(),
+            path: None,
+            local: None,
+            snippet: false,
+            release: None,
+            build: Vec::new(),
+        }
+    }
+
+    pub fn with_bundle(mut self, bundle: PathBuf) -> Self {
+        self.source = InstallSource::Bundle(bundle);
+        self
+    }
+
+    pub fn with_url(mut self, url: String) -> Self {
+        self.url = Some(url);
+        self
+    }
+
+    pub fn with_pin(mut self, pin: Option<String>) -> Self {
+        self.pin = pin;
+        self
+    }
+
+    pub fn with_https(mut self, https: bool) -> Self {
+        self.https = https;
+        self
+    }
+
+    pub fn with_identity_files(mut self, identity_files: Vec<PathBuf>) -> Self {
+        self.identity_files = identity_files;
+        self
+    }
+
+    pub fn with_fetch(mut self, fetch: FetchOverrides) -> Self {
+        self.fetch = fetch;
+        self
+    }
+
+    pub fn with_sparse(mut self, sparse: Vec<String>) -> Self {
+        self.sparse = sparse;
+        self
+    }
+
+    pub fn with_path(mut self, path: Option<String>) -> Self {
+        self.path = path;
+        self
+    }
+
+    pub fn with_local(mut self, local: Option<PathBuf>) -> Self {
+        self.local = local;
+        self
+    }
+
+    pub fn with_snippet(mut self, snippet: bool) -> Self {
+        self.snippet = snippet;
+        self
+    }
+
+    pub fn with_release(mut self, release: Option<ReleaseSpec>) -> Self {
+        self.release = release;
+        self
+    }
+
+    pub fn with_build(mut self, build: Vec<String>) -> Self {
+        self.build = build;
+        self
+    }
+
+    /// Name the plugin is registered under
+    pub fn plugin_name(&self) -> String {
+        match self.path {
+            Some(ref path) => PluginInfo::subdir_name(&self.name, path),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Remote URL for this plugin
+    pub fn url(&self) -> String {
+        if let Some(ref url) = self.url {
+            url.clone()
+        } else if self.private && !self.https {
+            format!("git@github.com:{}.git", self.name)
+        } else {
+            format!("https://github.com/{}.git", self.name)
+        }
+    }
+}
+
+/// Name of the directory inside the plugin dir where clones are staged before being moved into place
+const STAGING_DIR: &str = ".staging";
+
+/// Where a plugin installed from a bundle keeps it, relative to its `.git` directory
+const BUNDLE_FILE: &str = "zsh-mgr/origin.bundle";
+
+/// Remote pointing at the bundle a plugin was installed from
+pub const BUNDLE_REMOTE: &str = "bundle";
+
+/// Removes a directory on drop unless disarmed; used to roll back partial installs
+struct DirGuard {
+    path: PathBuf,
+    armed: bool,
+}
+
+impl DirGuard {
+    fn new(path: PathBuf) -> Self {
+        Self { path, armed: true }
+    }
+
+    fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for DirGuard {
+    fn drop(&mut self) {
+        if self.armed && self.path.exists() {
+            if let Err(e) = fs::remove_dir_all(&self.path) {
+                warn!("Failed to clean up {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// A plugin that has been moved into place but is not registered yet.
+/// Dropping it without calling `commit` removes the plugin directory again, unless the
+/// plugin reused a clone that was already there.
+#[derive(Debug)]
+pub struct InstalledPlugin {
+    pub info: PluginInfo,
+    pub path: PathBuf,
+    /// Outcome of the build commands, if the plugin has any; a failed build keeps the plugin
+    pub build: Option<BuildResult>,
+    plugin_dir: PathBuf,
+    /// The clone was made for this plugin
+    owns_clone: bool,
+    committed: bool,
+}
+
+impl InstalledPlugin {
+    /// Keep the plugin directory; called once the plugin is registered
+    pub fn commit(mut self) -> PluginInfo {
+        self.committed = true;
+        self.info.clone()
+    }
+}
+
+impl Drop for InstalledPlugin {
+    fn drop(&mut self) {
+        if self.committed || !self.owns_clone {
+            return;
+        }
+        debug!("Rolling back installation of {}", self.info.name);
+        if self.info.kind == PluginKind::Release {
+            if let Err(e) = release::unlink_bins(&release::bin_dir(&self.plugin_dir), &self.path) {
+                warn!("Failed to remove the binaries of {}: {}", self.info.name, e);
+            }
+        }
+        // Only the link of a local plugin is ours, never the directory behind it
+        let result = match self.info.kind {
+            PluginKind::Local => fs::remove_file(&self.path),
+            PluginKind::Git | PluginKind::Snippet | PluginKind::Release => fs::remove_dir_all(&self.path),
+        };
+        if let Err(e) = result {
+            warn!("Failed to remove {}: {}", self.path.display(), e);
+        }
+        remove_empty_parents(&self.path, &self.plugin_dir);
+    }
+}
+
+/// Remove the now-empty "user/" directories left behind by a rolled back "user/repo" plugin
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut current = path.parent();
+    while let Some(dir) = current {
+        if dir == root || !dir.starts_with(root) || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
+
+/// Clones plugins into the plugin directory. Registration in `plugins.json` is left to the
+/// caller so that a batch of parallel installs can be saved at once.
+///
+/// Clones go to a staging directory first and are only moved into place once they are
+/// complete and valid, so a failed or interrupted install never leaves a partial plugin behind.
+pub struct PluginInstaller {
+    plugin_dir: PathBuf,
+    cloner: RepoCloner,
+    releases: ReleaseClient,
+    build_timeout: Duration,
+    cancel: CancelToken,
+}
+
+impl PluginInstaller {
+    pub fn new(config: &Config) -> Result<Self> {
+        let credentials = Arc::new(CredentialManager::new()
+            .map_err(|e| anyhow::anyhow!("Failed to create credential manager: {}", e))?
+            .with_settings(&config.credentials));
+        let object_cache = config
+            .object_cache
+            .clone()
+            .map(|root| Arc::new(ObjectCache::new(root, Arc::clone(&credentials))));
+        let installer = Self {
+            plugin_dir: config.plugin_dir.clone(),
+            cloner: RepoCloner::new(credentials)
+                .with_object_cache(object_cache)
+                .with_fetch_settings(config.fetch.clone()),
+            releases: ReleaseClient::new(
+                &config.release_api,
+                TokenStore::new(dirs::home_dir().unwrap_or_default(), config.credentials.token_command.clone()),
+            ),
+            build_timeout: Duration::from_secs(config.build_timeout),
+            cancel: CancelToken::new(),
+        };
+        installer.clean_stale_staging();
+        Ok(installer)
+    }
+
+    /// Stop build commands when `cancel` is set, killing the one that is running
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    fn staging_root(&self) -> PathBuf {
+        self.plugin_dir.join(STAGING_DIR)
+    }
+
+    /// Remove staging directories left behind by installs whose process no longer exists
+    fn clean_stale_staging(&self) {
+        // Liveness can only be checked where /proc is available
+        if !Path::new("/proc/self").exists() {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(self.staging_root()) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let pid = name.split('-').next().unwrap_or_default();
+            if !Path::new("/proc").join(pid).exists() {
+                debug!("Removing stale staging directory {}", entry.path().display());
+                let _ = fs::remove_dir_all(entry.path());
+            }
+        }
+    }
+
+    /// Scratch directory in the staging area, owned by this process and cleaned up if it dies
+    pub fn staging_path(&self, label: &str) -> PathBuf {
+        self.staging_root()
+            .join(format!("{}-{}", std::process::id(), label.replace('/', "_")))
+    }
+
+    /// Directory a plugin is installed into
+    pub fn plugin_path(&self, name: &str) -> PathBuf {
+        self.plugin_dir.join(name)
+    }
+
+    /// Clone the plugin into a staging directory, validate it and move it into place.
+    /// The returned plugin must be registered and committed, or it is removed again on drop.
+    pub fn install(
+        &self,
+        request: &InstallRequest,
+        progress: &(dyn Fn(CloneProgress) + Sync),
+    ) -> Result<InstalledPlugin> {
+        let mut installed = self.install_files(request, progress)?;
+        installed.info.build = request.build.clone();
+        installed.build = self.build(&installed.info);
+        Ok(installed)
+    }
+
+    /// Run the build commands of `info` in its directory, if it has any
+    pub fn build(&self, info: &PluginInfo) -> Option<BuildResult> {
+        if info.build.is_empty() {
+            return None;
+        }
+        Some(build::run(&info.name, &self.plugin_path(&info.name), &info.build, self.build_timeout, &self.cancel))
+    }
+
+    fn install_files(
+        &self,
+        request: &InstallRequest,
+        progress: &(dyn Fn(CloneProgress) + Sync),
+    ) -> Result<InstalledPlugin> {
+        if let Some(ref source) = request.local {
+            return self.link_local(request, source);
+        }
+        if request.snippet {
+            return self.install_snippet(request);
+        }
+        if let Some(ref spec) = request.release {
+            return self.install_release(request, spec);
+        }
+
+        // Validate flags before touching the network
+        let mut clone_options = match request.flags {
+            Some(ref f) => CloneOptions::parse(f)?,
+            None => CloneOptions::default(),
+        };
+        clone_options.identity_files = request.identity_files.clone();
+        clone_options.fetch = request.fetch.clone();
+        clone_options.sparse = request.sparse.clone();
+
+        let dest = self.plugin_path(&request.name);
+        if let Some(ref path) = request.path {
+            check_subdir(path)?;
+            if dest.exists() {
+                return self.reuse_clone(request, &dest, path);
+            }
+        }
+        if dest.exists() {
+            bail!(
+                "{} already exists but is not registered; run 'zsh-mgr sync' or remove it",
+                dest.display()
+            );
+        }
+
+        fs::create_dir_all(self.staging_root())?;
+        let staging = self.staging_path(&request.name);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        let mut staging_guard = DirGuard::new(staging.clone());
+
+        let url = request.url();
+        let repo = match request.source {
+            InstallSource::Remote => self.cloner.clone_repo(&url, &staging, &clone_options, progress),
+            InstallSource::Bundle(ref bundle) => self
+                .cloner
+                .clone_from_bundle(bundle, &url, &staging, &clone_options, progress),
+        }
+        .with_context(|| format!("Git clone failed for {}", request.name))?;
+
+        // A clone is only usable if HEAD resolves to a commit
+        repo.head()
+            .and_then(|head| head.peel_to_commit())
+            .with_context(|| format!("Cloned repository for {} has no valid HEAD", request.name))?;
+        if let Some(ref path) = request.path {
+            self.ensure_subdir(&repo, &staging, &clone_options.identity_files, request, path)?;
+        }
+
+        if let Some(ref pin) = request.pin {
+            self.cloner
+                .reset_to(&repo, &url, &clone_options, pin)
+                .with_context(|| format!("Failed to check out pinned commit {} of {}", pin, request.name))?;
+        }
+
+        // Keep the bundle next to the repository and reachable as a remote
+        if let InstallSource::Bundle(ref bundle) = request.source {
+            let kept = repo.path().join(BUNDLE_FILE);
+            if let Some(parent) = kept.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(bundle, &kept)?;
+            let final_path = dest.join(".git").join(BUNDLE_FILE);
+            repo.remote(BUNDLE_REMOTE, &final_path.to_string_lossy())?;
+        }
+        // Only a clone that actually is shallow records a depth
+        let depth = if repo.is_shallow() {
+            clone_options.depth.or_else(|| shallow_depth(&repo))
+        } else {
+            None
+        };
+        drop(repo);
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&staging, &dest)
+            .with_context(|| format!("Failed to move {} into place", request.name))?;
+        staging_guard.disarm();
+
+        let mut info = PluginInfo::new(request.plugin_name(), url, request.private, request.flags.clone());
+        info.pinned = request.pin.clone();
+        info.depth = depth;
+        info.sparse = if clone_options.sparse.is_empty() {
+            Vec::new()
+        } else {
+            git_cli::sparse_patterns(&dest)
+        };
+        info.repo = request.path.as_ref().map(|_| request.name.clone());
+        info.path = request.path.clone();
+
+        Ok(InstalledPlugin {
+            info,
+            path: dest,
+            plugin_dir: self.plugin_dir.clone(),
+            owns_clone: true,
+            committed: false,
+            build: None,
+        })
+    }
+
+    /// Register directory `source` as a local plugin by linking it into the plugin directory
+    fn link_local(&self, request: &InstallRequest, source: &Path) -> Result<InstalledPlugin> {
+        let source = fs::canonicalize(source)
+            .with_context(|| format!("Local plugin directory not found: {}", source.display()))?;
+        if !source.is_dir() {
+            bail!("{} is not a directory", source.display());
+        }
+
+        let dest = self.plugin_path(&request.name);
+        if dest.symlink_metadata().is_ok() {
+            bail!(
+                "{} already exists but is not registered; run 'zsh-mgr sync' or remove it",
+                dest.display()
+            );
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        std::os::unix::fs::symlink(&source, &dest)
+            .with_context(|| format!("Failed to link {} to {}", dest.display(), source.display()))?;
+
+        let mut info = PluginInfo::new(request.name.clone(), source.to_string_lossy().into_owned(), false, None);
+        info.kind = PluginKind::Local;
+
+        Ok(InstalledPlugin {
+            info,
+            path: dest,
+            plugin_dir: self.plugin_dir.clone(),
+            owns_clone: true,
+            committed: false,
+            build: None,
+        })
+    }
+
+    /// Download a snippet into a staging directory and move it into place
+    fn install_snippet(&self, request: &InstallRequest) -> Result<InstalledPlugin> {
+        let url = request.url();
+        let dest = self.plugin_path(&request.name);
+        if dest.exists() {
+            bail!(
+                "{} already exists but is not registered; run 'zsh-mgr sync' or remove it",
+                dest.display()
+            );
+        }
+
+        let download = match request.source {
+            InstallSource::Bundle(ref copy) => {
+                let content = fs::read(copy).with_context(|| format!("Failed to read {}", copy.display()))?;
+                snippet::Download { checksum: snippet::checksum(&content), content, etag: None }
+            }
+            InstallSource::Remote => snippet::download(&url, None)?
+                .with_context(|| format!("{} sent no content", url))?,
+        };
+
+        let staging = self.staging_path(&request.name);
+        let mut guard = DirGuard::new(staging.clone());
+        fs::create_dir_all(&staging)?;
+        // The file is named after the plugin, not after the staging directory
+        let file = snippet::snippet_file(&dest);
+        fs::write(staging.join(file.file_name().unwrap()), &download.content)?;
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&staging, &dest)
+            .with_context(|| format!("Failed to move {} into place", request.name))?;
+        guard.disarm();
+
+        let mut info = PluginInfo::new(request.name.clone(), url, false, None);
+        info.kind = PluginKind::Snippet;
+        info.checksum = Some(download.checksum);
+        info.etag = download.etag;
+
+        Ok(InstalledPlugin {
+            info,
+            path: dest,
+            plugin_dir: self.plugin_dir.clone(),
+            owns_clone: true,
+            committed: false,
+            build: None,
+        })
+    }
+
+    /// Install an asset of the latest release of `request.name` and link its binaries
+    fn install_release(&self, request: &InstallRequest, spec: &ReleaseSpec) -> Result<InstalledPlugin> {
+        let dest = self.plugin_path(&request.name);
+        if dest.exists() {
+            bail!(
+                "{} already exists but is not registered; run 'zsh-mgr sync' or remove it",
+                dest.display()
+            );
+        }
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // A release from a bundle keeps the version it was exported with, which only the
+        // caller knows
+        let extracted = match request.source {
+            InstallSource::Bundle(ref dir) => {
+                fs::rename(dir, &dest)
+                    .with_context(|| format!("Failed to move {} into place", request.name))?;
+                None
+            }
+            InstallSource::Remote => {
+                let latest = self.releases.latest(&request.name)?;
+                let staging = self.staging_path(&request.name);
+                let mut guard = DirGuard::new(staging.clone());
+                let extracted = self.releases.extract(&latest, spec.pattern(), &spec.bins, &staging)?;
+                debug!("Installing {} {} from {}", request.name, extracted.tag, extracted.asset);
+
+                fs::rename(&staging, &dest)
+                    .with_context(|| format!("Failed to move {} into place", request.name))?;
+                guard.disarm();
+                Some(extracted)
+            }
+        };
+
+        let mut info = PluginInfo::new(request.name.clone(), request.url(), false, None);
+        info.kind = PluginKind::Release;
+        info.version = extracted.as_ref().map(|e| e.tag.clone());
+        info.checksum = extracted.map(|e| e.checksum);
+        info.asset = spec.asset.clone();
+        info.bins = spec.bins.clone();
+        let installed = InstalledPlugin {
+            info,
+            path: dest,
+            plugin_dir: self.plugin_dir.clone(),
+            owns_clone: true,
+            committed: false,
+            build: None,
+        };
+        // Dropping `installed` on failure removes the plugin and the links made so far
+        release::link_bins(&release::bin_dir(&self.plugin_dir), &installed.path, &spec.bins)?;
+        Ok(installed)
+    }
+
+    /// Replace release plugin `info` with the latest release, unless it already has it.
+    /// Returns the new release.
+    pub fn update_release(&self, info: &PluginInfo) -> Result<Option<Extracted>> {
+        let latest = self.releases.latest(&info.name)?;
+        if info.version.as_deref() == Some(latest.tag_name.as_str()) {
+            return Ok(None);
+        }
+
+        let spec = ReleaseSpec { asset: info.asset.clone(), bins: info.bins.clone() };
+        let staging = self.staging_path(&info.name);
+        let mut guard = DirGuard::new(staging.clone());
+        let extracted = self.releases.extract(&latest, spec.pattern(), &spec.bins, &staging)?;
+
+        // Swap the directories, so the old release stays in place until the new one is complete
+        let dest = self.plugin_path(&info.name);
+        let old = self.staging_path(&format!("{}-old", info.name));
+        let _old_guard = DirGuard::new(old.clone());
+        fs::rename(&dest, &old)?;
+        if let Err(e) = fs::rename(&staging, &dest) {
+            fs::rename(&old, &dest)?;
+            return Err(e).with_context(|| format!("Failed to move {} into place", info.name));
+        }
+
+        // The old release is only removed once the new binaries are linked
+        let bin_dir = release::bin_dir(&self.plugin_dir);
+        let old_links = release::bin_links(&bin_dir, &dest);
+        let linked = release::unlink_bins(&bin_dir, &dest).and_then(|_| release::link_bins(&bin_dir, &dest, &spec.bins));
+        if let Err(e) = linked {
+            // The new release goes back to staging, where `guard` removes it
+            let restored = release::unlink_bins(&bin_dir, &dest)
+                .and_then(|_| Ok(fs::rename(&dest, &staging)?))
+                .and_then(|_| Ok(fs::rename(&old, &dest)?))
+                .and_then(|_| release::restore_links(&old_links));
+            if let Err(restore) = restored {
+                warn!("Failed to restore the previous release of {}: {:#}", info.name, restore);
+            }
+            return Err(e).with_context(|| format!("Failed to link the binaries of {}", info.name));
+        }
+        guard.disarm();
+        Ok(Some(extracted))
+    }
+
+    /// Install a subdirectory plugin from the clone of its repository that is already there
+    fn reuse_clone(&self, request: &InstallRequest, dest: &Path, path: &str) -> Result<InstalledPlugin> {
+        let repo = git2::Repository::open(dest)
+            .with_context(|| format!("{} is not a git repository", dest.display()))?;
+        let identities = CredentialManager::repo_identities(&repo);
+        self.ensure_subdir(&repo, dest, &identities, request, path)?;
+        debug!("Reusing the clone of {} for {}", request.name, path);
+
+        let mut info = PluginInfo::new(request.plugin_name(), request.url(), request.private, request.flags.clone());
+        info.depth = shallow_depth(&repo);
+        if git_cli::is_sparse(&repo) {
+            info.sparse = git_cli::sparse_patterns(dest);
+        }
+        info.repo = Some(request.name.clone());
+        info.path = Some(path.to_string());
+
+        Ok(InstalledPlugin {
+            info,
+            path: dest.to_path_buf(),
+            plugin_dir: self.plugin_dir.clone(),
+            owns_clone: false,
+            committed: false,
+            build: None,
+        })
+    }
+
+    /// Make sure subdirectory `path` is checked out in `workdir`, adding it to a sparse checkout
+    fn ensure_subdir(
+        &self,
+        repo: &git2::Repository,
+        workdir: &Path,
+        identities: &[PathBuf],
+        request: &InstallRequest,
+        path: &str,
+    ) -> Result<()> {
+        if !workdir.join(path).is_dir() && git_cli::is_sparse(repo) {
+            self.cloner.add_sparse(workdir, &request.url(), identities, path)?;
+        }
+        if !workdir.join(path).is_dir() {
+            bail!("Directory '{}' not found in {}", path, request.name);
+        }
+        Ok(())
+    }
+}
+
+/// Reject subdirectories that are absolute or leave the repository
+fn check_subdir(path: &str) -> Result<()> {
+    let path = Path::new(path);
+    let inside = path
+        .components()
+        .all(|component| matches!(component, std::path::Component::Normal(_)));
+    if path.as_os_str().is_empty() || !inside {
+        bail!("Invalid plugin path '{}': it must be a directory inside the repository", path.display());
+    }
+    Ok(())
+}
+
+/// Default number of concurrent clones for `install_parallel`
+pub const DEFAULT_JOBS: usize = 8;
+
+/// Install several plugins on a local thread pool of `jobs` threads, showing a live
+/// progress table. Results are returned in the order of `requests`.
+pub fn install_parallel<'a>(
+    installer: &PluginInstaller,
+    requests: &'a [InstallRequest],
+    jobs: usize,
+) -> Result<Vec<(&'a InstallRequest, Result<InstalledPlugin>)>> {
+    let progress = ProgressDisplay::new(requests.iter().map(|r| r.plugin_name()).collect());
+    progress.initial_draw();
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs.max(1)).build()?;
+    let mut results: Vec<Option<Result<InstalledPlugin>>> = requests.iter().map(|_| None).collect();
+
+    // Plugins sharing a repository would clone it at the same time, so each wave installs
+    // one plugin per repository; the others reuse its clone in the following waves
+    let mut pending: Vec<usize> = (0..requests.len()).collect();
+    while !pending.is_empty() {
+        let mut seen = HashSet::new();
+        let (wave, rest): (Vec<usize>, Vec<usize>) =
+            pending.into_iter().partition(|&i| seen.insert(requests[i].name.as_str()));
+        let installed: Vec<_> = pool.install(|| {
+            wave.par_iter()
+                .map(|&i| {
+                    progress.update_status(i, RepoStatus::Cloning);
+                    let result = installer.install(&requests[i], &|p| progress.update_transfer(i, p));
+                    match result {
+                        Ok(_) => progress.update_status(i, RepoStatus::Success),
+                        Err(ref e) => progress.update_status(i, RepoStatus::Failed(format!("{:#}", e))),
+                    }
+                    (i, result)
+                })
+                .collect()
+        });
+        for (i, result) in installed {
+            results[i] = Some(result);
+        }
+        pending = rest;
+    }
+
+    Ok(requests
+        .iter()
+        .zip(results)
+        .filter_map(|(request, result)| Some((request, result?)))
+        .collect())
+}
+
+/// Plugins whose build commands failed, with the reason
+pub fn build_errors(plugins: &[InstalledPlugin]) -> Vec<(String, String)> {
+    plugins
+        .iter()
+        .filter_map(|p| p.build.as_ref())
+        .filter(|b| !b.success)
+        .map(|b| (b.plugin.clone(), b.error.clone().unwrap_or_default()))
+        .collect()
+}
+
+/// Add freshly installed plugins to the list, save it and create their timestamp files.
+/// If anything fails, the plugins are removed from the list and their directories deleted.
+pub fn register(config: &Config, plugin_list: &mut PluginList, plugins: Vec<InstalledPlugin>) -> Result<()> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    for plugin in &plugins {
+        let mut info = plugin.info.clone();
+        // A shared clone is pinned and shaped the same for all its plugins
+        let existing = plugin_list
+            .plugins_of(info.repo_name())
+            .next()
+            .map(|existing| (existing.url.clone(), existing.flags.clone(), existing.depth, existing.pinned.clone()));
+        if let Some((url, flags, depth, pinned)) = existing {
+            info.url = url;
+            info.flags = flags;
+            info.depth = depth;
+            info.pinned = pinned;
+        }
+        let sparse = info.sparse.clone();
+        plugin_list.add(info);
+        if !sparse.is_empty() {
+            plugin_list.set_sparse(&plugin.info.name, sparse);
+        }
+    }
+
+    let result = plugins
+        .iter()
+        .filter(|p| p.owns_clone && p.info.kind != PluginKind::Local)
+        .try_for_each(|p| fs::write(config.timestamp_file(p.info.repo_name()), now.to_string()))
+        .map_err(anyhow::Error::from)
+        .and_then(|_| plugin_list.save());
+
+    if let Err(e) = result {
+        for plugin in &plugins {
+            plugin_list.remove(&plugin.info.name);
+            if plugin.owns_clone {
+                let _ = fs::remove_file(config.timestamp_file(plugin.info.repo_name()));
+            }
+        }
+        // Dropping `plugins` removes the uncommitted directories
+        return Err(e.context("Failed to register installed plugins"));
+    }
+
+    for plugin in plugins {
+        plugin.commit();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{commit_file, init_repo, test_config, tool_release, HttpServer, TempDir};
+
+    #[test]
+    fn url_depends_on_privacy() {
+        let public = InstallRequest::new("zsh-users/zsh-autosuggestions".to_string(), None, false);
+        assert_eq!(public.url(), "https://github.com/zsh-users/zsh-autosuggestions.git");
+
+        let private = InstallRequest::new("me/secret".to_string(), None, true);
+        assert_eq!(private.url(), "git@github.com:me/secret.git");
+        assert_eq!(private.with_https(true).url(), "https://github.com/me/secret.git");
+    }
+
+    #[test]
+    fn install_rejects_bad_flags_before_cloning() {
+        let tmp = TempDir::new("installer");
+        let installer = PluginInstaller::new(&test_config(tmp.path())).unwrap();
+        let request = InstallRequest::new("u/repo".to_string(), Some("--bare".to_string()), false);
+
+        let err = installer.install(&request, &|_| {}).unwrap_err();
+        assert!(err.to_string().contains("--bare"));
+        assert!(!installer.plugin_path("u/repo").exists());
+    }
+
+    /// An uncommitted plugin as returned by `install`, backed by a directory under the plugin dir
+    fn installed(config: &Config, name: &str) -> InstalledPlugin {
+        let path = config.plugin_dir.join(name);
+        fs::create_dir_all(&path).unwrap();
+        InstalledPlugin {
+            info: PluginInfo::new(name.to_string(), format!("https://github.com/{}.git", name), false, None),
+            path,
+            plugin_dir: config.plugin_dir.clone(),
+            owns_clone: true,
+            committed: false,
+            build: None,
+        }
+    }
+
+    fn empty_list(config: &Config) -> PluginList {
+        fs::create_dir_all(&config.plugin_dir).unwrap();
+        fs::write(config.plugin_list_file(), "[]").unwrap();
+        PluginList::load(config).unwrap()
+    }
+
+    #[test]
+    fn install_refuses_unregistered_existing_directory() {
+        let tmp = TempDir::new("installer");
+        let config = test_config(tmp.path());
+        fs::create_dir_all(config.plugin_dir.join("u/repo")).unwrap();
+        let installer = PluginInstaller::new(&config).unwrap();
+
+        let err = installer
+            .install(&InstallRequest::new("u/repo".to_string(), None, false), &|_| {})
+            .unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert!(config.plugin_dir.join("u/repo").exists());
+    }
+
+    #[test]
+    fn register_saves_the_list_and_timestamps() {
+        let tmp = TempDir::new("installer");
+        let config = test_config(tmp.path());
+        let mut plugin_list = empty_list(&config);
+
+        register(&config, &mut plugin_list, vec![installed(&config, "u/repo")]).unwrap();
+
+        assert!(PluginList::load(&config).unwrap().get("u/repo").is_some());
+        assert!(config.timestamp_file("u/repo").exists());
+        assert!(config.plugin_dir.join("u/repo").exists());
+    }
+
+    #[test]
+    fn register_rolls_back_on_failure() {
+        let tmp = TempDir::new("installer");
+        let config = test_config(tmp.path());
+        let mut plugin_list = empty_list(&config);
+        // A directory in place of the timestamp file makes writing it fail
+        fs::create_dir_all(config.timestamp_file("u/broken")).unwrap();
+
+        let plugins = vec![installed(&config, "u/ok"), installed(&config, "u/broken")];
+        assert!(register(&config, &mut plugin_list, plugins).is_err());
+
+        assert!(plugin_list.list().is_empty());
+        assert!(!config.timestamp_file("u/ok").exists());
+        assert!(!config.plugin_dir.join("u/ok").exists());
+        assert!(!config.plugin_dir.join("u/broken").exists());
+        assert!(!config.plugin_dir.join("u").exists());
+    }
+
+    #[test]
+    fn dropping_an_uncommitted_plugin_removes_it() {
+        let tmp = TempDir::new("installer");
+        let config = test_config(tmp.path());
+        fs::create_dir_all(config.plugin_dir.join("u/other")).unwrap();
+
+        drop(installed(&config, "u/repo"));
+        assert!(!config.plugin_dir.join("u/repo").exists());
+        // The user directory is still in use by another plugin
+        assert!(config.plugin_dir.join("u").exists());
+
+        let info = installed(&config, "v/repo").commit();
+        assert_eq!(info.name, "v/repo");
+        assert!(config.plugin_dir.join("v/repo").exists());
+    }
+
+    #[test]
+    fn stale_staging_directories_are_removed() {
+        let tmp = TempDir::new("installer");
+        let config = test_config(tmp.path());
+        let staging = config.plugin_dir.join(STAGING_DIR);
+        // No process has pid 0 in /proc
+        fs::create_dir_all(staging.join("0-u_repo")).unwrap();
+        let ours = staging.join(format!("{}-u_repo", std::process::id()));
+        fs::create_dir_all(&ours).unwrap();
+
+        PluginInstaller::new(&config).unwrap();
+
+        assert!(!staging.join("0-u_repo").exists());
+        assert!(ours.exists());
+    }
+
+    #[test]
+    fn install_checks_out_the_pinned_commit() {
+        let tmp = TempDir::new("installer");
+        let config = test_config(tmp.path());
+        let upstream_path = tmp.path().join("upstream");
+        let upstream = init_repo(&upstream_path);
+        let pin = upstream.head().unwrap().target().unwrap();
+        commit_file(&upstream, "new.zsh", "echo new\n", "After the pin");
+
+        let request = InstallRequest::new("u/repo".to_string(), None, false)
+            .with_url(upstream_path.to_str().unwrap().to_string())
+            .with_pin(Some(pin.to_string()));
+        let plugin = PluginInstaller::new(&config).unwrap().install(&request, &|_| {}).unwrap();
+
+        assert_eq!(plugin.info.pinned, Some(pin.to_string()));
+        assert_eq!(plugin.info.url, upstream_path.to_str().unwrap());
+        let repo = git2::Repository::open(&plugin.path).unwrap();
+        assert_eq!(repo.head().unwrap().target(), Some(pin));
+        assert!(!plugin.path.join("new.zsh").exists());
+    }
+
+    #[test]
+    fn check_subdir_rejects_paths_leaving_the_repository() {
+        assert!(check_subdir("plugins/git").is_ok());
+        for path in ["", "..", "./plugins/git", "plugins/../../etc", "/etc", "plugins/.."] {
+            assert!(check_subdir(path).is_err(), "{:?} was accepted", path);
+        }
+    }
+
+    #[test]
+    fn subdirectory_plugins_share_the_clone_of_their_repository() {
+        let tmp = TempDir::new("installer");
+        let config = test_config(tmp.path());
+        let upstream_path = tmp.path().join("upstream");
+        let upstream = init_repo(&upstream_path);
+        for plugin in ["a", "b"] {
+            fs::create_dir_all(upstream_path.join("plugins").join(plugin)).unwrap();
+            let file = format!("plugins/{}/{}.plugin.zsh", plugin, plugin);
+            commit_file(&upstream, &file, "echo plugin\n", "Add plugin");
+        }
+        let installer = PluginInstaller::new(&config).unwrap();
+        let request = |path: &str| {
+            InstallRequest::new("u/repo".to_string(), None, false)
+                .with_url(upstream_path.to_str().unwrap().to_string())
+                .with_path(Some(path.to_string()))
+        };
+
+        let first = installer.install(&request("plugins/a"), &|_| {}).unwrap().commit();
+        assert_eq!(first.name, "u/repo/plugins/a");
+        assert_eq!((first.repo.as_deref(), first.path.as_deref()), (Some("u/repo"), Some("plugins/a")));
+        assert_eq!(first.load_spec(), "u/repo path:plugins/a");
+
+        let second = installer.install(&request("plugins/b"), &|_| {}).unwrap();
+        assert_eq!(second.path, config.plugin_dir.join("u/repo"));
+        // The clone belongs to the first plugin, dropping the second one keeps it
+        drop(second);
+        assert!(config.plugin_dir.join("u/repo/plugins/a").exists());
+
+        let err = installer.install(&request("plugins/c"), &|_| {}).unwrap_err();
+        assert!(err.to_string().contains("'plugins/c' not found"));
+        assert!(installer.install(&request("../other"), &|_| {}).is_err());
+    }
+
+    #[test]
+    fn local_plugins_are_linked_and_only_the_link_is_rolled_back() {
+        let tmp = TempDir::new("installer");
+        let config = test_config(tmp.path());
+        let source = tmp.path().join("work/my-plugin");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("my-plugin.plugin.zsh"), "echo mine\n").unwrap();
+        let installer = PluginInstaller::new(&config).unwrap();
+        let request = InstallRequest::new("me/my-plugin".to_string(), None, false).with_local(Some(source.clone()));
+
+        let plugin = installer.install(&request, &|_| {}).unwrap();
+        assert_eq!(plugin.info.kind, PluginKind::Local);
+        assert_eq!(plugin.info.url, fs::canonicalize(&source).unwrap().to_str().unwrap());
+        assert_eq!(fs::read_link(&plugin.path).unwrap(), fs::canonicalize(&source).unwrap());
+        assert!(installer.install(&request, &|_| {}).unwrap_err().to_string().contains("already exists"));
+
+        let link = plugin.path.clone();
+        drop(plugin);
+        assert!(link.symlink_metadata().is_err());
+        assert!(source.join("my-plugin.plugin.zsh").exists());
+
+        let missing = request.with_local(Some(tmp.path().join("missing")));
+        assert!(installer.install(&missing, &|_| {}).unwrap_err().to_string().contains("not found"));
+    }
+
+    #[test]
+    fn snippets_are_downloaded_into_a_plugin_file() {
+        let tmp = TempDir::new("installer");
+        let config = test_config(tmp.path());
+        let source = tmp.path().join("aliases.zsh");
+        fs::write(&source, "alias l=ls\n").unwrap();
+        let url = snippet::source_url(source.to_str().unwrap()).unwrap();
+        let request = InstallRequest::new("aliases".to_string(), None, false)
+            .with_url(url.clone())
+            .with_snippet(true);
+
+        let plugin = PluginInstaller::new(&config).unwrap().install(&request, &|_| {}).unwrap();
+        assert_eq!(plugin.info.kind, PluginKind::Snippet);
+        assert_eq!(plugin.info.url, url);
+        assert_eq!(plugin.info.checksum, Some(snippet::checksum(b"alias l=ls\n")));
+        let file = config.plugin_dir.join("aliases/aliases.plugin.zsh");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "alias l=ls\n");
+
+        drop(plugin);
+        assert!(!config.plugin_dir.join("aliases").exists());
+    }
+
+    #[test]
+    fn release_plugins_link_their_binaries() {
+        let tmp = TempDir::new("installer");
+        let server = HttpServer::start(|base| tool_release(base, "v1.0.0"));
+        let mut config = test_config(tmp.path());
+        config.release_api = server.url();
+        let spec = ReleaseSpec { asset: Some("tool-*".to_string()), bins: vec!["tool".to_string()] };
+        let request = InstallRequest::new("u/tool".to_string(), None, false).with_release(Some(spec));
+
+        let plugin = PluginInstaller::new(&config).unwrap().install(&request, &|_| {}).unwrap();
+        assert_eq!(plugin.info.kind, PluginKind::Release);
+        assert_eq!(plugin.info.version.as_deref(), Some("v1.0.0"));
+        let link = config.bin_dir().join("tool");
+        assert!(fs::read_link(&link).unwrap().starts_with(&plugin.path));
+
+        // An uncommitted release takes its links along
+        drop(plugin);
+        assert!(link.symlink_metadata().is_err());
+        assert!(!config.plugin_dir.join("u/tool").exists());
+    }
+
+    #[test]
+    fn release_updates_replace_the_binaries_or_keep_the_old_release() {
+        let tmp = TempDir::new("installer");
+        let mut config = test_config(tmp.path());
+        let v1 = HttpServer::start(|base| tool_release(base, "v1.0.0"));
+        config.release_api = v1.url();
+        let spec = ReleaseSpec { asset: Some("tool-*".to_string()), bins: vec!["tool".to_string()] };
+        let request = InstallRequest::new("u/tool".to_string(), None, false).with_release(Some(spec));
+        let mut info = PluginInstaller::new(&config).unwrap().install(&request, &|_| {}).unwrap().commit();
+
+        let v2 = HttpServer::start(|base| tool_release(base, "v2.0.0"));
+        config.release_api = v2.url();
+        let installer = PluginInstaller::new(&config).unwrap();
+        let dest = config.plugin_dir.join("u/tool");
+        let link = config.bin_dir().join("tool");
+        let staged = || fs::read_dir(installer.staging_root()).map_or(0, |entries| entries.count());
+
+        // A binary missing from the new release leaves the old one in place, links included
+        info.bins.push("missing".to_string());
+        assert!(installer.update_release(&info).is_err());
+        assert_eq!(fs::read_link(&link).unwrap(), dest.join("tool-v1.0.0/bin/tool"));
+        assert!(link.exists());
+        assert!(!config.bin_dir().join("missing").exists());
+        assert_eq!(staged(), 0);
+
+        info.bins.pop();
+        let extracted = installer.update_release(&info).unwrap().unwrap();
+        assert_eq!(extracted.tag, "v2.0.0");
+        assert_eq!(fs::read_link(&link).unwrap(), dest.join("tool-v2.0.0/bin/tool"));
+        assert!(!dest.join("tool-v1.0.0").exists());
+        assert_eq!(staged(), 0);
+    }
+
+    #[test]
+    fn build_commands_run_in_the_new_plugin() {
+        let tmp = TempDir::new("installer");
+        let config = test_config(tmp.path());
+        let upstream_path = tmp.path().join("upstream");
+        init_repo(&upstream_path);
+        let installer = PluginInstaller::new(&config).unwrap();
+        let request = |name: &str, build: &[&str]| {
+            InstallRequest::new(name.to_string(), None, false)
+                .with_url(upstream_path.to_str().unwrap().to_string())
+                .with_build(build.iter().map(|c| c.to_string()).collect())
+        };
+
+        let plugin = installer.install(&request("u/built", &["touch built"]), &|_| {}).unwrap();
+        assert!(plugin.build.as_ref().unwrap().success);
+        assert_eq!(plugin.info.build, ["touch built"]);
+        assert!(plugin.path.join("built").exists());
+
+        let plain = installer.install(&request("u/plain", &[]), &|_| {}).unwrap();
+        assert!(plain.build.is_none());
+
+        // A failed build is reported but the plugin stays installed
+        let failed = installer.install(&request("u/failed", &["exit 3"]), &|_| {}).unwrap();
+        assert!(failed.path.exists());
+        let plugins = [plugin, plain, failed];
+        assert_eq!(build_errors(&plugins), [("u/failed".to_string(), "`exit 3` exited with status 3".to_string())]);
+    }
+}
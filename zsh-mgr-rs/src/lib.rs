@@ -1,4 +1,23 @@
+pub mod askpass;
+pub mod build;
+pub mod bundle;
+pub mod clone;
 pub mod credentials_manager;
-pub mod git_update;
+pub mod engine;
+pub mod fetch_options;
+pub mod git_cli;
+pub mod history;
+pub mod http;
 pub mod config;
-pub mod updater;
+pub mod installer;
+pub mod local;
+pub mod manifest;
+pub mod object_cache;
+pub mod progress;
+pub mod release;
+pub mod snippet;
+pub mod ssh_config;
+pub mod tokens;
+
+#[cfg(test)]
+mod test_support;
@@ -0,0 +1,120 @@
+use git2::{BranchType, ErrorCode, Repository, Status, StatusOptions};
+use std::fmt;
+use std::path::Path;
+
+/// State of the git repository behind a local plugin, which `update` reports instead of
+/// updating it
+#[derive(Debug, Clone)]
+pub struct LocalStatus {
+    /// Checked out branch; `None` on a detached HEAD
+    pub branch: Option<String>,
+    /// Files with uncommitted changes, untracked ones included
+    pub changed_files: usize,
+    /// Commits ahead of and behind the upstream branch, as of the last fetch
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+impl LocalStatus {
+    /// Read the status of the repository at `path`, or `None` if it is not a git repository
+    pub fn read(path: &Path) -> Result<Option<Self>, git2::Error> {
+        let repo = match Repository::open(path) {
+            Ok(repo) => repo,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut options = StatusOptions::new();
+        options.include_untracked(true).exclude_submodules(true);
+        let changed_files = repo
+            .statuses(Some(&mut options))?
+            .iter()
+            .filter(|entry| entry.status() != Status::IGNORED)
+            .count();
+
+        let head = repo.head().ok();
+        let branch = head
+            .as_ref()
+            .filter(|head| head.is_branch())
+            .and_then(|head| head.shorthand())
+            .map(str::to_string);
+        let ahead_behind = (|| {
+            let local = head.as_ref()?.target()?;
+            let upstream = repo
+                .find_branch(branch.as_deref()?, BranchType::Local)
+                .ok()?
+                .upstream()
+                .ok()?
+                .get()
+                .target()?;
+            repo.graph_ahead_behind(local, upstream).ok()
+        })();
+
+        Ok(Some(Self {
+            branch,
+            changed_files,
+            ahead_behind,
+        }))
+    }
+}
+
+impl fmt::Display for LocalStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.branch.as_deref().unwrap_or("detached HEAD"))?;
+        match self.changed_files {
+            0 => write!(f, ", clean")?,
+            1 => write!(f, ", 1 changed file")?,
+            n => write!(f, ", {} changed files", n)?,
+        }
+        if let Some((ahead, behind)) = self.ahead_behind {
+            if ahead > 0 {
+                write!(f, ", {} ahead", ahead)?;
+            }
+            if behind > 0 {
+                write!(f, ", {} behind", behind)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{clone_repo, commit_file, init_repo, TempDir};
+
+    #[test]
+    fn directories_without_git_have_no_status() {
+        let tmp = TempDir::new("local");
+        assert!(LocalStatus::read(tmp.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn status_counts_changes_and_commits_ahead() {
+        let tmp = TempDir::new("local");
+        let upstream = init_repo(&tmp.path().join("upstream"));
+        let clone = clone_repo(&tmp.path().join("upstream"), &tmp.path().join("clone"));
+        let clone_path = tmp.path().join("clone");
+        assert_eq!(LocalStatus::read(&clone_path).unwrap().unwrap().to_string(), "main, clean");
+
+        commit_file(&clone, "mine.zsh", "echo mine\n", "Local commit");
+        std::fs::write(clone_path.join("README.md"), "edited\n").unwrap();
+        std::fs::write(clone_path.join("new.zsh"), "echo new\n").unwrap();
+        let status = LocalStatus::read(&clone_path).unwrap().unwrap();
+        assert_eq!(status.ahead_behind, Some((1, 0)));
+        assert_eq!(status.to_string(), "main, 2 changed files, 1 ahead");
+
+        // Behind counts what the last fetch brought, nothing is fetched here
+        commit_file(&upstream, "theirs.zsh", "echo theirs\n", "Upstream commit");
+        assert_eq!(LocalStatus::read(&clone_path).unwrap().unwrap().ahead_behind, Some((1, 0)));
+    }
+
+    #[test]
+    fn detached_heads_are_shown_as_such() {
+        let status = LocalStatus {
+            branch: None,
+            changed_files: 1,
+            ahead_behind: Some((0, 3)),
+        };
+        assert_eq!(status.to_string(), "detached HEAD, 1 changed file, 3 behind");
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::config::{PluginInfo, PluginKind};
+use crate::installer::InstallRequest;
+use crate::release::ReleaseSpec;
+
+/// Version written by `export`; `import` refuses manifests newer than this
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Portable, host-independent description of a plugin set, as written by `zsh-mgr export`.
+/// Unlike `plugins.json` it has no timestamps or paths, only what is needed to reproduce
+/// the plugins on another machine. Plugins are listed in load order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub plugins: Vec<PluginSpec>,
+}
+
+/// One plugin of a `Manifest`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginSpec {
+    /// Plugin in format "user/repo"
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub private: bool,
+    /// Git clone flags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flags: Option<String>,
+    /// Commit to check out and keep instead of following the remote
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<String>,
+    /// Directories of a sparse checkout
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sparse: Vec<String>,
+    /// Repository of a plugin loaded from one of its subdirectories
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// Subdirectory of `repo` the plugin is loaded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "PluginKind::is_git")]
+    pub kind: PluginKind,
+    /// Pattern of the asset a release plugin downloads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+    /// Binaries a release plugin links
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bins: Vec<String>,
+    /// Commands run in the plugin directory after installing and updating it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub build: Vec<String>,
+}
+
+impl Manifest {
+    pub fn from_plugins(plugins: &[PluginInfo]) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            plugins: plugins.iter().map(PluginSpec::from).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self> {
+        let manifest: Self = serde_json::from_str(contents).context("Invalid plugin manifest")?;
+        if manifest.version > MANIFEST_VERSION {
+            bail!(
+                "Manifest version {} is newer than supported version {}; upgrade zsh-mgr",
+                manifest.version,
+                MANIFEST_VERSION
+            );
+        }
+        Ok(manifest)
+    }
+}
+
+impl From<&PluginInfo> for PluginSpec {
+    fn from(info: &PluginInfo) -> Self {
+        Self {
+            name: info.name.clone(),
+            url: info.url.clone(),
+            private: info.private,
+            flags: info.flags.clone(),
+            pinned: info.pinned.clone(),
+            sparse: info.sparse.clone(),
+            repo: info.repo.clone(),
+            path: info.path.clone(),
+            kind: info.kind,
+            asset: info.asset.clone(),
+            bins: info.bins.clone(),
+            build: info.build.clone(),
+        }
+    }
+}
+
+impl PluginSpec {
+    pub fn install_request(&self) -> InstallRequest {
+        let name = self.repo.clone().unwrap_or_else(|| self.name.clone());
+        InstallRequest::new(name, self.flags.clone(), self.private)
+            .with_url(self.url.clone())
+            .with_pin(self.pinned.clone())
+            .with_sparse(self.sparse.clone())
+            .with_path(self.path.clone())
+            .with_local((self.kind == PluginKind::Local).then(|| PathBuf::from(&self.url)))
+            .with_snippet(self.kind == PluginKind::Snippet)
+            .with_release(self.release_spec())
+            .with_build(self.build.clone())
+    }
+
+    fn release_spec(&self) -> Option<ReleaseSpec> {
+        (self.kind == PluginKind::Release).then(|| ReleaseSpec {
+            asset: self.asset.clone(),
+            bins: self.bins.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_round_trip() {
+        let mut pinned = PluginInfo::new(
+            "u/pinned".to_string(),
+            "git@github.com:u/pinned.git".to_string(),
+            true,
+            Some("--depth 1".to_string()),
+        );
+        pinned.pinned = Some("abc123".to_string());
+        let plugins = vec![
+            PluginInfo::new("u/plain".to_string(), "https://github.com/u/plain.git".to_string(), false, None),
+            pinned,
+        ];
+
+        let json = Manifest::from_plugins(&plugins).to_json().unwrap();
+        assert!(!json.contains("installed_at"));
+        assert!(!json.contains("last_updated"));
+
+        let manifest = Manifest::from_json(&json).unwrap();
+        assert_eq!(manifest.version, MANIFEST_VERSION);
+        let names: Vec<_> = manifest.plugins.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["u/plain", "u/pinned"]);
+
+        let spec = &manifest.plugins[1];
+        assert_eq!(spec.url, "git@github.com:u/pinned.git");
+        assert!(spec.private);
+        assert_eq!(spec.flags.as_deref(), Some("--depth 1"));
+        assert_eq!(spec.pinned.as_deref(), Some("abc123"));
+        assert_eq!(manifest.plugins[0].pinned, None);
+    }
+
+    #[test]
+    fn from_json_accepts_minimal_entries_and_rejects_newer_versions() {
+        let manifest = Manifest::from_json(r#"{"version":1,"plugins":[{"name":"u/a","url":"https://x/u/a"}]}"#).unwrap();
+        assert!(!manifest.plugins[0].private);
+        assert_eq!(manifest.plugins[0].flags, None);
+
+        let newer = format!(r#"{{"version":{},"plugins":[]}}"#, MANIFEST_VERSION + 1);
+        assert!(Manifest::from_json(&newer).unwrap_err().to_string().contains("newer"));
+        assert!(Manifest::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn install_request_keeps_url_and_pin() {
+        let spec = PluginSpec {
+            name: "u/a".to_string(),
+            url: "https://gitlab.com/u/a.git".to_string(),
+            private: false,
+            flags: None,
+            pinned: Some("abc123".to_string()),
+            sparse: vec!["plugin".to_string()],
+            repo: None,
+            path: None,
+            kind: PluginKind::Git,
+            asset: None,
+            bins: Vec::new(),
+            build: vec!["make".to_string()],
+        };
+        let request = spec.install_request();
+        assert_eq!(request.url(), "https://gitlab.com/u/a.git");
+        assert_eq!(request.pin.as_deref(), Some("abc123"));
+        assert_eq!(request.sparse, ["plugin"]);
+        assert_eq!(request.local, None);
+        assert!(request.release.is_none());
+        assert_eq!(request.build, ["make"]);
+    }
+
+    #[test]
+    fn subdirectory_plugins_are_installed_from_their_repository() {
+        let mut info = PluginInfo::new("u/repo/plugins/git".to_string(), "https://github.com/u/repo.git".to_string(), false, None);
+        info.repo = Some("u/repo".to_string());
+        info.path = Some("plugins/git".to_string());
+
+        let request = PluginSpec::from(&info).install_request();
+        assert_eq!(request.name, "u/repo");
+        assert_eq!(request.path.as_deref(), Some("plugins/git"));
+        assert_eq!(request.plugin_name(), "u/repo/plugins/git");
+    }
+
+    #[test]
+    fn local_plugins_are_linked_from_their_directory() {
+        let mut info = PluginInfo::new("mine".to_string(), "/home/me/mine".to_string(), false, None);
+        info.kind = PluginKind::Local;
+
+        let spec = PluginSpec::from(&info);
+        assert_eq!(serde_json::to_value(&spec).unwrap()["kind"], "local");
+        assert_eq!(spec.install_request().local, Some(PathBuf::from("/home/me/mine")));
+    }
+
+    #[test]
+    fn release_plugins_keep_their_asset_and_binaries() {
+        let mut info = PluginInfo::new("u/tool".to_string(), "https://github.com/u/tool.git".to_string(), false, None);
+        info.kind = PluginKind::Release;
+        info.asset = Some("tool-*.tar.gz".to_string());
+        info.bins = vec!["tool".to_string()];
+
+        let release = PluginSpec::from(&info).install_request().release.unwrap();
+        assert_eq!(release.pattern(), "tool-*.tar.gz");
+        assert_eq!(release.bins, ["tool"]);
+    }
+}
@@ -0,0 +1,259 @@
+use git2::{
+    AutotagOption, Error, FetchOptions, Repository, RepositoryInitMode, RepositoryInitOptions,
+};
+use log::debug;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::clone::CloneProgress;
+use crate::credentials_manager::CredentialManager;
+
+/// Central cache of bare repositories, one per remote URL.
+///
+/// `add` and `update` fetch into the cache first; plugin working copies then borrow the
+/// cached objects through `objects/info/alternates` instead of downloading them again.
+/// The cache can be shared by several users on one machine when its directory is group-writable.
+pub struct ObjectCache {
+    root: PathBuf,
+    credentials: Arc<CredentialManager>,
+}
+
+impl ObjectCache {
+    pub fn new(root: PathBuf, credentials: Arc<CredentialManager>) -> Self {
+        Self { root, credentials }
+    }
+
+    /// Location of the bare repository caching `url`.
+    /// SSH and HTTPS URLs of the same repository map to the same entry
+    /// (`git@github.com:user/repo.git` and `https://github.com/user/repo` both become
+    /// `<root>/github.com/user/repo.git`).
+    pub fn path_for(&self, url: &str) -> PathBuf {
+        let key = url.trim_end_matches('/').trim_end_matches(".git");
+        let key = match key.split_once("://") {
+            Some((_, rest)) => rest,
+            None => key,
+        };
+        // Drop "user@" from "user@host:path" / "user@host/path"
+        let key = match key.split_once('@') {
+            Some((user, rest)) if !user.contains('/') => rest,
+            _ => key,
+        };
+
+        let components: Vec<_> = key
+            .split(['/', ':'])
+            .filter(|c| !c.is_empty() && *c != "." && *c != "..")
+            .collect();
+
+        let mut path = self.root.clone();
+        if let Some((last, parents)) = components.split_last() {
+            path.extend(parents);
+            path.push(format!("{}.git", last));
+        }
+        path
+    }
+
+    /// Fetch every branch and tag of `url` into its cache repository, creating it if needed
+    pub fn fetch(
+        &self,
+        url: &str,
+        identities: &[PathBuf],
+        progress: &(dyn Fn(CloneProgress) + Sync),
+    ) -> Result<Repository, Error> {
+        let path = self.path_for(url);
+
+        let repo = if path.exists() {
+            Repository::open_bare(&path)?
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| Error::from_str(&e.to_string()))?;
+            }
+            let mut init_options = RepositoryInitOptions::new();
+            init_options
+                .bare(true)
+                .mode(RepositoryInitMode::SHARED_GROUP);
+            Repository::init_opts(&path, &init_options)?
+        };
+
+        debug!("Fetching {} into object cache {}", url, path.display());
+        {
+            let mut callbacks = self.credentials.create_clone_callbacks(identities);
+            callbacks.transfer_progress(|stats| {
+                if let Some(update) = CloneProgress::from_transfer(&stats) {
+                    progress(update);
+                }
+                true
+            });
+
+            let mut fetch_options = FetchOptions::new();
+            fetch_options.remote_callbacks(callbacks);
+            fetch_options.download_tags(AutotagOption::All);
+
+            let mut remote = repo.remote_anonymous(&self.credentials.resolve_url(url))?;
+            remote.fetch(
+                &["+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*"],
+                Some(&mut fetch_options),
+                None,
+            )?;
+
+            // Remember the remote's default branch as HEAD of the cache repository
+            if let Ok(head) = remote.default_branch() {
+                if let Some(head) = head.as_str() {
+                    repo.set_head(head)?;
+                }
+            }
+        }
+
+        Ok(repo)
+    }
+
+    /// Branch the remote HEAD pointed to during the last fetch
+    pub fn default_branch(cache_repo: &Repository) -> Result<String, Error> {
+        let head = cache_repo.find_reference("HEAD")?;
+        let target = head
+            .symbolic_target()
+            .ok_or_else(|| Error::from_str("Object cache HEAD is not symbolic"))?;
+        Ok(target.trim_start_matches("refs/heads/").to_string())
+    }
+
+    /// Make `repo` borrow objects from `cache_repo` through git alternates
+    pub fn link(&self, repo: &Repository, cache_repo: &Repository) -> Result<(), Error> {
+        let objects = cache_repo.path().join("objects");
+        let objects = objects
+            .to_str()
+            .ok_or_else(|| Error::from_str("Object cache path is not valid UTF-8"))?;
+
+        let alternates = repo.path().join("objects/info/alternates");
+        let existing = fs::read_to_string(&alternates).unwrap_or_default();
+        if existing.lines().any(|line| line.trim() == objects) {
+            return Ok(());
+        }
+
+        debug!("Linking {} to object cache {}", repo.path().display(), objects);
+        let io_err = |e: std::io::Error| Error::from_str(&e.to_string());
+        if let Some(parent) = alternates.parent() {
+            fs::create_dir_all(parent).map_err(io_err)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&alternates)
+            .map_err(io_err)?;
+        writeln!(file, "{}", objects).map_err(io_err)?;
+
+        // Make the objects visible to the already opened repository as well
+        repo.odb()?.add_disk_alternate(objects)
+    }
+
+    /// Point the remote-tracking refs of `repo` at the cached branches (only `branch` if given)
+    /// and copy the cached tags, without transferring any objects
+    pub fn copy_refs(
+        repo: &Repository,
+        cache_repo: &Repository,
+        remote_name: &str,
+        branch: Option<&str>,
+    ) -> Result<(), Error> {
+        for reference in cache_repo.references()? {
+            let reference = reference?;
+            let (Some(name), Some(target)) = (reference.name(), reference.target()) else {
+                continue;
+            };
+
+            if let Some(cached_branch) = name.strip_prefix("refs/heads/") {
+                if branch.is_some_and(|b| b != cached_branch) {
+                    continue;
+                }
+                repo.reference(
+                    &format!("refs/remotes/{}/{}", remote_name, cached_branch),
+                    target,
+                    true,
+                    "fetch: from object cache",
+                )?;
+            } else if name.starts_with("refs/tags/") {
+                repo.reference(name, target, true, "fetch: tag from object cache")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete `remote_name`'s tracking branches whose branch no longer exists in the cache
+    pub fn prune_refs(repo: &Repository, cache_repo: &Repository, remote_name: &str) -> Result<(), Error> {
+        let prefix = format!("refs/remotes/{}/", remote_name);
+        for reference in repo.references_glob(&format!("{}*", prefix))? {
+            let mut reference = reference?;
+            let Some(branch) = reference.name().and_then(|name| name.strip_prefix(&prefix)) else {
+                continue;
+            };
+            if branch == "HEAD" || cache_repo.find_reference(&format!("refs/heads/{}", branch)).is_ok() {
+                continue;
+            }
+            debug!("Pruning {}{}", prefix, branch);
+            reference.delete()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{commit_file, init_repo, TempDir};
+    use std::path::Path;
+
+    fn cache(root: &Path) -> ObjectCache {
+        ObjectCache::new(root.to_path_buf(), Arc::new(CredentialManager::new().unwrap()))
+    }
+
+    #[test]
+    fn path_for_maps_ssh_and_https_urls_to_one_entry() {
+        let expected = Path::new("/cache/github.com/user/repo.git");
+        let cache = cache(Path::new("/cache"));
+        assert_eq!(cache.path_for("https://github.com/user/repo.git"), expected);
+        assert_eq!(cache.path_for("https://github.com/user/repo/"), expected);
+        assert_eq!(cache.path_for("git@github.com:user/repo.git"), expected);
+        assert_eq!(cache.path_for("ssh://git@github.com/user/repo"), expected);
+    }
+
+    #[test]
+    fn path_for_keeps_ports_and_paths_inside_the_root() {
+        let cache = cache(Path::new("/cache"));
+        assert_eq!(
+            cache.path_for("ssh://git@example.com:2222/group/sub/repo.git"),
+            Path::new("/cache/example.com/2222/group/sub/repo.git")
+        );
+        assert_eq!(
+            cache.path_for("https://example.com/../../etc/./repo"),
+            Path::new("/cache/example.com/etc/repo.git")
+        );
+    }
+
+    #[test]
+    fn fetch_link_and_copy_refs() {
+        let tmp = TempDir::new("object-cache");
+        let upstream_path = tmp.path().join("upstream");
+        let upstream = init_repo(&upstream_path);
+        let main = commit_file(&upstream, "a.zsh", "echo a\n", "Add a");
+        upstream.branch("dev", &upstream.find_commit(main).unwrap(), false).unwrap();
+        upstream.tag_lightweight("v1", upstream.find_commit(main).unwrap().as_object(), false).unwrap();
+
+        let cache = cache(&tmp.path().join("cache"));
+        let url = upstream_path.to_str().unwrap();
+        let cache_repo = cache.fetch(url, &[], &|_| {}).unwrap();
+        assert!(cache_repo.is_bare());
+        assert_eq!(cache_repo.path(), cache.path_for(url).join(""));
+        assert_eq!(ObjectCache::default_branch(&cache_repo).unwrap(), "main");
+
+        let repo = Repository::init(tmp.path().join("plugin")).unwrap();
+        cache.link(&repo, &cache_repo).unwrap();
+        cache.link(&repo, &cache_repo).unwrap();
+        let alternates = fs::read_to_string(repo.path().join("objects/info/alternates")).unwrap();
+        assert_eq!(alternates.lines().count(), 1);
+        assert!(repo.find_commit(main).is_ok());
+
+        ObjectCache::copy_refs(&repo, &cache_repo, "origin", Some("main")).unwrap();
+        assert_eq!(repo.refname_to_id("refs/remotes/origin/main").unwrap(), main);
+        assert!(repo.find_reference("refs/remotes/origin/dev").is_err());
+        assert_eq!(repo.refname_to_id("refs/tags/v1").unwrap(), main);
+    }
+}
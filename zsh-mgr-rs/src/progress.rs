@@ -0,0 +1,271 @@
+use colored::Colorize;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::clone::CloneProgress;
+
+/// Minimum time between two redraws caused only by transfer progress
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Status of a repository in a parallel batch
+#[derive(Debug, Clone)]
+pub enum RepoStatus {
+    Pending,
+    /// Queued behind other fetches to the same host
+    Waiting,
+    Cloning,
+    Fetching,
+    Merging,
+    Success,
+    /// Left alone on purpose, with the reason
+    Skipped(String),
+    Failed(String),
+}
+
+impl RepoStatus {
+    pub fn to_string(&self, repo_name: &str) -> String {
+        match self {
+            RepoStatus::Pending => format!("⏳ {} {} {}", "[".bright_black(), format!("{:<50}", repo_name).bright_black(), "]".bright_black()),
+            RepoStatus::Waiting => format!("⏸  {} {} {} - {}", "[".bright_black(), format!("{:<50}", repo_name).bright_black(), "]".bright_black(), "waiting".bright_black()),
+            RepoStatus::Cloning => format!("📦 {} {} {}", "[".cyan(), format!("{:<50}", repo_name).cyan(), "]".cyan()),
+            RepoStatus::Fetching => format!("🔄 {} {} {}", "[".cyan(), format!("{:<50}", repo_name).cyan(), "]".cyan()),
+            RepoStatus::Merging => format!("⬇️  {} {} {}", "[".yellow(), format!("{:<50}", repo_name).yellow(), "]".yellow()),
+            RepoStatus::Success => format!("✓ {} {} {}", "[".green(), format!("{:<50}", repo_name).green(), "]".green()),
+            RepoStatus::Skipped(reason) => format!("⏭  {} {} {} - {}", "[".yellow(), format!("{:<50}", repo_name).yellow(), "]".yellow(), reason.yellow()),
+            RepoStatus::Failed(err) => format!("✗ {} {} {} - {}", "[".red(), format!("{:<50}", repo_name).red(), "]".red(), err.red()),
+        }
+    }
+
+    /// Whether the repository is still being worked on
+    fn is_active(&self) -> bool {
+        matches!(self, RepoStatus::Cloning | RepoStatus::Fetching | RepoStatus::Merging)
+    }
+}
+
+/// Transfer stats shown next to an active repository, e.g. "12/40 objects, 3.1 KiB"
+fn transfer_detail(progress: &CloneProgress) -> String {
+    match *progress {
+        CloneProgress::Receiving { received_objects, total_objects, received_bytes } => format!(
+            "{}/{} objects, {}",
+            received_objects,
+            total_objects,
+            format_bytes(received_bytes)
+        ),
+        CloneProgress::Resolving { indexed_deltas, total_deltas } => {
+            format!("resolving {}/{} deltas", indexed_deltas, total_deltas)
+        }
+        CloneProgress::CheckingOut { completed, total } => {
+            format!("checking out {}/{} files", completed, total)
+        }
+    }
+}
+
+/// Human-readable size, e.g. "3.1 KiB"
+pub fn format_bytes(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+struct Row {
+    name: String,
+    status: RepoStatus,
+    transfer: Option<CloneProgress>,
+}
+
+impl Row {
+    fn render(&self) -> String {
+        let line = self.status.to_string(&self.name);
+        match self.transfer {
+            Some(ref transfer) if self.status.is_active() => {
+                format!("{} {}", line, transfer_detail(transfer).dimmed())
+            }
+            _ => line,
+        }
+    }
+}
+
+struct State {
+    rows: Vec<Row>,
+    /// Message shown below the rows, e.g. that the batch was interrupted
+    footer: Option<String>,
+    /// Lines written by the last draw, to move back up over on the next one
+    drawn: usize,
+    last_draw: Instant,
+}
+
+/// Manages real-time progress display for parallel operations: one line per repository on
+/// stderr, redrawn in place whenever a status changes. When stderr is not a terminal, each
+/// status change is printed as a line of its own instead. Rows are addressed by their position
+/// in the names given to `new`, since two repositories may be shown under the same name.
+pub struct ProgressDisplay {
+    state: Mutex<State>,
+    live: bool,
+}
+
+impl ProgressDisplay {
+    pub fn new(names: Vec<String>) -> Self {
+        let rows = names
+            .into_iter()
+            .map(|name| Row { name, status: RepoStatus::Pending, transfer: None })
+            .collect();
+
+        Self {
+            state: Mutex::new(State { rows, footer: None, drawn: 0, last_draw: Instant::now() }),
+            live: io::stderr().is_terminal(),
+        }
+    }
+
+    pub fn update_status(&self, row: usize, status: RepoStatus) {
+        if let Ok(mut state) = self.state.lock() {
+            let Some(row) = state.rows.get_mut(row) else {
+                return;
+            };
+            row.status = status;
+            row.transfer = None;
+
+            if self.live {
+                Self::redraw(&mut state);
+            } else if !matches!(row.status, RepoStatus::Pending) {
+                eprintln!("{}", row.render());
+            }
+        }
+    }
+
+    /// Record transfer progress for a repository; only shown on a terminal
+    pub fn update_transfer(&self, row: usize, progress: CloneProgress) {
+        if !self.live {
+            return;
+        }
+        if let Ok(mut state) = self.state.lock() {
+            if let Some(row) = state.rows.get_mut(row) {
+                row.transfer = Some(progress);
+            }
+            if state.last_draw.elapsed() >= REDRAW_INTERVAL {
+                Self::redraw(&mut state);
+            }
+        }
+    }
+
+    fn redraw(state: &mut State) {
+        let mut stderr = io::stderr().lock();
+
+        // Move cursor up to the beginning of our progress section
+        if state.drawn > 0 {
+            let _ = write!(stderr, "\x1B[{}A", state.drawn);
+        }
+
+        // Clear and redraw all lines
+        for line in Self::lines(state) {
+            let _ = writeln!(stderr, "\r\x1B[2K{}", line);
+        }
+
+        stderr.flush().ok();
+        state.drawn = state.rows.len() + usize::from(state.footer.is_some());
+        state.last_draw = Instant::now();
+    }
+
+    fn lines(state: &State) -> impl Iterator<Item = String> + '_ {
+        state.rows.iter().map(Row::render).chain(state.footer.clone())
+    }
+
+    /// Show `message` below the table, where printing it would be overwritten by the next redraw
+    pub fn set_footer(&self, message: String) {
+        if !self.live {
+            eprintln!("{}", message);
+            return;
+        }
+        if let Ok(mut state) = self.state.lock() {
+            state.footer = Some(message);
+            Self::redraw(&mut state);
+        }
+    }
+
+    pub fn initial_draw(&self) {
+        if !self.live {
+            return;
+        }
+        if let Ok(mut state) = self.state.lock() {
+            let mut stderr = io::stderr().lock();
+            for line in Self::lines(&state) {
+                let _ = writeln!(stderr, "{}", line);
+            }
+            state.drawn = state.rows.len() + usize::from(state.footer.is_some());
+            state.last_draw = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_line_shows_the_name_and_error() {
+        colored::control::set_override(false);
+        let line = RepoStatus::Failed("timed out".to_string()).to_string("u/repo");
+        assert!(line.starts_with("✗ [ u/repo "));
+        assert!(line.ends_with("] - timed out"));
+        assert!(RepoStatus::Success.to_string("u/repo").starts_with("✓ [ u/repo"));
+    }
+
+    #[test]
+    fn update_status_only_touches_the_given_row() {
+        let display = ProgressDisplay::new(vec!["foo".to_string(), "foo".to_string()]);
+        display.update_status(1, RepoStatus::Cloning);
+        display.update_status(2, RepoStatus::Success);
+
+        let state = display.state.lock().unwrap();
+        assert!(matches!(state.rows[0].status, RepoStatus::Pending));
+        assert!(matches!(state.rows[1].status, RepoStatus::Cloning));
+    }
+
+    #[test]
+    fn the_footer_is_drawn_below_the_rows() {
+        colored::control::set_override(false);
+        let display = ProgressDisplay { live: true, ..ProgressDisplay::new(vec!["a".to_string()]) };
+        display.initial_draw();
+        display.set_footer("Interrupted".to_string());
+
+        let state = display.state.lock().unwrap();
+        let lines: Vec<_> = ProgressDisplay::lines(&state).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("⏳ [ a "));
+        assert_eq!(lines[1], "Interrupted");
+        assert_eq!(state.drawn, 2);
+    }
+
+    #[test]
+    fn format_bytes_picks_a_unit() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3174), "3.1 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 + 512 * 1024), "5.5 MiB");
+    }
+
+    #[test]
+    fn transfer_stats_are_shown_for_active_rows_only() {
+        colored::control::set_override(false);
+        let transfer = CloneProgress::Receiving {
+            received_objects: 12,
+            total_objects: 40,
+            received_bytes: 2048,
+        };
+        let mut row = Row {
+            name: "repo".to_string(),
+            status: RepoStatus::Fetching,
+            transfer: Some(transfer),
+        };
+        assert!(row.render().ends_with("] 12/40 objects, 2.0 KiB"));
+
+        row.status = RepoStatus::Success;
+        assert!(row.render().ends_with(']'));
+
+        let resolving = CloneProgress::Resolving { indexed_deltas: 3, total_deltas: 9 };
+        assert_eq!(transfer_detail(&resolving), "resolving 3/9 deltas");
+    }
+}
@@ -0,0 +1,461 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use log::{debug, warn};
+use serde::Deserialize;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::http;
+use crate::snippet::checksum;
+use crate::tokens::{http_host, TokenStore};
+
+/// Release API used when `config.json` sets none
+pub const DEFAULT_RELEASE_API: &str = "https://api.github.com";
+
+/// Asset pattern used when a plugin declares none
+pub const DEFAULT_ASSET_PATTERN: &str = "*{os}*{arch}*";
+
+/// Largest release asset accepted
+const MAX_ASSET_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Largest checksums file accepted
+const MAX_CHECKSUMS_SIZE: u64 = 1024 * 1024;
+
+/// What a release plugin installs from the releases of its repository
+#[derive(Debug, Clone, Default)]
+pub struct ReleaseSpec {
+    /// Pattern of the asset to download; `DEFAULT_ASSET_PATTERN` if `None`
+    pub asset: Option<String>,
+    /// Binaries to link into the bin directory
+    pub bins: Vec<String>,
+}
+
+impl ReleaseSpec {
+    pub fn pattern(&self) -> &str {
+        self.asset.as_deref().unwrap_or(DEFAULT_ASSET_PATTERN)
+    }
+}
+
+/// Directory the binaries of release plugins are linked into
+pub fn bin_dir(plugin_dir: &Path) -> PathBuf {
+    plugin_dir.join(".bin")
+}
+
+/// A release as returned by `GET /repos/{owner}/{repo}/releases/latest`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+    /// `sha256:<hex>`, published by GitHub for newer assets
+    #[serde(default)]
+    pub digest: Option<String>,
+}
+
+/// A release asset that was downloaded, verified and extracted
+#[derive(Debug)]
+pub struct Extracted {
+    pub tag: String,
+    pub asset: String,
+    /// SHA-256 of the asset, hex encoded
+    pub checksum: String,
+}
+
+/// Client for the release API of GitHub or a server that mirrors it
+pub struct ReleaseClient {
+    api: String,
+    tokens: TokenStore,
+}
+
+impl ReleaseClient {
+    pub fn new(api: &str, tokens: TokenStore) -> Self {
+        Self {
+            api: api.trim_end_matches('/').to_string(),
+            tokens,
+        }
+    }
+
+    /// Latest release of `repo` ("owner/name")
+    pub fn latest(&self, repo: &str) -> Result<Release> {
+        let url = format!("{}/repos/{}/releases/latest", self.api, repo);
+        debug!("Looking up the latest release of {} at {}", repo, url);
+        let request = self.request(&url).set("Accept", "application/vnd.github+json");
+        let body = http::read_body(http::call(request)?, MAX_CHECKSUMS_SIZE)?;
+        serde_json::from_slice(&body).with_context(|| format!("Invalid release from {}", url))
+    }
+
+    fn download(&self, url: &str, max_size: u64) -> Result<Vec<u8>> {
+        http::read_body(http::call(self.request(url))?, max_size)
+    }
+
+    /// GET request, authenticated with the token of the API host when going to that host
+    fn request(&self, url: &str) -> ureq::Request {
+        let request = http::agent().get(url);
+        if http_host(url).is_none() || http_host(url) != http_host(&self.api) {
+            return request;
+        }
+        match self.tokens.token_for_url(url) {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token.token)),
+            None => request,
+        }
+    }
+
+    /// Download the asset of `release` matching `pattern`, verify it against its published
+    /// checksum and extract it into `dest`, which must contain `bins` afterwards
+    pub fn extract(&self, release: &Release, pattern: &str, bins: &[String], dest: &Path) -> Result<Extracted> {
+        let asset = find_asset(release, pattern)?;
+        let expected = self.expected_checksum(release, asset)?;
+
+        let content = self.download(&asset.browser_download_url, MAX_ASSET_SIZE)?;
+        let actual = checksum(&content);
+        if !actual.eq_ignore_ascii_case(&expected) {
+            bail!("Checksum mismatch for {}: expected {}, got {}", asset.name, expected, actual);
+        }
+
+        fs::create_dir_all(dest)?;
+        if is_tar_gz(&asset.name) {
+            tar::Archive::new(GzDecoder::new(content.as_slice()))
+                .unpack(dest)
+                .with_context(|| format!("Failed to extract {}", asset.name))?;
+        } else {
+            // A bare executable, named after the binary it provides
+            let name = bins.first().map(String::as_str).unwrap_or(&asset.name);
+            let file = dest.join(name);
+            fs::write(&file, &content)?;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o755))?;
+        }
+
+        for bin in bins {
+            if find_bin(dest, bin).is_none() {
+                bail!("{} does not contain the binary '{}'", asset.name, bin);
+            }
+        }
+
+        Ok(Extracted {
+            tag: release.tag_name.clone(),
+            asset: asset.name.clone(),
+            checksum: actual,
+        })
+    }
+
+    /// SHA-256 the release publishes for `asset`, from the asset's digest or a checksums file
+    fn expected_checksum(&self, release: &Release, asset: &Asset) -> Result<String> {
+        if let Some(hex) = asset.digest.as_deref().and_then(|d| d.strip_prefix("sha256:")) {
+            return Ok(hex.to_string());
+        }
+
+        let own_file = format!("{}.sha256", asset.name);
+        let candidates = release.assets.iter().filter(|a| {
+            let name = a.name.to_ascii_lowercase();
+            a.name == own_file || name.contains("checksums") || name.contains("sha256sums")
+        });
+        for candidate in candidates {
+            let contents = self.download(&candidate.browser_download_url, MAX_CHECKSUMS_SIZE)?;
+            let contents = String::from_utf8_lossy(&contents);
+            if let Some(hex) = parse_checksums(&contents, &asset.name, candidate.name == own_file) {
+                return Ok(hex);
+            }
+        }
+        bail!("Release {} publishes no SHA-256 checksum for {}", release.tag_name, asset.name)
+    }
+}
+
+/// Find the checksum of `file` in a `sha256sum`-style listing. A file of its own may hold
+/// just the checksum.
+fn parse_checksums(contents: &str, file: &str, own_file: bool) -> Option<String> {
+    let is_hex = |s: &str| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit());
+    for line in contents.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(hex), name) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let name = name.map(|n| n.trim_start_matches('*'));
+        if is_hex(hex) && (name == Some(file) || (own_file && name.is_none_or(|n| n.ends_with(file)))) {
+            return Some(hex.to_ascii_lowercase());
+        }
+    }
+    None
+}
+
+/// Asset of `release` for this machine. `{os}` and `{arch}` in `pattern` stand for the names
+/// releases commonly use for them, `*` and `?` are wildcards and case is ignored.
+pub fn find_asset<'a>(release: &'a Release, pattern: &str) -> Result<&'a Asset> {
+    let patterns = expand_pattern(pattern);
+    let mut matching: Vec<_> = release
+        .assets
+        .iter()
+        .filter(|asset| !is_auxiliary(&asset.name))
+        .filter(|asset| patterns.iter().any(|p| glob_match(p, &asset.name.to_ascii_lowercase())))
+        .collect();
+    // Archives win over bare files, e.g. packages for other package managers
+    matching.sort_by_key(|asset| !is_tar_gz(&asset.name));
+    match matching.first() {
+        Some(asset) => {
+            if matching.len() > 1 {
+                warn!("Several assets of {} match '{}', using {}", release.tag_name, pattern, asset.name);
+            }
+            Ok(asset)
+        }
+        None => bail!(
+            "No asset of release {} matches '{}' for {}/{}",
+            release.tag_name,
+            pattern,
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        ),
+    }
+}
+
+fn expand_pattern(pattern: &str) -> Vec<String> {
+    let pattern = pattern.to_ascii_lowercase();
+    let mut patterns = Vec::new();
+    for os in os_names() {
+        for arch in arch_names() {
+            let expanded = pattern.replace("{os}", os).replace("{arch}", arch);
+            if !patterns.contains(&expanded) {
+                patterns.push(expanded);
+            }
+        }
+    }
+    patterns
+}
+
+fn os_names() -> &'static [&'static str] {
+    match std::env::consts::OS {
+        "macos" => &["darwin", "macos", "apple"],
+        "linux" => &["linux"],
+        "freebsd" => &["freebsd"],
+        _ => &[std::env::consts::OS],
+    }
+}
+
+fn arch_names() -> &'static [&'static str] {
+    match std::env::consts::ARCH {
+        "x86_64" => &["x86_64", "amd64", "x64"],
+        "aarch64" => &["aarch64", "arm64"],
+        "arm" => &["armv7", "armhf", "arm"],
+        "x86" => &["i386", "i686", "386", "x86"],
+        _ => &[std::env::consts::ARCH],
+    }
+}
+
+/// `*` matches any run of characters and `?` any single one
+fn glob_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+            Some(('?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+            Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+        }
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
+fn is_tar_gz(name: &str) -> bool {
+    name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+/// Checksums, signatures and software bills of materials published next to the assets
+fn is_auxiliary(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    [".sha256", ".sha512", ".asc", ".sig", ".pem", ".sbom", ".json", ".txt"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+        || name.contains("checksums")
+}
+
+/// Executable named `bin` in the extracted release at `root`
+pub fn find_bin(root: &Path, bin: &str) -> Option<PathBuf> {
+    walkdir::WalkDir::new(root)
+        .into_iter()
+        .flatten()
+        .find(|entry| entry.file_type().is_file() && entry.file_name() == bin)
+        .map(|entry| entry.into_path())
+}
+
+/// Link `bins` of the release plugin at `plugin_path` into `bin_dir`, replacing links that
+/// already point into the plugin
+pub fn link_bins(bin_dir: &Path, plugin_path: &Path, bins: &[String]) -> Result<()> {
+    fs::create_dir_all(bin_dir)?;
+    for bin in bins {
+        let target = find_bin(plugin_path, bin)
+            .with_context(|| format!("{} does not contain the binary '{}'", plugin_path.display(), bin))?;
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o755))?;
+
+        let link = bin_dir.join(bin);
+        if let Ok(existing) = fs::read_link(&link) {
+            if !existing.starts_with(plugin_path) {
+                bail!("{} is already provided by {}", link.display(), existing.display());
+            }
+            fs::remove_file(&link)?;
+        } else if link.symlink_metadata().is_ok() {
+            bail!("{} already exists and is not a link", link.display());
+        }
+        std::os::unix::fs::symlink(&target, &link)
+            .with_context(|| format!("Failed to link {}", link.display()))?;
+    }
+    Ok(())
+}
+
+/// Links in `bin_dir` that point into the release plugin at `plugin_path`, with their targets
+pub fn bin_links(bin_dir: &Path, plugin_path: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let Ok(entries) = fs::read_dir(bin_dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let target = fs::read_link(entry.path()).ok()?;
+            target.starts_with(plugin_path).then(|| (entry.path(), target))
+        })
+        .collect()
+}
+
+/// Re-create links returned by `bin_links`
+pub fn restore_links(links: &[(PathBuf, PathBuf)]) -> Result<()> {
+    for (link, target) in links {
+        std::os::unix::fs::symlink(target, link).with_context(|| format!("Failed to link {}", link.display()))?;
+    }
+    Ok(())
+}
+
+/// Remove the links in `bin_dir` that point into the release plugin at `plugin_path`
+pub fn unlink_bins(bin_dir: &Path, plugin_path: &Path) -> Result<()> {
+    for (link, _) in bin_links(bin_dir, plugin_path) {
+        fs::remove_file(link)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{tool_archive, tool_release, HttpServer, TempDir};
+
+    const HEX: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn release(names: &[&str]) -> Release {
+        Release {
+            tag_name: "v1.0.0".to_string(),
+            assets: names
+                .iter()
+                .map(|name| Asset {
+                    name: name.to_string(),
+                    browser_download_url: format!("https://example.com/{}", name),
+                    digest: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn client(server: &HttpServer) -> ReleaseClient {
+        ReleaseClient::new(&server.url(), TokenStore::default())
+    }
+
+    #[test]
+    fn find_asset_picks_this_machine_and_prefers_archives() {
+        let own = format!("tool-{}-{}", os_names()[0], arch_names()[0]);
+        let names = [
+            format!("{}.deb", own),
+            format!("{}.tar.gz", own),
+            format!("{}.tar.gz.sha256", own),
+            "tool-plan9-mips.tar.gz".to_string(),
+            "checksums.txt".to_string(),
+        ];
+        let release = release(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        assert_eq!(find_asset(&release, DEFAULT_ASSET_PATTERN).unwrap().name, format!("{}.tar.gz", own));
+    }
+
+    #[test]
+    fn find_asset_ignores_case_and_fails_without_match() {
+        let release = release(&["Tool-Plan9-MIPS.tgz", "tool.sig"]);
+        assert_eq!(find_asset(&release, "tool-plan9-*").unwrap().name, "Tool-Plan9-MIPS.tgz");
+        assert!(find_asset(&release, "tool.s?g").is_err());
+        assert!(find_asset(&release, "other-*").is_err());
+    }
+
+    #[test]
+    fn parse_checksums_finds_the_file_in_a_listing() {
+        let listing = format!("{0}  tool-a.tar.gz\n{1} *tool-b.tar.gz\n", "0".repeat(64), HEX.to_uppercase());
+        assert_eq!(parse_checksums(&listing, "tool-b.tar.gz", false).as_deref(), Some(HEX));
+        assert_eq!(parse_checksums(&listing, "tool.tar.gz", false), None);
+        assert_eq!(parse_checksums("not a checksum  tool.tar.gz\n", "tool.tar.gz", false), None);
+    }
+
+    #[test]
+    fn parse_checksums_accepts_a_bare_checksum_in_its_own_file() {
+        assert_eq!(parse_checksums(&format!("{}\n", HEX), "tool.tar.gz", true).as_deref(), Some(HEX));
+        assert_eq!(parse_checksums(&format!("{}\n", HEX), "tool.tar.gz", false), None);
+        let own = format!("{}  dist/tool.tar.gz\n", HEX);
+        assert_eq!(parse_checksums(&own, "tool.tar.gz", true).as_deref(), Some(HEX));
+    }
+
+    #[test]
+    fn extract_verifies_and_unpacks_the_latest_release() {
+        let tmp = TempDir::new("release");
+        let server = HttpServer::start(|base| tool_release(base, "v1.0.0"));
+        let client = client(&server);
+
+        let latest = client.latest("u/tool").unwrap();
+        assert_eq!(latest.tag_name, "v1.0.0");
+        let extracted = client
+            .extract(&latest, "tool-*", &["tool".to_string()], &tmp.path().join("out"))
+            .unwrap();
+        assert_eq!((extracted.tag.as_str(), extracted.asset.as_str()), ("v1.0.0", "tool-any.tar.gz"));
+        assert_eq!(extracted.checksum, checksum(&tool_archive("v1.0.0")));
+        assert!(find_bin(&tmp.path().join("out"), "tool").is_some());
+
+        let err = client.extract(&latest, "tool-*", &["other".to_string()], &tmp.path().join("out2")).unwrap_err();
+        assert!(err.to_string().contains("does not contain the binary 'other'"));
+        assert!(client.latest("u/missing").unwrap_err().to_string().contains("HTTP 404"));
+    }
+
+    #[test]
+    fn extract_rejects_checksum_mismatches() {
+        let tmp = TempDir::new("release");
+        let server = HttpServer::start(|base| {
+            let mut routes = tool_release(base, "v1.0.0");
+            routes.retain(|(path, _)| !path.ends_with("checksums.txt"));
+            routes.push(("/dl/v1.0.0/checksums.txt".to_string(), format!("{}  tool-any.tar.gz\n", HEX).into_bytes()));
+            routes
+        });
+        let client = client(&server);
+
+        let latest = client.latest("u/tool").unwrap();
+        let err = client.extract(&latest, "tool-*", &[], &tmp.path().join("out")).unwrap_err();
+        assert!(err.to_string().starts_with("Checksum mismatch for tool-any.tar.gz"));
+        assert!(!tmp.path().join("out").exists());
+    }
+
+    #[test]
+    fn bins_are_linked_and_unlinked_per_plugin() {
+        let tmp = TempDir::new("release");
+        let (bin_dir, plugin, other) = (tmp.path().join(".bin"), tmp.path().join("u/tool"), tmp.path().join("v/tool"));
+        for path in [&plugin, &other] {
+            fs::create_dir_all(path.join("bin")).unwrap();
+            fs::write(path.join("bin/tool"), "#!/bin/sh\n").unwrap();
+        }
+
+        link_bins(&bin_dir, &plugin, &["tool".to_string()]).unwrap();
+        assert_eq!(fs::read_link(bin_dir.join("tool")).unwrap(), plugin.join("bin/tool"));
+        assert_eq!(fs::metadata(plugin.join("bin/tool")).unwrap().permissions().mode() & 0o111, 0o111);
+        // Linking again replaces the plugin's own link, another plugin's is refused
+        link_bins(&bin_dir, &plugin, &["tool".to_string()]).unwrap();
+        assert!(link_bins(&bin_dir, &other, &["tool".to_string()]).unwrap_err().to_string().contains("already provided"));
+
+        unlink_bins(&bin_dir, &other).unwrap();
+        assert!(bin_dir.join("tool").symlink_metadata().is_ok());
+        unlink_bins(&bin_dir, &plugin).unwrap();
+        assert!(bin_dir.join("tool").symlink_metadata().is_err());
+    }
+}
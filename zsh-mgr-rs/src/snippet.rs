@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::http;
+
+/// Largest snippet accepted; anything bigger is not a single zsh file
+const MAX_SNIPPET_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Contents of a snippet as downloaded from its source
+pub struct Download {
+    pub content: Vec<u8>,
+    /// SHA-256 of `content`, hex encoded
+    pub checksum: String,
+    /// ETag sent by the server, used to skip the download next time if nothing changed
+    pub etag: Option<String>,
+}
+
+/// URL a snippet is downloaded from: HTTP(S) and `file://` URLs are kept, anything else is
+/// taken as a path to a local file
+pub fn source_url(source: &str) -> Result<String> {
+    if ["http://", "https://", "file://"].iter().any(|scheme| source.starts_with(scheme)) {
+        return Ok(source.to_string());
+    }
+    let path = PathBuf::from(shellexpand::tilde(source).to_string());
+    let path = fs::canonicalize(&path).with_context(|| format!("Snippet not found: {}", path.display()))?;
+    Ok(format!("file://{}", path.display()))
+}
+
+/// Plugin name for a snippet: its file name without `.plugin.zsh`, `.zsh` or `.sh`
+pub fn snippet_name(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file = path.rsplit('/').next()?;
+    let name = [".plugin.zsh", ".zsh", ".sh"]
+        .iter()
+        .find_map(|ext| file.strip_suffix(ext))
+        .unwrap_or(file);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// File a snippet plugin is stored in, named so that it is sourced like any other plugin
+pub fn snippet_file(plugin_path: &Path) -> PathBuf {
+    let name = plugin_path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    plugin_path.join(format!("{}.plugin.zsh", name))
+}
+
+pub fn checksum(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Download the snippet at `url`. With the `etag` of the last download the server may answer
+/// that nothing changed, in which case `None` is returned.
+pub fn download(url: &str, etag: Option<&str>) -> Result<Option<Download>> {
+    if let Some(path) = url.strip_prefix("file://") {
+        let content = fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+        return Ok(Some(Download { checksum: checksum(&content), content, etag: None }));
+    }
+
+    debug!("Downloading snippet {}", url);
+    let mut request = http::agent().get(url);
+    if let Some(etag) = etag {
+        request = request.set("If-None-Match", etag);
+    }
+    let response = http::call(request)?;
+    if response.status() == 304 {
+        return Ok(None);
+    }
+
+    let etag = response.header("ETag").map(str::to_string);
+    let content = http::read_body(response, MAX_SNIPPET_SIZE)?;
+    Ok(Some(Download { checksum: checksum(&content), content, etag }))
+}
+
+/// Replace the file of a snippet in `plugin_path` without ever leaving it half written
+pub fn write(plugin_path: &Path, content: &[u8]) -> Result<()> {
+    let file = snippet_file(plugin_path);
+    let partial = file.with_extension("partial");
+    fs::write(&partial, content)?;
+    fs::rename(&partial, &file)?;
+    Ok(())
+}
+
+/// Outcome of updating a snippet
+pub struct SnippetUpdate {
+    /// The file was replaced with new contents
+    pub changed: bool,
+    pub checksum: String,
+    pub etag: Option<String>,
+}
+
+/// Download the snippet of `plugin_path` again and replace its file if the contents changed
+pub fn update(plugin_path: &Path, url: &str, checksum: Option<&str>, etag: Option<&str>) -> Result<SnippetUpdate> {
+    let file = snippet_file(plugin_path);
+    let unchanged = |etag: Option<String>| SnippetUpdate {
+        changed: false,
+        checksum: checksum.map(str::to_string).unwrap_or_default(),
+        etag,
+    };
+    // Without its file the snippet is downloaded again even if the server has nothing new
+    let etag = etag.filter(|_| file.exists());
+
+    let Some(download) = download(url, etag)? else {
+        return Ok(unchanged(etag.map(str::to_string)));
+    };
+    if file.exists() && checksum == Some(download.checksum.as_str()) {
+        return Ok(unchanged(download.etag));
+    }
+    write(plugin_path, &download.content)?;
+    Ok(SnippetUpdate {
+        changed: true,
+        checksum: download.checksum,
+        etag: download.etag,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Serve `content` with `etag` over HTTP, answering 304 to requests that already have it
+    fn serve(content: &'static str, etag: &'static str) -> (String, std::thread::JoinHandle<Vec<u16>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/snippet.zsh", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut statuses = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut cached = false;
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    cached |= line.to_ascii_lowercase().starts_with("if-none-match:") && line.contains(etag);
+                }
+                let response = if cached {
+                    statuses.push(304);
+                    "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    statuses.push(200);
+                    format!(
+                        "HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        etag,
+                        content.len(),
+                        content
+                    )
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            statuses
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn snippet_name_strips_the_zsh_extensions() {
+        assert_eq!(snippet_name("https://example.com/a/git.plugin.zsh").as_deref(), Some("git"));
+        assert_eq!(snippet_name("https://example.com/prompt.zsh?raw=1#top").as_deref(), Some("prompt"));
+        assert_eq!(snippet_name("file:///home/me/aliases.sh").as_deref(), Some("aliases"));
+        assert_eq!(snippet_name("https://example.com/functions").as_deref(), Some("functions"));
+        assert_eq!(snippet_name("https://example.com/").as_deref(), None);
+        assert_eq!(snippet_name("https://example.com/.zsh").as_deref(), None);
+    }
+
+    #[test]
+    fn source_url_turns_paths_into_file_urls() {
+        let tmp = TempDir::new("snippet");
+        let file = tmp.path().join("aliases.zsh");
+        fs::write(&file, "alias l=ls\n").unwrap();
+
+        assert_eq!(source_url("https://example.com/a.zsh").unwrap(), "https://example.com/a.zsh");
+        assert_eq!(source_url("file:///a.zsh").unwrap(), "file:///a.zsh");
+        let expected = format!("file://{}", fs::canonicalize(&file).unwrap().display());
+        assert_eq!(source_url(file.to_str().unwrap()).unwrap(), expected);
+        assert!(source_url(tmp.path().join("missing.zsh").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn snippets_are_stored_as_plugin_files() {
+        assert_eq!(snippet_file(Path::new("/plugins/git")), Path::new("/plugins/git/git.plugin.zsh"));
+    }
+
+    #[test]
+    fn update_replaces_the_file_only_when_the_contents_change() {
+        let tmp = TempDir::new("snippet");
+        let source = tmp.path().join("source.zsh");
+        let plugin = tmp.path().join("plugins/source");
+        fs::create_dir_all(&plugin).unwrap();
+        fs::write(&source, "echo one\n").unwrap();
+        let url = source_url(source.to_str().unwrap()).unwrap();
+
+        let first = update(&plugin, &url, None, None).unwrap();
+        assert!(first.changed);
+        assert_eq!(first.checksum, checksum(b"echo one\n"));
+        assert_eq!(fs::read_to_string(snippet_file(&plugin)).unwrap(), "echo one\n");
+
+        assert!(!update(&plugin, &url, Some(&first.checksum), None).unwrap().changed);
+
+        fs::write(&source, "echo two\n").unwrap();
+        let second = update(&plugin, &url, Some(&first.checksum), None).unwrap();
+        assert!(second.changed);
+        assert_eq!(fs::read_to_string(snippet_file(&plugin)).unwrap(), "echo two\n");
+        assert!(!snippet_file(&plugin).with_extension("partial").exists());
+    }
+
+    #[test]
+    fn unchanged_etags_skip_the_download() {
+        let tmp = TempDir::new("snippet");
+        let plugin = tmp.path().join("snippet");
+        fs::create_dir_all(&plugin).unwrap();
+        let (url, server) = serve("echo served\n", "\"v1\"");
+
+        let first = update(&plugin, &url, None, None).unwrap();
+        assert!(first.changed);
+        assert_eq!(first.etag.as_deref(), Some("\"v1\""));
+
+        let second = update(&plugin, &url, Some(&first.checksum), first.etag.as_deref()).unwrap();
+        assert!(!second.changed);
+        assert_eq!(second.etag, first.etag);
+        assert_eq!(server.join().unwrap(), [200, 304]);
+    }
+}
@@ -0,0 +1,326 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Subset of `~/.ssh/config` relevant to git over SSH: `Host` blocks with
+/// `HostName`, `User`, `Port`, `IdentityFile` and `IdentitiesOnly`.
+/// `Match` blocks are skipped and `Include` is not followed.
+#[derive(Debug, Clone, Default)]
+pub struct SshConfig {
+    blocks: Vec<HostBlock>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct HostBlock {
+    /// Host patterns, `!` marks a negated pattern
+    patterns: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+/// Settings that apply to one host, after matching every `Host` block like ssh does:
+/// the first value found wins, except for `IdentityFile` which accumulates
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SshHostConfig {
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_files: Vec<PathBuf>,
+    pub identities_only: bool,
+}
+
+/// Parts of an SSH remote URL (`ssh://user@host:port/path` or scp-like `user@host:path`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshUrl {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    pub path: String,
+}
+
+impl SshUrl {
+    /// Parse `url`, returning `None` for non-SSH URLs
+    pub fn parse(url: &str) -> Option<Self> {
+        let (rest, scp_like) = match url.split_once("://") {
+            Some(("ssh" | "git+ssh" | "ssh+git", rest)) => (rest, false),
+            Some(_) => return None,
+            None => (url, true),
+        };
+
+        let (authority, path) = if scp_like {
+            // "host:path", where the colon must come before any slash
+            let colon = rest.find(':')?;
+            if rest[..colon].contains('/') {
+                return None;
+            }
+            (&rest[..colon], rest[colon + 1..].to_string())
+        } else {
+            let slash = rest.find('/').unwrap_or(rest.len());
+            (&rest[..slash], rest[slash..].to_string())
+        };
+
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (None, authority),
+        };
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) if !scp_like => (host, Some(port.parse().ok()?)),
+            _ => (host_port, None),
+        };
+        if host.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            user,
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+
+    /// Format as an `ssh://` URL
+    pub fn to_url(&self) -> String {
+        let mut url = String::from("ssh://");
+        if let Some(ref user) = self.user {
+            url.push_str(user);
+            url.push('@');
+        }
+        url.push_str(&self.host);
+        if let Some(port) = self.port {
+            url.push_str(&format!(":{}", port));
+        }
+        if !self.path.starts_with('/') {
+            url.push('/');
+        }
+        url.push_str(&self.path);
+        url
+    }
+}
+
+impl SshConfig {
+    /// Parse `~/.ssh/config` under `home_dir`; a missing or unreadable file yields an empty config
+    pub fn load(home_dir: &Path) -> Self {
+        fs::read_to_string(home_dir.join(".ssh/config"))
+            .map(|contents| Self::parse(&contents))
+            .unwrap_or_default()
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut blocks = Vec::new();
+        // Options before the first Host line apply to every host
+        let mut current = Some(HostBlock {
+            patterns: vec!["*".to_string()],
+            options: Vec::new(),
+        });
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // "Key value", "Key=value" or "Key = value"
+            let (key, value) = match line.find(|c: char| c.is_whitespace() || c == '=') {
+                Some(pos) => (
+                    &line[..pos],
+                    line[pos..].trim_start_matches(|c: char| c.is_whitespace() || c == '=').trim(),
+                ),
+                None => (line, ""),
+            };
+            let key = key.to_ascii_lowercase();
+            let value = value.trim_matches('"').to_string();
+
+            match key.as_str() {
+                "host" => {
+                    blocks.extend(current.take());
+                    current = Some(HostBlock {
+                        patterns: value.split_whitespace().map(str::to_string).collect(),
+                        options: Vec::new(),
+                    });
+                }
+                "match" => blocks.extend(current.take()),
+                _ => {
+                    if let Some(ref mut block) = current {
+                        block.options.push((key, value));
+                    }
+                }
+            }
+        }
+        blocks.extend(current);
+
+        Self { blocks }
+    }
+
+    /// Settings for `host`, the host name as written in the remote URL
+    pub fn lookup(&self, host: &str, home_dir: &Path) -> SshHostConfig {
+        let mut config = SshHostConfig::default();
+        let mut identities_only = None;
+
+        for block in self.blocks.iter().filter(|b| b.matches(host)) {
+            for (key, value) in &block.options {
+                match key.as_str() {
+                    "hostname" if config.hostname.is_none() => config.hostname = Some(value.clone()),
+                    "user" if config.user.is_none() => config.user = Some(value.clone()),
+                    "port" if config.port.is_none() => config.port = value.parse().ok(),
+                    "identitiesonly" if identities_only.is_none() => {
+                        identities_only = Some(value.eq_ignore_ascii_case("yes"))
+                    }
+                    "identityfile" if !value.eq_ignore_ascii_case("none") => {
+                        config.identity_files.push(PathBuf::from(value));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        config.identities_only = identities_only.unwrap_or(false);
+
+        // Tokens are expanded once every value is known, as %h and %r refer to the result
+        let hostname = config.hostname.clone().unwrap_or_else(|| host.to_string());
+        let user = config.user.clone().unwrap_or_default();
+        config.identity_files = config
+            .identity_files
+            .iter()
+            .map(|path| expand_tokens(&path.to_string_lossy(), home_dir, &hostname, &user))
+            .collect();
+
+        config
+    }
+}
+
+impl HostBlock {
+    fn matches(&self, host: &str) -> bool {
+        let mut matched = false;
+        for pattern in &self.patterns {
+            match pattern.strip_prefix('!') {
+                Some(negated) if wildcard_match(negated, host) => return false,
+                Some(_) => {}
+                None => matched |= wildcard_match(pattern, host),
+            }
+        }
+        matched
+    }
+}
+
+/// Match `text` against an ssh pattern, where `*` matches any run of characters and `?` one character
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_ascii_lowercase().chars().collect();
+    let text: Vec<char> = text.to_ascii_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Expand `~` and the `%d`, `%h`, `%r` and `%%` tokens of an `IdentityFile` value
+fn expand_tokens(value: &str, home_dir: &Path, hostname: &str, user: &str) -> PathBuf {
+    let home = home_dir.to_string_lossy();
+    let mut expanded = String::new();
+    let mut chars = value.chars();
+
+    if value == "~" || value.starts_with("~/") {
+        expanded.push_str(&home);
+        chars.next();
+    }
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('d') => expanded.push_str(&home),
+            Some('h') => expanded.push_str(hostname),
+            Some('r') => expanded.push_str(user),
+            Some('%') => expanded.push('%'),
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+
+    PathBuf::from(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+IdentityFile ~/.ssh/global
+Host gh github-work
+    HostName github.com
+    User git
+    Port 2222
+    IdentityFile ~/.ssh/%h_%r
+    IdentitiesOnly yes
+Host *.internal !db.internal
+    User=admin
+Host *
+    User nobody
+    IdentityFile none
+";
+
+    #[test]
+    fn wildcard_match_handles_stars_and_question_marks() {
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*.example.com", "git.EXAMPLE.com"));
+        assert!(wildcard_match("git?ub.com", "github.com"));
+        assert!(wildcard_match("g*b*.com", "gitlab.bitbucket.com"));
+        assert!(!wildcard_match("*.example.com", "example.com"));
+        assert!(!wildcard_match("git?ub.com", "gitub.com"));
+    }
+
+    #[test]
+    fn lookup_merges_matching_blocks_first_value_first() {
+        let home = Path::new("/home/me");
+        let config = SshConfig::parse(CONFIG).lookup("github-work", home);
+        assert_eq!(config.hostname.as_deref(), Some("github.com"));
+        assert_eq!(config.user.as_deref(), Some("git"));
+        assert_eq!(config.port, Some(2222));
+        assert!(config.identities_only);
+        assert_eq!(
+            config.identity_files,
+            [PathBuf::from("/home/me/.ssh/global"), PathBuf::from("/home/me/.ssh/github.com_git")]
+        );
+    }
+
+    #[test]
+    fn lookup_honours_negated_patterns() {
+        let config = SshConfig::parse(CONFIG);
+        let home = Path::new("/home/me");
+        assert_eq!(config.lookup("web.internal", home).user.as_deref(), Some("admin"));
+        assert_eq!(config.lookup("db.internal", home).user.as_deref(), Some("nobody"));
+        assert_eq!(config.lookup("example.com", home).hostname, None);
+    }
+
+    #[test]
+    fn ssh_url_parses_both_forms() {
+        let url = SshUrl::parse("git@gh:user/repo.git").unwrap();
+        assert_eq!((url.user.as_deref(), url.host.as_str(), url.port), (Some("git"), "gh", None));
+        assert_eq!(url.path, "user/repo.git");
+
+        let url = SshUrl::parse("ssh://git@host:2222/user/repo").unwrap();
+        assert_eq!((url.host.as_str(), url.port, url.path.as_str()), ("host", Some(2222), "/user/repo"));
+        assert_eq!(url.to_url(), "ssh://git@host:2222/user/repo");
+
+        assert_eq!(SshUrl::parse("https://github.com/user/repo"), None);
+        assert_eq!(SshUrl::parse("./dir/with:colon"), None);
+    }
+}
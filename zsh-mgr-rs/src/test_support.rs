@@ -0,0 +1,284 @@
+//! Helpers shared by the unit tests: throwaway directories and git repositories
+
+use git2::{Commit, Oid, Repository, RepositoryInitOptions, Signature};
+use crate::config::Config;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Directory under the system temp dir that is removed when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(prefix: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let path = std::env::temp_dir().join(format!(
+            "zsh-mgr-test-{}-{}-{}",
+            prefix,
+            std::process::id(),
+            id
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// In-memory writer whose clones share one buffer, for output handed over as `Box<dyn Write>`
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+
+    /// Every line parsed as a JSON value
+    pub fn json_lines(&self) -> Vec<serde_json::Value> {
+        self.contents()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Configuration with plugins and config files under `dir`
+pub fn test_config(dir: &Path) -> Config {
+    Config {
+        plugin_dir: dir.join("plugins"),
+        config_dir: dir.join("config"),
+        time_threshold: 604800,
+        mgr_time_threshold: 604800,
+        object_cache: None,
+        credentials: Default::default(),
+        private_protocol: Default::default(),
+        concurrency: Default::default(),
+        fetch: Default::default(),
+        release_api: crate::release::DEFAULT_RELEASE_API.to_string(),
+        build_timeout: crate::build::DEFAULT_BUILD_TIMEOUT,
+    }
+}
+
+fn set_identity(repo: &Repository) {
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "Test").unwrap();
+    config.set_str("user.email", "test@example.com").unwrap();
+}
+
+/// Initialise a repository on `main` with a single commit
+pub fn init_repo(path: &Path) -> Repository {
+    let mut opts = RepositoryInitOptions::new();
+    opts.initial_head("main");
+    let repo = Repository::init_opts(path, &opts).unwrap();
+    set_identity(&repo);
+    commit_file(&repo, "README.md", "init\n", "Initial commit");
+    repo
+}
+
+/// Clone `upstream` into `dest` over libgit2's local transport
+pub fn clone_repo(upstream: &Path, dest: &Path) -> Repository {
+    let repo = Repository::clone(upstream.to_str().unwrap(), dest).unwrap();
+    set_identity(&repo);
+    repo
+}
+
+/// Write `name` into the working tree and commit it on HEAD
+pub fn commit_file(repo: &Repository, name: &str, content: &str, message: &str) -> Oid {
+    let workdir = repo.workdir().unwrap();
+    std::fs::write(workdir.join(name), content).unwrap();
+
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new(name)).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+    let signature = Signature::now("Test", "test@example.com").unwrap();
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let parents: Vec<&Commit> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+        .unwrap()
+}
+
+/// `git daemon` serving the repositories under a directory over `git://`, for what libgit2's
+/// local transport cannot do (shallow fetches). Stopped when dropped.
+pub struct GitDaemon {
+    child: Child,
+    port: u16,
+}
+
+impl GitDaemon {
+    pub fn start(base: &Path) -> Self {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        // `git daemon` would leave git-daemon running as a grandchild, so start it directly
+        let exec_path = Command::new("git").arg("--exec-path").output().unwrap().stdout;
+        let exec_path = PathBuf::from(String::from_utf8(exec_path).unwrap().trim());
+        let child = Command::new(exec_path.join("git-daemon"))
+            .args(["--export-all", "--reuseaddr", "--listen=127.0.0.1"])
+            .arg(format!("--port={}", port))
+            .arg(format!("--base-path={}", base.display()))
+            .arg(base)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        Self { child, port }
+    }
+
+    /// URL of the repository `name` under the served directory
+    pub fn url(&self, name: &str) -> String {
+        format!("git://127.0.0.1:{}/{}", self.port, name)
+    }
+}
+
+impl Drop for GitDaemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Shallow clone of `url` with the git CLI, `depth` commits deep
+pub fn shallow_clone(url: &str, dest: &Path, depth: u32) -> Repository {
+    let status = Command::new("git")
+        .args(["clone", "-q", "--depth", &depth.to_string(), url])
+        .arg(dest)
+        .status()
+        .unwrap();
+    assert!(status.success(), "git clone {} failed", url);
+    let repo = Repository::open(dest).unwrap();
+    set_identity(&repo);
+    repo
+}
+
+/// HTTP server answering GET requests for fixed paths and 404 for anything else.
+/// Stopped when dropped.
+pub struct HttpServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HttpServer {
+    /// Serve the routes `routes` builds from the server's base URL
+    pub fn start(routes: impl FnOnce(&str) -> Vec<(String, Vec<u8>)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let routes = routes(&format!("http://{}", addr));
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+
+        let thread = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                let _ = reader.read_line(&mut request_line);
+                let mut header = String::new();
+                while reader.read_line(&mut header).is_ok_and(|n| n > 0) && !header.trim().is_empty() {
+                    header.clear();
+                }
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+                let (status, body) = match routes.iter().find(|(route, _)| route == path) {
+                    Some((_, body)) => ("200 OK", body.as_slice()),
+                    None => ("404 Not Found", b"".as_slice()),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = stream.write_all(body);
+            }
+        });
+        Self { addr, stop, thread: Some(thread) }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake up the accept loop so it sees the flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// `.tar.gz` holding an executable `tool-<version>/bin/tool` that prints `version`
+pub fn tool_archive(version: &str) -> Vec<u8> {
+    let script = format!("#!/bin/sh\necho {}\n", version);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(script.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+
+    let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast()));
+    archive
+        .append_data(&mut header, format!("tool-{}/bin/tool", version), script.as_bytes())
+        .unwrap();
+    archive.into_inner().unwrap().finish().unwrap()
+}
+
+/// Routes of a release API where `u/tool` has release `tag` with a `tool-any.tar.gz` asset
+/// and a `checksums.txt` listing it
+pub fn tool_release(base: &str, tag: &str) -> Vec<(String, Vec<u8>)> {
+    let archive = tool_archive(tag);
+    let checksums = format!("{}  tool-any.tar.gz\n", crate::snippet::checksum(&archive));
+    let release = serde_json::json!({
+        "tag_name": tag,
+        "assets": [
+            { "name": "tool-any.tar.gz", "browser_download_url": format!("{}/dl/{}/tool-any.tar.gz", base, tag) },
+            { "name": "checksums.txt", "browser_download_url": format!("{}/dl/{}/checksums.txt", base, tag) },
+        ],
+    });
+    vec![
+        ("/repos/u/tool/releases/latest".to_string(), release.to_string().into_bytes()),
+        (format!("/dl/{}/tool-any.tar.gz", tag), archive),
+        (format!("/dl/{}/checksums.txt", tag), checksums.into_bytes()),
+    ]
+}
@@ -0,0 +1,249 @@
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Login sent with a token when neither the URL nor `~/.netrc` names one.
+/// GitHub accepts any login for tokens, GitLab and Gitea accept this one too.
+pub const DEFAULT_TOKEN_LOGIN: &str = "x-access-token";
+
+/// An access token for HTTPS remotes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpToken {
+    pub login: Option<String>,
+    pub token: String,
+}
+
+/// HTTPS tokens per host, looked up in order from `ZSH_MGR_TOKEN_<HOST>`, `~/.netrc`
+/// and the configured token command, at most once per host and process
+#[derive(Debug, Default)]
+pub struct TokenStore {
+    home_dir: PathBuf,
+    token_command: Option<String>,
+    tokens: Mutex<HashMap<String, Option<HttpToken>>>,
+}
+
+impl TokenStore {
+    pub fn new(home_dir: PathBuf, token_command: Option<String>) -> Self {
+        Self {
+            home_dir,
+            token_command,
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Token for the host of an `http(s)://` URL, `None` for other URLs or if there is none
+    pub fn token_for_url(&self, url: &str) -> Option<HttpToken> {
+        self.token(&http_host(url)?)
+    }
+
+    pub fn token(&self, host: &str) -> Option<HttpToken> {
+        // The lock is held while the command runs, so it is not started twice for one host
+        let mut tokens = self.tokens.lock().ok()?;
+        if let Some(cached) = tokens.get(host) {
+            return cached.clone();
+        }
+
+        let token = env_token(host)
+            .or_else(|| netrc_token(&self.home_dir.join(".netrc"), host))
+            .or_else(|| {
+                self.token_command
+                    .as_deref()
+                    .and_then(|command| command_token(command, host))
+            });
+        tokens.insert(host.to_string(), token.clone());
+        token
+    }
+}
+
+/// Environment variable holding the token for `host`, e.g. `ZSH_MGR_TOKEN_GITLAB_EXAMPLE_COM`
+pub fn env_var_name(host: &str) -> String {
+    let suffix: String = host
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    format!("ZSH_MGR_TOKEN_{}", suffix)
+}
+
+fn env_token(host: &str) -> Option<HttpToken> {
+    let name = env_var_name(host);
+    let token = env::var(&name).ok().filter(|t| !t.trim().is_empty())?;
+    debug!("Using HTTPS token from {}", name);
+    Some(HttpToken {
+        login: None,
+        token: token.trim().to_string(),
+    })
+}
+
+fn netrc_token(path: &Path, host: &str) -> Option<HttpToken> {
+    let contents = fs::read_to_string(path).ok()?;
+    let token = parse_netrc(&contents, host)?;
+    debug!("Using HTTPS token for {} from {}", host, path.display());
+    Some(token)
+}
+
+/// Find the `machine` entry for `host` in netrc contents, falling back to a `default` entry
+pub fn parse_netrc(contents: &str, host: &str) -> Option<HttpToken> {
+    let mut words = contents
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(str::split_whitespace);
+
+    // (machine, login, password), where a `default` entry has no machine
+    let mut entries: Vec<(Option<&str>, Option<&str>, Option<&str>)> = Vec::new();
+    while let Some(word) = words.next() {
+        match word {
+            "machine" => entries.push((Some(words.next()?), None, None)),
+            "default" => entries.push((None, None, None)),
+            "login" | "password" | "account" => {
+                let value = words.next()?;
+                if let Some(entry) = entries.last_mut() {
+                    match word {
+                        "login" => entry.1 = Some(value),
+                        "password" => entry.2 = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+            // Macro definitions run until an empty line, which the word split cannot see
+            "macdef" => break,
+            _ => {}
+        }
+    }
+
+    entries
+        .iter()
+        .find(|(machine, _, _)| machine.is_some_and(|m| m.eq_ignore_ascii_case(host)))
+        .or_else(|| entries.iter().find(|(machine, _, _)| machine.is_none()))
+        .and_then(|(_, login, password)| {
+            Some(HttpToken {
+                login: login.map(str::to_string),
+                token: password.filter(|p| !p.is_empty())?.to_string(),
+            })
+        })
+}
+
+/// Run the token command through `sh`, with the host as `$1`, and use its trimmed output
+fn command_token(command: &str, host: &str) -> Option<HttpToken> {
+    let output = Command::new("sh")
+        .args(["-c", command, "zsh-mgr", host])
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if token.is_empty() {
+                debug!("Token command printed nothing for {}", host);
+                return None;
+            }
+            debug!("Using HTTPS token for {} from the token command", host);
+            Some(HttpToken { login: None, token })
+        }
+        Ok(output) => {
+            warn!("Token command exited with {} for {}", output.status, host);
+            None
+        }
+        Err(e) => {
+            warn!("Failed to run token command: {}", e);
+            None
+        }
+    }
+}
+
+/// Host name of an `http://` or `https://` URL, without user info or port
+pub fn http_host(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = match host_port.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => host_port,
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn token(login: Option<&str>, token: &str) -> Option<HttpToken> {
+        Some(HttpToken {
+            login: login.map(str::to_string),
+            token: token.to_string(),
+        })
+    }
+
+    #[test]
+    fn parse_netrc_matches_machines_and_falls_back_to_default() {
+        let netrc = "\
+# personal tokens
+machine GitLab.example.com login me password glpat-1
+machine github.com
+    login octo
+    password ghp-2
+default login anon password fallback
+";
+        assert_eq!(parse_netrc(netrc, "gitlab.example.com"), token(Some("me"), "glpat-1"));
+        assert_eq!(parse_netrc(netrc, "github.com"), token(Some("octo"), "ghp-2"));
+        assert_eq!(parse_netrc(netrc, "codeberg.org"), token(Some("anon"), "fallback"));
+        assert_eq!(parse_netrc("machine github.com login octo", "github.com"), None);
+        assert_eq!(parse_netrc("machine github.com", "github.com"), None);
+    }
+
+    #[test]
+    fn parse_netrc_stops_at_macdef() {
+        let netrc = "\
+machine a.com password one
+macdef init
+machine b.com password two
+
+machine c.com password three
+";
+        assert_eq!(parse_netrc(netrc, "a.com"), token(None, "one"));
+        assert_eq!(parse_netrc(netrc, "b.com"), None);
+        assert_eq!(parse_netrc(netrc, "c.com"), None);
+    }
+
+    #[test]
+    fn http_host_strips_userinfo_and_ports() {
+        assert_eq!(http_host("https://GitHub.com/u/repo.git").as_deref(), Some("github.com"));
+        assert_eq!(http_host("https://me:pw@gitlab.example.com:8443/u/repo").as_deref(), Some("gitlab.example.com"));
+        assert_eq!(http_host("http://host?query").as_deref(), Some("host"));
+        assert_eq!(http_host("git@github.com:u/repo.git"), None);
+        assert_eq!(http_host("ssh://github.com/u/repo"), None);
+        assert_eq!(http_host("https:///path"), None);
+    }
+
+    #[test]
+    fn env_var_name_uppercases_and_replaces_punctuation() {
+        assert_eq!(env_var_name("gitlab.example.com"), "ZSH_MGR_TOKEN_GITLAB_EXAMPLE_COM");
+        assert_eq!(env_var_name("my-host"), "ZSH_MGR_TOKEN_MY_HOST");
+    }
+
+    #[test]
+    fn token_store_prefers_env_then_netrc_then_command_once() {
+        let tmp = TempDir::new("tokens");
+        fs::write(tmp.path().join(".netrc"), "machine netrc.test password from-netrc\n").unwrap();
+        let calls = tmp.path().join("calls");
+        let command = format!("echo \"$1\" >> {}; echo from-command", calls.display());
+        env::set_var(env_var_name("env.test"), " from-env\n");
+
+        let store = TokenStore::new(tmp.path().to_path_buf(), Some(command));
+        assert_eq!(store.token_for_url("https://env.test/u/repo"), token(None, "from-env"));
+        assert_eq!(store.token("netrc.test"), token(None, "from-netrc"));
+        assert_eq!(store.token("cmd.test"), token(None, "from-command"));
+        assert_eq!(store.token("cmd.test"), token(None, "from-command"));
+        assert_eq!(store.token_for_url("git@cmd.test:u/repo"), None);
+
+        env::remove_var(env_var_name("env.test"));
+        assert_eq!(fs::read_to_string(&calls).unwrap(), "cmd.test\n");
+    }
+}